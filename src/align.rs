@@ -0,0 +1,220 @@
+use crate::decoder;
+use crate::error::{Error, Result};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Highest sample rate the files are reduced to before correlating them.
+const ALIGN_RATE: u32 = 8000;
+/// Seconds of the first file correlated against the second.
+const ALIGN_WINDOW: f64 = 15.0;
+/// Largest offset searched for, in seconds either way.
+pub const MAX_OFFSET: f64 = 2.0;
+/// Normalised correlation below which the files are taken not to match.
+const MIN_CORRELATION: f32 = 0.3;
+
+/// How a second decode of the same material lines up with the first.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Alignment {
+    /// Seconds the second file lags behind the first, negative when it leads.
+    pub offset: f64,
+    /// Normalised correlation at that offset, 1.0 for identical waveforms.
+    pub correlation: f32,
+}
+
+impl Alignment {
+    /// Whether the files match well enough for the offset to mean anything.
+    pub fn is_reliable(&self) -> bool {
+        self.correlation >= MIN_CORRELATION
+    }
+
+    /// `offset` when reliable, otherwise no shift at all.
+    pub fn shift(&self) -> f64 {
+        if self.is_reliable() {
+            self.offset
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Finds the offset of `second_path` against `first_path` by cross-correlating
+/// their mono downmixes, starting `start` seconds into the first file.
+pub fn find_offset(
+    first_path: &str,
+    second_path: &str,
+    start: f64,
+    cancel_token: Arc<AtomicBool>,
+) -> Result<Alignment> {
+    let mut first = decoder::open(first_path)?;
+    let mut second = decoder::open(second_path)?;
+    let rate = ALIGN_RATE
+        .min(first.info().sample_rate)
+        .min(second.info().sample_rate)
+        .max(1);
+
+    // The second file is read from earlier on, so it can be found to lead too.
+    let second_start = (start - MAX_OFFSET).max(0.0);
+    let lead = start - second_start;
+    let first = decode_mono(first.as_mut(), start, ALIGN_WINDOW, rate, &cancel_token)?;
+    let second = decode_mono(
+        second.as_mut(),
+        second_start,
+        lead + ALIGN_WINDOW + MAX_OFFSET,
+        rate,
+        &cancel_token,
+    )?;
+    if first.is_empty() || second.is_empty() {
+        return Ok(Alignment {
+            offset: 0.0,
+            correlation: 0.0,
+        });
+    }
+
+    // correlation[lag] = sum of first[n] * second[n + lag], over FFTs long
+    // enough that the circular correlation does not wrap.
+    let size = (first.len() + second.len()).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+    let spectrum = |samples: &[f32]| {
+        let mut buffer = vec![Complex::new(0.0, 0.0); size];
+        for (value, &sample) in buffer.iter_mut().zip(samples) {
+            value.re = sample;
+        }
+        forward.process(&mut buffer);
+        buffer
+    };
+    let first_spectrum = spectrum(&first);
+    let mut correlation = spectrum(&second);
+    for (value, first_value) in correlation.iter_mut().zip(&first_spectrum) {
+        *value *= first_value.conj();
+    }
+    inverse.process(&mut correlation);
+
+    // Normalised by the energy of both windows, so quieter stretches of
+    // the second file do not lose against louder ones.
+    let first_energy: f64 = first.iter().map(|&s| (s as f64).powi(2)).sum();
+    let mut second_energy = vec![0.0f64; second.len() + 1];
+    for (i, &s) in second.iter().enumerate() {
+        second_energy[i + 1] = second_energy[i] + (s as f64).powi(2);
+    }
+    let lags = ((lead - MAX_OFFSET) * rate as f64).max(0.0) as usize
+        ..=(((lead + MAX_OFFSET) * rate as f64) as usize).min(second.len() - 1);
+
+    let mut best = Alignment {
+        offset: 0.0,
+        correlation: 0.0,
+    };
+    for lag in lags {
+        let end = (lag + first.len()).min(second.len());
+        let energy = first_energy * (second_energy[end] - second_energy[lag]);
+        if energy <= f64::EPSILON {
+            continue;
+        }
+        let value = (correlation[lag].re as f64 / size as f64 / energy.sqrt()) as f32;
+        if value > best.correlation {
+            best = Alignment {
+                offset: lag as f64 / rate as f64 - lead,
+                correlation: value,
+            };
+        }
+    }
+    Ok(best)
+}
+
+/// Reads `seconds` of `decoder` from `start` as a mono signal averaged down
+/// to `rate`, shorter when the file ends first.
+fn decode_mono(
+    decoder: &mut dyn decoder::AudioDecoder,
+    start: f64,
+    seconds: f64,
+    rate: u32,
+    cancel_token: &AtomicBool,
+) -> Result<Vec<f32>> {
+    let info = decoder.info().clone();
+    let channels = info.channels.max(1) as usize;
+    if start > 0.0 {
+        decoder.seek(start)?;
+    }
+
+    let length = (seconds * rate as f64) as usize;
+    let mut sums = vec![0.0f32; length];
+    let mut counts = vec![0u32; length];
+    let mut frame_index = 0u64;
+    let mut samples = Vec::new();
+    'decode: while decoder.next_block(&mut samples)? {
+        if cancel_token.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        for frame in samples.chunks_exact(channels) {
+            let index = (frame_index * rate as u64 / info.sample_rate.max(1) as u64) as usize;
+            if index >= length {
+                break 'decode;
+            }
+            sums[index] += frame.iter().sum::<f32>() / channels as f32;
+            counts[index] += 1;
+            frame_index += 1;
+        }
+    }
+
+    let filled = counts.iter().take_while(|&&count| count > 0).count();
+    Ok(sums[..filled]
+        .iter()
+        .zip(&counts)
+        .map(|(&sum, &count)| sum / count as f32)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{white_noise, write_wav};
+
+    /// Offset found for `second` against `first`, both at `ALIGN_RATE`.
+    fn align(name: &str, first: &[f32], second: &[f32]) -> Alignment {
+        let first_file = write_wav(&format!("{name}-first"), ALIGN_RATE, 1, first);
+        let second_file = write_wav(&format!("{name}-second"), ALIGN_RATE, 1, second);
+        find_offset(
+            first_file.path_str(),
+            second_file.path_str(),
+            1.0,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap()
+    }
+
+    const SECONDS: usize = 6;
+
+    #[test]
+    fn late_second_file_has_a_positive_offset() {
+        let first = white_noise(1, SECONDS * ALIGN_RATE as usize, 1.0);
+        // 0.3 s of silence in front.
+        let mut second = vec![0.0; 2400];
+        second.extend_from_slice(&first);
+        let alignment = align("late", &first, &second);
+        assert!((alignment.offset - 0.3).abs() < 1e-3, "{:?}", alignment);
+        assert!(alignment.correlation > 0.99);
+        assert_eq!(alignment.shift(), alignment.offset);
+    }
+
+    #[test]
+    fn early_second_file_has_a_negative_offset() {
+        let first = white_noise(2, SECONDS * ALIGN_RATE as usize, 1.0);
+        // The first 0.25 s cut off.
+        let alignment = align("early", &first, &first[2000..]);
+        assert!((alignment.offset + 0.25).abs() < 1e-3, "{:?}", alignment);
+        assert!(alignment.is_reliable());
+    }
+
+    #[test]
+    fn unrelated_files_are_not_shifted() {
+        let first = white_noise(3, SECONDS * ALIGN_RATE as usize, 1.0);
+        let second = white_noise(4, SECONDS * ALIGN_RATE as usize, 1.0);
+        let alignment = align("unrelated", &first, &second);
+        assert!(!alignment.is_reliable(), "{:?}", alignment);
+        assert_eq!(alignment.shift(), 0.0);
+    }
+}
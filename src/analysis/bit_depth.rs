@@ -0,0 +1,245 @@
+/// Samples per channel the noise floor is measured over.
+const BLOCK_FRAMES: usize = 4096;
+/// Blocks whose first difference is this much louder (relative to the signal)
+/// count as noise; music has most of its energy low and stays well below √2.
+const MIN_NOISE_DIFF_RATIO: f64 = 1.2;
+/// Blocks quieter than this are digital silence or fades, not a noise floor.
+const MIN_NOISE_RMS: f64 = 1e-9;
+/// Noise floors above this are the quietest part of the music, not dither.
+const MAX_NOISE_FLOOR_DB: f32 = -60.0;
+/// Scale between the noise RMS and one LSB, halfway between plain rounding
+/// (LSB/√12) and TPDF dither (LSB/2).
+const NOISE_PER_LSB: f64 = 1.0 / 2.63;
+/// Noise floors only count when they sit clearly below the declared depth,
+/// analog noise of a genuine recording usually covers the last few bits.
+const NOISE_MARGIN_BITS: u32 = 4;
+/// The decoders hand out `f32`, so deeper integer formats cannot be checked bit by bit.
+const MAX_VERIFIABLE_BITS: u32 = 24;
+
+use serde::Serialize;
+
+/// Effective resolution of an integer PCM stream compared to its header.
+#[derive(Debug, Clone, Serialize)]
+pub struct BitDepthAnalysis {
+    /// Bits per sample according to the container / codec.
+    pub declared_bits: u32,
+    /// Best estimate of the real resolution of the audio.
+    pub effective_bits: u32,
+    /// Highest bit position that is ever set; lower, always-zero bits are padding.
+    pub used_bits: Option<u32>,
+    /// RMS level of the quietest noise-like passages in dBFS.
+    pub noise_floor_db: Option<f32>,
+    /// Resolution the noise floor corresponds to (dither or quantisation noise).
+    pub noise_floor_bits: Option<u32>,
+}
+
+impl BitDepthAnalysis {
+    pub fn summary(&self) -> String {
+        if self.effective_bits < self.declared_bits {
+            format!(
+                "declared {} bit, effective {} bit",
+                self.declared_bits, self.effective_bits
+            )
+        } else {
+            format!("{} bit", self.declared_bits)
+        }
+    }
+
+    pub fn to_key_values(&self) -> Vec<(String, String)> {
+        let or_na = |value: Option<String>| value.unwrap_or_else(|| "N/A".to_string());
+        vec![
+            ("bit_depth.declared".into(), self.declared_bits.to_string()),
+            (
+                "bit_depth.effective".into(),
+                self.effective_bits.to_string(),
+            ),
+            (
+                "bit_depth.used_bits".into(),
+                or_na(self.used_bits.map(|b| b.to_string())),
+            ),
+            (
+                "bit_depth.noise_floor_db".into(),
+                or_na(self.noise_floor_db.map(|db| format!("{:.1}", db))),
+            ),
+        ]
+    }
+}
+
+pub(super) struct BitDepthAnalyzer {
+    declared_bits: u32,
+    channels: usize,
+
+    /// OR of all samples scaled to 32-bit integers.
+    bit_mask: u32,
+
+    previous: Vec<f64>,
+    block_frames: usize,
+    block_energy: f64,
+    block_diff_energy: f64,
+    noise_rms: Vec<f32>,
+}
+
+impl BitDepthAnalyzer {
+    pub(super) fn new(declared_bits: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            declared_bits,
+            channels,
+            bit_mask: 0,
+            previous: vec![0.0; channels],
+            block_frames: 0,
+            block_energy: 0.0,
+            block_diff_energy: 0.0,
+            noise_rms: Vec::new(),
+        }
+    }
+
+    pub(super) fn push_interleaved(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                // Integer PCM of up to 24 bits is exact in f32, so this recovers the raw value.
+                let value = (sample as f64 * 2f64.powi(31)).round() as i64;
+                self.bit_mask |= value.clamp(i32::MIN as i64, i32::MAX as i64) as i32 as u32;
+
+                let sample = sample as f64;
+                let diff = sample - self.previous[channel];
+                self.previous[channel] = sample;
+                self.block_energy += sample * sample;
+                self.block_diff_energy += diff * diff;
+            }
+
+            self.block_frames += 1;
+            if self.block_frames == BLOCK_FRAMES {
+                self.finish_block();
+            }
+        }
+    }
+
+    fn finish_block(&mut self) {
+        let count = (self.block_frames * self.channels) as f64;
+        let rms = (self.block_energy / count).sqrt();
+        let diff_rms = (self.block_diff_energy / count).sqrt();
+
+        // White noise has a first difference √2 times its own level.
+        if rms > MIN_NOISE_RMS && diff_rms / rms > MIN_NOISE_DIFF_RATIO {
+            self.noise_rms.push((diff_rms / 2f64.sqrt()) as f32);
+        }
+
+        self.block_frames = 0;
+        self.block_energy = 0.0;
+        self.block_diff_energy = 0.0;
+    }
+
+    pub(super) fn finish(mut self) -> BitDepthAnalysis {
+        let used_bits = if self.declared_bits > MAX_VERIFIABLE_BITS || self.bit_mask == 0 {
+            None
+        } else {
+            Some(32 - self.bit_mask.trailing_zeros())
+        };
+
+        // The quietest noise-like blocks, louder ones are hi-hats and other noisy music.
+        self.noise_rms.sort_by(|a, b| a.total_cmp(b));
+        let noise_floor_db = self
+            .noise_rms
+            .get(self.noise_rms.len() / 10)
+            .map(|rms| 20.0 * rms.log10())
+            .filter(|&db| db <= MAX_NOISE_FLOOR_DB);
+        let noise_floor_bits = noise_floor_db.map(|db| {
+            // Full scale is ±1.0, so one LSB at n bits is 2^(1-n).
+            let lsb = 10f64.powf(db as f64 / 20.0) / NOISE_PER_LSB;
+            (1.0 - lsb.log2()).round().max(1.0) as u32
+        });
+
+        let mut effective_bits = self.declared_bits;
+        if let Some(used) = used_bits {
+            effective_bits = effective_bits.min(used);
+        }
+        if let Some(noise) = noise_floor_bits {
+            if noise + NOISE_MARGIN_BITS <= effective_bits {
+                effective_bits = noise;
+            }
+        }
+
+        BitDepthAnalysis {
+            declared_bits: self.declared_bits,
+            effective_bits,
+            used_bits,
+            noise_floor_db,
+            noise_floor_bits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Noise;
+
+    /// Triangular noise with an RMS of half an LSB at `bits`.
+    fn dither(noise: &mut Noise, bits: u32) -> f64 {
+        let lsb = 2f64.powi(1 - bits as i32);
+        (noise.next_f64() + noise.next_f64() - 1.0) * 1.5f64.sqrt() * lsb
+    }
+
+    fn quantize(sample: f64, bits: u32) -> f32 {
+        let scale = 2f64.powi(bits as i32 - 1);
+        ((sample * scale).round() / scale) as f32
+    }
+
+    fn analyse(declared_bits: u32, samples: &[f32]) -> BitDepthAnalysis {
+        let mut analyzer = BitDepthAnalyzer::new(declared_bits, 1);
+        analyzer.push_interleaved(samples);
+        analyzer.finish()
+    }
+
+    fn sine(n: usize) -> f64 {
+        0.5 * (n as f64 * 0.0731).sin()
+    }
+
+    #[test]
+    fn zero_padded_lsbs_are_detected() {
+        let samples: Vec<f32> = (0..100_000).map(|n| quantize(sine(n), 16)).collect();
+        let analysis = analyse(24, &samples);
+        assert_eq!(analysis.used_bits, Some(16));
+        assert_eq!(analysis.effective_bits, 16);
+        assert_eq!(analysis.summary(), "declared 24 bit, effective 16 bit");
+    }
+
+    #[test]
+    fn full_resolution_keeps_the_declared_depth() {
+        let mut noise = Noise::new(0x9e37_79b9_7f4a_7c15);
+        let samples: Vec<f32> = (0..100_000)
+            .map(|n| quantize(sine(n) + dither(&mut noise, 24), 24))
+            .collect();
+        let analysis = analyse(24, &samples);
+        assert_eq!(analysis.used_bits, Some(24));
+        assert_eq!(analysis.effective_bits, 24);
+        assert_eq!(analysis.summary(), "24 bit");
+    }
+
+    #[test]
+    fn dither_floor_reveals_a_shallower_master() {
+        // A 16-bit master's dither, stored at 24 bits after a gain change.
+        let mut noise = Noise::new(0x2545_f491_4f6c_dd1d);
+        let samples: Vec<f32> = (0..100_000)
+            .map(|_| quantize(dither(&mut noise, 16) * 0.99, 24))
+            .collect();
+        let analysis = analyse(24, &samples);
+        assert_eq!(analysis.used_bits, Some(24));
+        let floor = analysis.noise_floor_db.unwrap();
+        assert!((floor + 96.4).abs() < 1.0, "{floor}");
+        assert_eq!(analysis.noise_floor_bits, Some(16));
+        assert_eq!(analysis.effective_bits, 16);
+    }
+
+    #[test]
+    fn silence_and_float_formats_are_not_checked_bit_by_bit() {
+        let analysis = analyse(16, &[0.0; 10_000]);
+        assert_eq!(analysis.used_bits, None);
+        assert_eq!(analysis.effective_bits, 16);
+
+        let samples: Vec<f32> = (0..10_000).map(|n| quantize(sine(n), 16)).collect();
+        let analysis = analyse(32, &samples);
+        assert_eq!(analysis.used_bits, None);
+    }
+}
@@ -0,0 +1,459 @@
+use super::{band_level_db, content_edge, median, ANALYSIS_FFT_SIZE};
+use serde::Serialize;
+
+/// Length of the blocks the cutoff is tracked over.
+const BLOCK_SECONDS: f32 = 1.0;
+/// Width of the moving average applied to block spectra before the cutoff search.
+const SMOOTHING_HZ: f32 = 100.0;
+/// Blocks quieter than this between 1 and 8 kHz carry no usable information.
+const MIN_MUSIC_LEVEL_DB: f32 = -80.0;
+/// The cutoff is where the spectrum last rises this far above its floor.
+const CUTOFF_ABOVE_FLOOR_DB: f32 = 12.0;
+/// Level difference across a cutoff that counts as an encoder lowpass.
+const SHELF_MIN_DROP_DB: f32 = 25.0;
+/// Level difference at a single frequency that counts as an SBR crossover.
+const SBR_MIN_STEP_DB: f32 = 12.0;
+/// Lowpass frequencies commonly used by MP3, AAC and Vorbis encoders.
+const KNOWN_CUTOFFS_HZ: [f32; 5] = [16_000.0, 17_000.0, 19_000.0, 19_500.0, 20_000.0];
+/// Above this a shelf is more likely resampling than a lossy encoder.
+const MAX_LOSSY_CUTOFF_HZ: f32 = 20_500.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LossyVerdict {
+    Lossless,
+    Lossy,
+    Inconclusive,
+}
+
+impl LossyVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lossless => "lossless",
+            Self::Lossy => "lossy",
+            Self::Inconclusive => "inconclusive",
+        }
+    }
+}
+
+/// Spectral fingerprints left behind by lossy encoders.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LossySignature {
+    /// Steep lowpass at a constant frequency.
+    HardShelf { frequency_hz: f32 },
+    /// Spectral band replication (HE-AAC), a level step where the copied band starts.
+    Sbr { crossover_hz: f32 },
+    /// MP3 scalefactor band 21 (above ~16 kHz) switching on and off between frames.
+    Sfb21Hole,
+}
+
+impl std::fmt::Display for LossySignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::HardShelf { frequency_hz } => {
+                write!(f, "{:.1} kHz shelf", frequency_hz / 1000.0)
+            }
+            Self::Sbr { crossover_hz } => write!(f, "SBR at {:.1} kHz", crossover_hz / 1000.0),
+            Self::Sfb21Hole => write!(f, "sfb21 hole"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LossyAnalysis {
+    pub verdict: LossyVerdict,
+    /// 0.0..1.0, how sure the verdict is.
+    pub confidence: f32,
+    /// Median cutoff frequency over all blocks with music in them.
+    pub cutoff_hz: Option<f32>,
+    /// Cutoff per `block_seconds` block, `None` for silent blocks.
+    pub cutoff_over_time: Vec<Option<f32>>,
+    pub block_seconds: f32,
+    pub signatures: Vec<LossySignature>,
+}
+
+impl LossyAnalysis {
+    pub fn summary(&self) -> String {
+        let percent = (self.confidence * 100.0).round();
+        match self.verdict {
+            LossyVerdict::Lossy => {
+                let signatures: Vec<String> =
+                    self.signatures.iter().map(|s| s.to_string()).collect();
+                format!("Lossy ({}%): {}", percent, signatures.join(", "))
+            }
+            LossyVerdict::Lossless => match self.cutoff_hz {
+                Some(cutoff) => format!(
+                    "Lossless ({}%), content to {:.1} kHz",
+                    percent,
+                    cutoff / 1000.0
+                ),
+                None => format!("Lossless ({}%)", percent),
+            },
+            LossyVerdict::Inconclusive => "Transcode check inconclusive".to_string(),
+        }
+    }
+
+    pub fn to_key_values(&self) -> Vec<(String, String)> {
+        let signatures: Vec<String> = self
+            .signatures
+            .iter()
+            .map(|s| match s {
+                LossySignature::HardShelf { frequency_hz } => {
+                    format!("hard_shelf:{:.0}", frequency_hz)
+                }
+                LossySignature::Sbr { crossover_hz } => format!("sbr:{:.0}", crossover_hz),
+                LossySignature::Sfb21Hole => "sfb21_hole".to_string(),
+            })
+            .collect();
+
+        vec![
+            ("lossy.verdict".into(), self.verdict.as_str().into()),
+            ("lossy.confidence".into(), format!("{:.2}", self.confidence)),
+            (
+                "lossy.cutoff_hz".into(),
+                self.cutoff_hz
+                    .map_or("N/A".to_string(), |hz| format!("{:.0}", hz)),
+            ),
+            (
+                "lossy.signatures".into(),
+                if signatures.is_empty() {
+                    "none".to_string()
+                } else {
+                    signatures.join(",")
+                },
+            ),
+        ]
+    }
+}
+
+/// Cutoff measurement of a single block.
+struct BlockCutoff {
+    frequency_hz: f32,
+    /// Level drop across the cutoff in dB.
+    drop_db: f32,
+    /// Strongest level step between 4 and 14 kHz, (frequency, dB).
+    step: Option<(f32, f32)>,
+}
+
+pub(super) struct LossyAnalyzer {
+    sample_rate: u32,
+    frames_per_block: usize,
+
+    block_power: Vec<f32>,
+    block_frames: usize,
+    blocks: Vec<Option<BlockCutoff>>,
+
+    // sfb21: frames with music below 16 kHz, and how many of them had the band above empty/filled
+    sfb21_frames: usize,
+    sfb21_holes: usize,
+    sfb21_filled: usize,
+}
+
+impl LossyAnalyzer {
+    pub(super) fn new(sample_rate: u32) -> Self {
+        let frames_per_block =
+            ((BLOCK_SECONDS * sample_rate as f32) / ANALYSIS_FFT_SIZE as f32).round() as usize;
+        Self {
+            sample_rate,
+            frames_per_block: frames_per_block.max(1),
+            block_power: vec![0.0; ANALYSIS_FFT_SIZE / 2 + 1],
+            block_frames: 0,
+            blocks: Vec::new(),
+            sfb21_frames: 0,
+            sfb21_holes: 0,
+            sfb21_filled: 0,
+        }
+    }
+
+    pub(super) fn push_frame(&mut self, power: &[f32]) {
+        self.check_sfb21(power);
+
+        for (acc, &p) in self.block_power.iter_mut().zip(power) {
+            *acc += p;
+        }
+        self.block_frames += 1;
+        if self.block_frames == self.frames_per_block {
+            self.finish_block();
+        }
+    }
+
+    fn check_sfb21(&mut self, power: &[f32]) {
+        let nyquist = self.sample_rate as f32 / 2.0;
+        if nyquist < 20_000.0 {
+            return;
+        }
+        let below = band_level_db(power, self.sample_rate, 12_000.0, 15_800.0);
+        let above = band_level_db(
+            power,
+            self.sample_rate,
+            16_200.0,
+            (nyquist * 0.95).min(19_500.0),
+        );
+        if below < MIN_MUSIC_LEVEL_DB - 10.0 {
+            return;
+        }
+        self.sfb21_frames += 1;
+        if above - below < -35.0 {
+            self.sfb21_holes += 1;
+        } else if above - below > -25.0 {
+            self.sfb21_filled += 1;
+        }
+    }
+
+    fn finish_block(&mut self) {
+        let frames = self.block_frames.max(1) as f32;
+        let mean: Vec<f32> = self.block_power.iter().map(|p| p / frames).collect();
+        let block = self.measure_block(&mean);
+        self.blocks.push(block);
+
+        self.block_power.iter_mut().for_each(|p| *p = 0.0);
+        self.block_frames = 0;
+    }
+
+    fn measure_block(&self, power: &[f32]) -> Option<BlockCutoff> {
+        let sr = self.sample_rate;
+        let nyquist = sr as f32 / 2.0;
+        let music_db = band_level_db(power, sr, 1_000.0, 8_000.0);
+        if music_db < MIN_MUSIC_LEVEL_DB {
+            return None;
+        }
+
+        let (frequency_hz, floor) = content_edge(power, sr, SMOOTHING_HZ, CUTOFF_ABOVE_FLOOR_DB);
+        let drop_db = band_level_db(power, sr, frequency_hz - 800.0, frequency_hz - 100.0)
+            - band_level_db(
+                power,
+                sr,
+                frequency_hz + 100.0,
+                (frequency_hz + 800.0).min(nyquist),
+            );
+
+        // Level steps inside the music band, the crossover of spectral band replication.
+        let mut step: Option<(f32, f32)> = None;
+        let mut hz = 4_000.0;
+        while hz <= 14_000.0_f32.min(frequency_hz - 1_000.0) {
+            let below = band_level_db(power, sr, hz - 600.0, hz - 100.0);
+            let above = band_level_db(power, sr, hz + 100.0, hz + 600.0);
+            let continues = band_level_db(power, sr, hz + 600.0, hz + 2_000.0) > floor + 20.0;
+            let size = below - above;
+            if size > SBR_MIN_STEP_DB && continues && step.is_none_or(|(_, s)| size > s) {
+                step = Some((hz, size));
+            }
+            hz += 50.0;
+        }
+
+        Some(BlockCutoff {
+            frequency_hz,
+            drop_db,
+            step,
+        })
+    }
+
+    /// The partial block at the end is dropped, a few frames of a fade-out
+    /// would report a cutoff of their own.
+    pub(super) fn finish(self) -> LossyAnalysis {
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let measured: Vec<&BlockCutoff> = self.blocks.iter().flatten().collect();
+        let cutoffs: Vec<f32> = measured.iter().map(|b| b.frequency_hz).collect();
+        let cutoff_hz = median(&cutoffs);
+        let cutoff_over_time = self
+            .blocks
+            .iter()
+            .map(|b| b.as_ref().map(|b| b.frequency_hz))
+            .collect();
+
+        let mut signatures = Vec::new();
+        let mut confidence: f32 = 0.0;
+
+        if let Some(cutoff) = cutoff_hz {
+            let count = measured.len() as f32;
+            let steep = measured
+                .iter()
+                .filter(|b| b.drop_db >= SHELF_MIN_DROP_DB)
+                .count() as f32
+                / count;
+            let consistent = cutoffs
+                .iter()
+                .filter(|&&hz| (hz - cutoff).abs() <= 300.0)
+                .count() as f32
+                / count;
+            let known = KNOWN_CUTOFFS_HZ
+                .iter()
+                .any(|&hz| (hz - cutoff).abs() <= 250.0);
+
+            if steep >= 0.5 && consistent >= 0.5 && cutoff <= MAX_LOSSY_CUTOFF_HZ {
+                signatures.push(LossySignature::HardShelf {
+                    frequency_hz: cutoff,
+                });
+                let bonus = if known { 0.2 } else { 0.05 };
+                confidence = confidence.max(0.45 + 0.15 * steep + 0.2 * consistent + bonus);
+            }
+
+            let steps: Vec<f32> = measured
+                .iter()
+                .filter_map(|b| b.step.map(|(hz, _)| hz))
+                .collect();
+            if steps.len() as f32 >= 0.5 * count {
+                if let Some(crossover) = median(&steps) {
+                    let consistent_steps = steps
+                        .iter()
+                        .filter(|&&hz| (hz - crossover).abs() <= 200.0)
+                        .count() as f32
+                        / count;
+                    if consistent_steps >= 0.6 {
+                        signatures.push(LossySignature::Sbr {
+                            crossover_hz: crossover,
+                        });
+                        confidence = confidence.max(0.4 + 0.5 * consistent_steps);
+                    }
+                }
+            }
+        }
+
+        if self.sfb21_frames > 0 {
+            let holes = self.sfb21_holes as f32 / self.sfb21_frames as f32;
+            let filled = self.sfb21_filled as f32 / self.sfb21_frames as f32;
+            if holes >= 0.1 && filled >= 0.1 {
+                signatures.push(LossySignature::Sfb21Hole);
+                confidence = confidence.max((0.5 + 0.8 * holes.min(filled)).min(0.9));
+            }
+        }
+
+        let verdict = if !signatures.is_empty() {
+            LossyVerdict::Lossy
+        } else if measured.len() < 3 {
+            LossyVerdict::Inconclusive
+        } else {
+            let steep = measured
+                .iter()
+                .filter(|b| b.drop_db >= SHELF_MIN_DROP_DB)
+                .count() as f32
+                / measured.len() as f32;
+            // Full-band content is the strongest hint, capped at what CD audio can hold.
+            let reach = cutoff_hz.unwrap_or(0.0) / (0.9 * nyquist.min(22_050.0));
+            confidence = 0.95 * (1.0 - steep) * reach.min(1.0);
+            LossyVerdict::Lossless
+        };
+
+        LossyAnalysis {
+            verdict,
+            confidence: confidence.clamp(0.0, 0.99),
+            cutoff_hz,
+            cutoff_over_time,
+            block_seconds: BLOCK_SECONDS,
+            signatures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::bin_to_hz;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// Power spectrum at `level_db` up to `cutoff_hz` and 110 dB lower above it.
+    fn spectrum(cutoff_hz: f32, level_db: f32) -> Vec<f32> {
+        (0..=ANALYSIS_FFT_SIZE / 2)
+            .map(|bin| {
+                let hz = bin_to_hz(bin, SAMPLE_RATE);
+                let db = if hz <= cutoff_hz {
+                    level_db
+                } else {
+                    level_db - 110.0
+                };
+                10f32.powf(db / 10.0)
+            })
+            .collect()
+    }
+
+    fn analyse(frames: impl Iterator<Item = Vec<f32>>) -> LossyAnalysis {
+        let mut analyzer = LossyAnalyzer::new(SAMPLE_RATE);
+        for frame in frames {
+            analyzer.push_frame(&frame);
+        }
+        analyzer.finish()
+    }
+
+    /// Ten seconds worth of analysis frames.
+    fn frame_count() -> usize {
+        10 * SAMPLE_RATE as usize / ANALYSIS_FFT_SIZE
+    }
+
+    #[test]
+    fn full_band_content_is_lossless() {
+        let frame = spectrum(SAMPLE_RATE as f32 / 2.0, -40.0);
+        let analysis = analyse(std::iter::repeat_n(frame, frame_count()));
+        assert_eq!(analysis.verdict, LossyVerdict::Lossless);
+        assert!(analysis.signatures.is_empty());
+        assert!(analysis.confidence > 0.9, "{}", analysis.confidence);
+    }
+
+    #[test]
+    fn encoder_lowpass_is_a_hard_shelf() {
+        let frame = spectrum(16_000.0, -40.0);
+        let analysis = analyse(std::iter::repeat_n(frame, frame_count()));
+        assert_eq!(analysis.verdict, LossyVerdict::Lossy);
+        let cutoff = analysis.cutoff_hz.unwrap();
+        assert!((cutoff - 16_000.0).abs() < 200.0, "{cutoff}");
+        assert!(matches!(
+            analysis.signatures[..],
+            [LossySignature::HardShelf { .. }]
+        ));
+        assert!(analysis.confidence > 0.9, "{}", analysis.confidence);
+    }
+
+    #[test]
+    fn shelf_above_lossy_lowpasses_is_not_flagged() {
+        let frame = spectrum(21_000.0, -40.0);
+        let analysis = analyse(std::iter::repeat_n(frame, frame_count()));
+        assert_ne!(analysis.verdict, LossyVerdict::Lossy);
+    }
+
+    /// Frames alternating between `filled` and `hole`.
+    fn alternating(filled: Vec<f32>, hole: Vec<f32>) -> impl Iterator<Item = Vec<f32>> {
+        (0..frame_count()).map(move |i| match i % 2 {
+            0 => filled.clone(),
+            _ => hole.clone(),
+        })
+    }
+
+    #[test]
+    fn switching_sfb21_band_is_capped_at_ninety_percent() {
+        let filled = spectrum(SAMPLE_RATE as f32 / 2.0, -40.0);
+        let analysis = analyse(alternating(filled, spectrum(16_000.0, -40.0)));
+        assert_eq!(analysis.verdict, LossyVerdict::Lossy);
+        assert_eq!(analysis.signatures, [LossySignature::Sfb21Hole]);
+        assert!(analysis.confidence <= 0.9, "{}", analysis.confidence);
+    }
+
+    #[test]
+    fn sfb21_cap_leaves_a_stronger_shelf_alone() {
+        let filled = spectrum(20_000.0, -40.0);
+        let analysis = analyse(alternating(filled, spectrum(16_000.0, -40.0)));
+        assert!(analysis.signatures.contains(&LossySignature::Sfb21Hole));
+        assert!(matches!(
+            analysis.signatures[0],
+            LossySignature::HardShelf { .. }
+        ));
+        assert!(analysis.confidence > 0.9, "{}", analysis.confidence);
+    }
+
+    #[test]
+    fn silence_is_inconclusive() {
+        let frame = vec![0.0; ANALYSIS_FFT_SIZE / 2 + 1];
+        let analysis = analyse(std::iter::repeat_n(frame, frame_count()));
+        assert_eq!(analysis.verdict, LossyVerdict::Inconclusive);
+        assert_eq!(analysis.cutoff_hz, None);
+        assert!(analysis.cutoff_over_time.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn partial_last_block_is_dropped() {
+        let frame = spectrum(SAMPLE_RATE as f32 / 2.0, -40.0);
+        let frames_per_block = LossyAnalyzer::new(SAMPLE_RATE).frames_per_block;
+        let analysis = analyse(std::iter::repeat_n(frame, 3 * frames_per_block + 7));
+        assert_eq!(analysis.cutoff_over_time.len(), 3);
+    }
+}
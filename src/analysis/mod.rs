@@ -0,0 +1,361 @@
+use crate::decoder;
+use crate::error::{Error, Result};
+use crate::settings::SpectogramWinFunc;
+use crate::spectrogram;
+use crate::utils::AudioInfo;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub mod bit_depth;
+pub mod lossy;
+pub mod stereo;
+pub mod upsampling;
+
+pub use bit_depth::BitDepthAnalysis;
+pub use lossy::{LossyAnalysis, LossySignature, LossyVerdict};
+pub use stereo::{StereoAnalysis, StereoVerdict};
+pub use upsampling::UpsamplingAnalysis;
+
+/// FFT length used by the analysers, ~10 Hz per bin at 44.1 kHz.
+pub const ANALYSIS_FFT_SIZE: usize = 4096;
+
+// ======================================================
+// Report
+// ======================================================
+
+/// Results of all file analysers.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisReport {
+    /// Where the analysed part of the file starts in seconds, the
+    /// `*_over_time` blocks count from here.
+    pub start_seconds: f64,
+    pub lossy: LossyAnalysis,
+    pub upsampling: UpsamplingAnalysis,
+    /// Only for integer PCM (WAV, FLAC, ALAC, ...) where the header declares a bit depth.
+    pub bit_depth: Option<BitDepthAnalysis>,
+    /// Only for files with a left and a right channel.
+    pub stereo: Option<StereoAnalysis>,
+}
+
+impl AnalysisReport {
+    /// Short findings for the legend header line and the GUI status bar.
+    pub fn summary(&self) -> Vec<String> {
+        let mut summary = Vec::new();
+        if let Some(bit_depth) = &self.bit_depth {
+            summary.push(bit_depth.summary());
+        }
+        if let Some(upsampling) = self.upsampling.summary() {
+            summary.push(upsampling);
+        }
+        summary.push(self.lossy.summary());
+        if let Some(stereo) = &self.stereo {
+            summary.push(stereo.summary());
+        }
+        summary
+    }
+
+    /// `key=value` lines in the style of ffprobe's default output.
+    pub fn to_key_values(&self) -> Vec<(String, String)> {
+        let mut values = Vec::new();
+        if let Some(bit_depth) = &self.bit_depth {
+            values.extend(bit_depth.to_key_values());
+        }
+        values.extend(self.upsampling.to_key_values());
+        values.extend(self.lossy.to_key_values());
+        if let Some(stereo) = &self.stereo {
+            values.extend(stereo.to_key_values());
+        }
+        values
+    }
+}
+
+/// Decodes `input_path` once and runs every analyser over it.
+pub fn analyze_file(input_path: &str, cancel_token: Arc<AtomicBool>) -> Result<AnalysisReport> {
+    analyze_range(input_path, 0.0, f64::INFINITY, cancel_token)
+}
+
+/// Runs every analyser over the part of `input_path` from `start` to `end`
+/// seconds, decoding only that part.
+pub fn analyze_range(
+    input_path: &str,
+    start: f64,
+    end: f64,
+    cancel_token: Arc<AtomicBool>,
+) -> Result<AnalysisReport> {
+    let mut decoder = decoder::open(input_path)?;
+    let info = decoder.info().clone();
+    let channels = (info.channels as usize).max(1);
+    let start = start.max(0.0);
+    if start > 0.0 {
+        decoder.seek(start)?;
+    }
+    let mut frames_left = match end.is_finite() {
+        true => ((end - start).max(0.0) * info.sample_rate as f64).round() as usize,
+        false => usize::MAX,
+    };
+
+    let mut analyzer = FileAnalyzer::new(&info, start);
+    let mut samples = Vec::new();
+    while frames_left > 0 && decoder.next_block(&mut samples)? {
+        if cancel_token.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        samples.truncate(frames_left.saturating_mul(channels));
+        frames_left -= samples.len() / channels;
+        analyzer.push_interleaved(&samples);
+    }
+    Ok(analyzer.finish())
+}
+
+/// Every file analyser, fed with samples decoded elsewhere, so a render can
+/// analyse the file from the same decode (see `utils::generate_magnitudes`).
+pub struct FileAnalyzer {
+    start_seconds: f64,
+    frames: SpectralFrames,
+    lossy: lossy::LossyAnalyzer,
+    upsampling: upsampling::UpsamplingAnalyzer,
+    bit_depth: Option<bit_depth::BitDepthAnalyzer>,
+    stereo: Option<stereo::StereoAnalyzer>,
+}
+
+impl FileAnalyzer {
+    /// Analyser for a file with stream parameters `info`, decoded from
+    /// `start_seconds` on.
+    pub fn new(info: &AudioInfo, start_seconds: f64) -> Self {
+        let channels = info.channels as usize;
+        Self {
+            start_seconds,
+            frames: SpectralFrames::new(channels),
+            lossy: lossy::LossyAnalyzer::new(info.sample_rate),
+            upsampling: upsampling::UpsamplingAnalyzer::new(
+                info.sample_rate,
+                ANALYSIS_FFT_SIZE / 2 + 1,
+            ),
+            bit_depth: (info.bits_per_sample > 0)
+                .then(|| bit_depth::BitDepthAnalyzer::new(info.bits_per_sample, channels)),
+            stereo: info.stereo_pair().map(|(left, right)| {
+                stereo::StereoAnalyzer::new(info.sample_rate, channels, left, right)
+            }),
+        }
+    }
+
+    /// Feeds decoded samples, interleaved with all of the file's channels.
+    pub fn push_interleaved(&mut self, samples: &[f32]) {
+        let (lossy, upsampling) = (&mut self.lossy, &mut self.upsampling);
+        self.frames.push_interleaved(samples, |power| {
+            lossy.push_frame(power);
+            upsampling.push_frame(power);
+        });
+        if let Some(bit_depth) = &mut self.bit_depth {
+            bit_depth.push_interleaved(samples);
+        }
+        if let Some(stereo) = &mut self.stereo {
+            stereo.push_interleaved(samples);
+        }
+    }
+
+    pub fn finish(self) -> AnalysisReport {
+        AnalysisReport {
+            start_seconds: self.start_seconds,
+            lossy: self.lossy.finish(),
+            upsampling: self.upsampling.finish(),
+            bit_depth: self.bit_depth.map(|b| b.finish()),
+            stereo: self.stereo.map(|s| s.finish()),
+        }
+    }
+}
+
+// ======================================================
+// Shared spectral front end
+// ======================================================
+
+/// Splits interleaved PCM into non-overlapping, Hann-windowed frames and
+/// hands their power spectra (linear, 0 dBFS = 1.0) to a callback.
+///
+/// Each channel is transformed on its own and the powers are averaged, so
+/// channels that are delayed or inverted against each other don't cancel
+/// out as they would in a downmix.
+struct SpectralFrames {
+    channels: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    power_norm: f32,
+    /// Interleaved samples of the frame being filled.
+    pending: Vec<f32>,
+    scratch: Vec<Complex<f32>>,
+    power: Vec<f32>,
+}
+
+impl SpectralFrames {
+    fn new(channels: usize) -> Self {
+        let channels = channels.max(1);
+        let window = spectrogram::window(SpectogramWinFunc::Hann, ANALYSIS_FFT_SIZE);
+        let window_sum: f32 = window.iter().sum();
+        Self {
+            channels,
+            fft: FftPlanner::new().plan_fft_forward(ANALYSIS_FFT_SIZE),
+            window,
+            power_norm: 4.0 / (window_sum * window_sum) / channels as f32,
+            pending: Vec::with_capacity(ANALYSIS_FFT_SIZE * channels),
+            scratch: vec![Complex::new(0.0, 0.0); ANALYSIS_FFT_SIZE],
+            power: vec![0.0; ANALYSIS_FFT_SIZE / 2 + 1],
+        }
+    }
+
+    fn push_interleaved(&mut self, samples: &[f32], mut on_frame: impl FnMut(&[f32])) {
+        for frame in samples.chunks_exact(self.channels) {
+            self.pending.extend_from_slice(frame);
+
+            if self.pending.len() == ANALYSIS_FFT_SIZE * self.channels {
+                self.power.fill(0.0);
+                for channel in 0..self.channels {
+                    let samples = self.pending[channel..].iter().step_by(self.channels);
+                    for ((value, sample), w) in
+                        self.scratch.iter_mut().zip(samples).zip(&self.window)
+                    {
+                        *value = Complex::new(sample * w, 0.0);
+                    }
+                    self.fft.process(&mut self.scratch);
+                    for (power, value) in self.power.iter_mut().zip(&self.scratch) {
+                        *power += value.norm_sqr() * self.power_norm;
+                    }
+                }
+                on_frame(&self.power);
+                self.pending.clear();
+            }
+        }
+    }
+}
+
+/// Converts a linear power value to dBFS, clamped to the spectrogram floor.
+fn power_to_db(power: f32) -> f32 {
+    (10.0 * power.max(1e-20).log10()).max(spectrogram::MIN_DB)
+}
+
+/// Frequency in Hz of FFT bin `bin`.
+fn bin_to_hz(bin: usize, sample_rate: u32) -> f32 {
+    bin as f32 * sample_rate as f32 / ANALYSIS_FFT_SIZE as f32
+}
+
+/// FFT bin closest to `hz`, clamped to the spectrum.
+fn hz_to_bin(hz: f32, sample_rate: u32) -> usize {
+    let bin = (hz * ANALYSIS_FFT_SIZE as f32 / sample_rate as f32).round() as usize;
+    bin.min(ANALYSIS_FFT_SIZE / 2)
+}
+
+/// Mean level in dB of the bins between `lo_hz` and `hi_hz`.
+fn band_level_db(power: &[f32], sample_rate: u32, lo_hz: f32, hi_hz: f32) -> f32 {
+    let lo = hz_to_bin(lo_hz, sample_rate);
+    let hi = hz_to_bin(hi_hz, sample_rate).max(lo + 1).min(power.len());
+    let mean = power[lo..hi].iter().sum::<f32>() / (hi - lo) as f32;
+    power_to_db(mean)
+}
+
+/// Finds where the content of a spectrum ends, searching down from Nyquist.
+///
+/// The spectrum is smoothed over `smoothing_hz` first. The edge is the highest
+/// frequency above 5 kHz that rises `above_floor_db` over the quietest point
+/// of that range. Returns `(edge_hz, floor_db)`.
+fn content_edge(
+    power: &[f32],
+    sample_rate: u32,
+    smoothing_hz: f32,
+    above_floor_db: f32,
+) -> (f32, f32) {
+    // Moving average in the linear domain, then dB.
+    let radius = hz_to_bin(smoothing_hz / 2.0, sample_rate).max(1);
+    let smoothed: Vec<f32> = (0..power.len())
+        .map(|k| {
+            let lo = k.saturating_sub(radius);
+            let hi = (k + radius + 1).min(power.len());
+            power_to_db(power[lo..hi].iter().sum::<f32>() / (hi - lo) as f32)
+        })
+        .collect();
+
+    let search_lo = hz_to_bin(5_000.0, sample_rate);
+    let floor = smoothed[search_lo..]
+        .iter()
+        .fold(f32::INFINITY, |acc, &db| acc.min(db));
+    let threshold = floor + above_floor_db;
+    // Nothing stands out above the floor: the spectrum is flat up to Nyquist.
+    let edge_bin = (search_lo..smoothed.len())
+        .rev()
+        .find(|&k| smoothed[k] > threshold)
+        .unwrap_or(smoothed.len() - 1);
+
+    (bin_to_hz(edge_bin, sample_rate), floor)
+}
+
+/// Median of `values`, `None` when empty.
+fn median(values: &[f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    Some(sorted[sorted.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak level of the averaged power spectra of a stereo sine whose
+    /// right channel is `right_gain` times the left one, `delay` frames late.
+    fn stereo_sine_peak_db(right_gain: f32, delay: usize) -> f32 {
+        let sample_rate = 44_100.0;
+        // On bin 100, so all the power lands in one bin.
+        let hz = bin_to_hz(100, 44_100);
+        let sine = |n: usize| (2.0 * std::f32::consts::PI * hz * n as f32 / sample_rate).sin();
+        let samples: Vec<f32> = (0..ANALYSIS_FFT_SIZE)
+            .flat_map(|n| [sine(n + delay), right_gain * sine(n)])
+            .collect();
+
+        let mut frames = SpectralFrames::new(2);
+        let mut peak = f32::MIN;
+        frames.push_interleaved(&samples, |power| {
+            peak = power_to_db(power.iter().copied().fold(0.0, f32::max));
+        });
+        peak
+    }
+
+    #[test]
+    fn opposite_channels_do_not_cancel() {
+        assert!(stereo_sine_peak_db(1.0, 0).abs() < 0.1);
+        assert!(stereo_sine_peak_db(-1.0, 0).abs() < 0.1);
+        // Half a period of bin 100 late, a downmix would cancel too.
+        let half_period = ANALYSIS_FFT_SIZE / 200;
+        assert!(stereo_sine_peak_db(1.0, half_period).abs() < 0.5);
+    }
+
+    #[test]
+    fn silent_channel_halves_the_power() {
+        assert!((stereo_sine_peak_db(0.0, 0) + 3.01).abs() < 0.1);
+    }
+
+    #[test]
+    fn renders_analyse_their_own_decode() {
+        use crate::render::{RenderRequest, Renderer};
+        use crate::settings::{AppSettings, SpectrogramBackend};
+
+        let samples = crate::test_support::white_noise(7, 2 * 3 * 44_100, 0.5);
+        let file = crate::test_support::write_wav("shared_decode", 44_100, 2, &samples);
+        let settings = AppSettings {
+            backend: SpectrogramBackend::Native,
+            analyze_audio: true,
+            legend: false,
+            ..AppSettings::default()
+        };
+        let request = RenderRequest::new(file.path_str(), settings).with_time_range(0.5, 2.5);
+        let rendered = Renderer::new().render(&request).unwrap().analysis.unwrap();
+
+        let separate = analyze_range(file.path_str(), 0.5, 2.5, Default::default()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&rendered).unwrap(),
+            serde_json::to_value(&separate).unwrap()
+        );
+    }
+}
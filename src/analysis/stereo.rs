@@ -0,0 +1,577 @@
+use super::power_to_db;
+use crate::settings::SpectogramWinFunc;
+use crate::spectrogram;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// FFT length of the stereo analyser, long enough that a delay of a few
+/// milliseconds still leaves most of the window overlapping.
+const STEREO_FFT_SIZE: usize = 8192;
+/// Length of the blocks the correlation is tracked over.
+const BLOCK_SECONDS: f32 = 0.5;
+/// Frequency bands the correlation is measured in separately, in Hz.
+const BANDS_HZ: [(f32, f32); 4] = [
+    (20.0, 250.0),
+    (250.0, 2_000.0),
+    (2_000.0, 8_000.0),
+    (8_000.0, 20_000.0),
+];
+/// Bands and blocks quieter than this in either channel have no correlation.
+const MIN_LEVEL_DB: f32 = -90.0;
+/// A channel this much quieter than the other counts as silent.
+const SILENT_CHANNEL_DB: f32 = 60.0;
+/// Correlation of channels that only differ by their gain, or by dither
+/// and lossy coding noise.
+const MONO_CORRELATION: f32 = 0.999;
+/// Correlation below which one channel is taken to be inverted. Real mixes
+/// stay positive overall, their centre outweighs any wide effects.
+const INVERTED_CORRELATION: f32 = -0.3;
+/// Correlation at the best lag that makes the channels delayed copies.
+const DELAYED_CORRELATION: f32 = 0.99;
+/// Longest delay between the channels searched for.
+const MAX_DELAY_SECONDS: f32 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoVerdict {
+    /// Two distinct channels.
+    Stereo,
+    /// The same signal in both channels, a mono file stored as stereo.
+    Mono,
+    /// One channel is the other with its polarity flipped, or the mix has
+    /// one channel inverted.
+    InvertedPolarity,
+    /// The same signal in both channels, one a little later than the other.
+    Delayed,
+    /// One channel carries nothing.
+    SilentChannel,
+    /// Neither channel carries anything.
+    Silent,
+}
+
+impl StereoVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stereo => "stereo",
+            Self::Mono => "mono",
+            Self::InvertedPolarity => "inverted_polarity",
+            Self::Delayed => "delayed",
+            Self::SilentChannel => "silent_channel",
+            Self::Silent => "silent",
+        }
+    }
+}
+
+/// Correlation of the left and right channel within one frequency band.
+#[derive(Debug, Clone, Serialize)]
+pub struct BandCorrelation {
+    pub low_hz: f32,
+    pub high_hz: f32,
+    /// Over the whole file, `None` when the band is silent.
+    pub correlation: Option<f32>,
+    /// Per `block_seconds` block, `None` for silent blocks.
+    pub correlation_over_time: Vec<Option<f32>>,
+}
+
+/// How the left and right channel of a file relate: +1 correlation for
+/// identical channels, 0 for unrelated ones, -1 for one the inverse of the other.
+#[derive(Debug, Clone, Serialize)]
+pub struct StereoAnalysis {
+    pub verdict: StereoVerdict,
+    /// Correlation over the whole file and band, `None` when either channel is silent.
+    pub correlation: Option<f32>,
+    /// Level of the left channel over the right one.
+    pub level_difference_db: Option<f32>,
+    /// Milliseconds the right channel lags behind the left one where they
+    /// correlate best, negative when it leads.
+    pub delay_ms: Option<f32>,
+    /// Correlation at that delay.
+    pub delay_correlation: Option<f32>,
+    /// Correlation over the whole band per `block_seconds` block, `None`
+    /// for silent blocks.
+    pub correlation_over_time: Vec<Option<f32>>,
+    pub block_seconds: f32,
+    pub bands: Vec<BandCorrelation>,
+}
+
+impl StereoAnalysis {
+    pub fn summary(&self) -> String {
+        match self.verdict {
+            StereoVerdict::Stereo => match self.correlation {
+                Some(correlation) => format!("Stereo, correlation {:+.2}", correlation),
+                None => "Stereo".to_string(),
+            },
+            StereoVerdict::Mono => "Mono stored as stereo".to_string(),
+            StereoVerdict::InvertedPolarity => format!(
+                "Polarity-inverted channel, correlation {:+.2}",
+                self.correlation.unwrap_or(-1.0)
+            ),
+            StereoVerdict::Delayed => {
+                let delay = self.delay_ms.unwrap_or(0.0);
+                let (later, earlier) = if delay >= 0.0 { ("R", "L") } else { ("L", "R") };
+                format!(
+                    "{} delayed {:.2} ms against {}",
+                    later,
+                    delay.abs(),
+                    earlier
+                )
+            }
+            StereoVerdict::SilentChannel => {
+                let silent = match self.level_difference_db.unwrap_or(0.0) > 0.0 {
+                    true => "R",
+                    false => "L",
+                };
+                format!("{} channel silent", silent)
+            }
+            StereoVerdict::Silent => "Silent".to_string(),
+        }
+    }
+
+    /// Correlation per band over the whole file, one line each.
+    pub fn band_lines(&self) -> Vec<String> {
+        self.bands
+            .iter()
+            .map(|band| {
+                let correlation = band
+                    .correlation
+                    .map_or("silent".to_string(), |c| format!("{:+.2}", c));
+                format!("{:.0}–{:.0} Hz: {}", band.low_hz, band.high_hz, correlation)
+            })
+            .collect()
+    }
+
+    pub fn to_key_values(&self) -> Vec<(String, String)> {
+        let or_na = |value: Option<f32>, decimals: usize| {
+            value.map_or("N/A".to_string(), |v| format!("{:.*}", decimals, v))
+        };
+        let mut values = vec![
+            ("stereo.verdict".into(), self.verdict.as_str().into()),
+            ("stereo.correlation".into(), or_na(self.correlation, 3)),
+            (
+                "stereo.level_difference_db".into(),
+                or_na(self.level_difference_db, 1),
+            ),
+            ("stereo.delay_ms".into(), or_na(self.delay_ms, 3)),
+            (
+                "stereo.delay_correlation".into(),
+                or_na(self.delay_correlation, 3),
+            ),
+        ];
+        for band in &self.bands {
+            values.push((
+                format!("stereo.band.{:.0}_{:.0}_hz", band.low_hz, band.high_hz),
+                or_na(band.correlation, 3),
+            ));
+        }
+        values
+    }
+}
+
+/// Cross and auto power summed over some bins and frames.
+#[derive(Debug, Clone, Copy, Default)]
+struct PowerSums {
+    cross: f64,
+    left: f64,
+    right: f64,
+    frames: usize,
+}
+
+impl PowerSums {
+    fn add(&mut self, other: &PowerSums) {
+        self.cross += other.cross;
+        self.left += other.left;
+        self.right += other.right;
+        self.frames += other.frames;
+    }
+
+    /// Level of the quieter channel in dBFS, `power_norm` scaling the FFT
+    /// power to a sine's.
+    fn min_level_db(&self, power_norm: f64) -> f32 {
+        let frames = self.frames.max(1) as f64;
+        power_to_db((self.left.min(self.right) * power_norm / frames) as f32)
+    }
+
+    fn correlation(&self, power_norm: f64) -> Option<f32> {
+        if self.min_level_db(power_norm) < MIN_LEVEL_DB {
+            return None;
+        }
+        Some((self.cross / (self.left * self.right).sqrt()).clamp(-1.0, 1.0) as f32)
+    }
+}
+
+pub(super) struct StereoAnalyzer {
+    sample_rate: u32,
+    channels: usize,
+    left: usize,
+    right: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    power_norm: f64,
+    pending_left: Vec<f32>,
+    pending_right: Vec<f32>,
+    left_scratch: Vec<Complex<f32>>,
+    right_scratch: Vec<Complex<f32>>,
+
+    /// Bin ranges of `BANDS_HZ` below Nyquist, with their frequencies.
+    bands: Vec<(usize, usize, f32, f32)>,
+    /// Cross spectrum and power spectra summed over the whole file.
+    cross_spectrum: Vec<Complex<f64>>,
+    left_power: Vec<f64>,
+    right_power: Vec<f64>,
+
+    block_frames: usize,
+    /// Sums of the current block, the whole band first and then each band.
+    block: Vec<PowerSums>,
+    totals: Vec<PowerSums>,
+    over_time: Vec<Vec<Option<f32>>>,
+}
+
+impl StereoAnalyzer {
+    pub(super) fn new(sample_rate: u32, channels: usize, left: usize, right: usize) -> Self {
+        let window = spectrogram::window(SpectogramWinFunc::Hann, STEREO_FFT_SIZE);
+        let window_sum: f64 = window.iter().map(|&w| w as f64).sum();
+        let bins = STEREO_FFT_SIZE / 2 + 1;
+        let nyquist = sample_rate as f32 / 2.0;
+        let hz_to_bin = |hz: f32| {
+            ((hz * STEREO_FFT_SIZE as f32 / sample_rate.max(1) as f32).round() as usize)
+                .clamp(1, bins - 1)
+        };
+        let bands: Vec<(usize, usize, f32, f32)> = BANDS_HZ
+            .iter()
+            .filter(|(low, _)| *low < nyquist)
+            .map(|&(low, high)| {
+                let high = high.min(nyquist);
+                (
+                    hz_to_bin(low),
+                    hz_to_bin(high).max(hz_to_bin(low) + 1),
+                    low,
+                    high,
+                )
+            })
+            .collect();
+        let block_frames =
+            ((BLOCK_SECONDS * sample_rate as f32 / STEREO_FFT_SIZE as f32).round() as usize).max(1);
+        let sums = bands.len() + 1;
+        Self {
+            sample_rate,
+            channels: channels.max(2),
+            left,
+            right,
+            fft: FftPlanner::new().plan_fft_forward(STEREO_FFT_SIZE),
+            window,
+            power_norm: 4.0 / (window_sum * window_sum),
+            pending_left: Vec::with_capacity(STEREO_FFT_SIZE),
+            pending_right: Vec::with_capacity(STEREO_FFT_SIZE),
+            left_scratch: vec![Complex::new(0.0, 0.0); STEREO_FFT_SIZE],
+            right_scratch: vec![Complex::new(0.0, 0.0); STEREO_FFT_SIZE],
+            bands,
+            cross_spectrum: vec![Complex::new(0.0, 0.0); bins],
+            left_power: vec![0.0; bins],
+            right_power: vec![0.0; bins],
+            block_frames,
+            block: vec![PowerSums::default(); sums],
+            totals: vec![PowerSums::default(); sums],
+            over_time: vec![Vec::new(); sums],
+        }
+    }
+
+    pub(super) fn push_interleaved(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            self.pending_left.push(frame[self.left]);
+            self.pending_right.push(frame[self.right]);
+            if self.pending_left.len() == STEREO_FFT_SIZE {
+                self.process_frame();
+                self.pending_left.clear();
+                self.pending_right.clear();
+            }
+        }
+    }
+
+    fn process_frame(&mut self) {
+        for (i, &w) in self.window.iter().enumerate() {
+            self.left_scratch[i] = Complex::new(self.pending_left[i] * w, 0.0);
+            self.right_scratch[i] = Complex::new(self.pending_right[i] * w, 0.0);
+        }
+        self.fft.process(&mut self.left_scratch);
+        self.fft.process(&mut self.right_scratch);
+
+        // DC is left out, an offset says nothing about the stereo image.
+        let mut frame = vec![PowerSums::default(); self.block.len()];
+        for bin in 1..self.cross_spectrum.len() {
+            let left = self.left_scratch[bin];
+            let right = self.right_scratch[bin];
+            let cross = left * right.conj();
+            let cross = Complex::new(cross.re as f64, cross.im as f64);
+            let left_power = left.norm_sqr() as f64;
+            let right_power = right.norm_sqr() as f64;
+            self.cross_spectrum[bin] += cross;
+            self.left_power[bin] += left_power;
+            self.right_power[bin] += right_power;
+
+            let sums = std::iter::once(0).chain(
+                self.bands
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &(low, high, _, _))| (low..high).contains(&bin))
+                    .map(|(band, _)| band + 1),
+            );
+            for index in sums {
+                frame[index].cross += cross.re;
+                frame[index].left += left_power;
+                frame[index].right += right_power;
+            }
+        }
+        for (block, frame) in self.block.iter_mut().zip(&frame) {
+            block.add(&PowerSums {
+                frames: 1,
+                ..*frame
+            });
+        }
+        if self.block[0].frames == self.block_frames {
+            self.finish_block();
+        }
+    }
+
+    fn finish_block(&mut self) {
+        for ((block, total), over_time) in self
+            .block
+            .iter_mut()
+            .zip(&mut self.totals)
+            .zip(&mut self.over_time)
+        {
+            over_time.push(block.correlation(self.power_norm));
+            total.add(block);
+            *block = PowerSums::default();
+        }
+    }
+
+    pub(super) fn finish(mut self) -> StereoAnalysis {
+        if self.block[0].frames > 0 {
+            self.finish_block();
+        }
+        let total = self.totals[0];
+        let block_seconds =
+            (self.block_frames * STEREO_FFT_SIZE) as f32 / self.sample_rate.max(1) as f32;
+        let bands = self
+            .bands
+            .iter()
+            .zip(&self.totals[1..])
+            .zip(&self.over_time[1..])
+            .map(
+                |((&(_, _, low_hz, high_hz), sums), over_time)| BandCorrelation {
+                    low_hz,
+                    high_hz,
+                    correlation: sums.correlation(self.power_norm),
+                    correlation_over_time: over_time.clone(),
+                },
+            )
+            .collect();
+        let mut analysis = StereoAnalysis {
+            verdict: StereoVerdict::Silent,
+            correlation: total.correlation(self.power_norm),
+            level_difference_db: None,
+            delay_ms: None,
+            delay_correlation: None,
+            correlation_over_time: self.over_time[0].clone(),
+            block_seconds,
+            bands,
+        };
+
+        // Anything quieter counts as silence, a silent channel is not infinitely quieter.
+        let level_db = |power: f64| {
+            power_to_db((power * self.power_norm / total.frames.max(1) as f64) as f32)
+                .max(MIN_LEVEL_DB)
+        };
+        let (left_db, right_db) = (level_db(total.left), level_db(total.right));
+        if left_db.max(right_db) <= MIN_LEVEL_DB {
+            return analysis;
+        }
+        let difference_db = left_db - right_db;
+        analysis.level_difference_db = Some(difference_db);
+        if difference_db.abs() > SILENT_CHANNEL_DB {
+            analysis.verdict = StereoVerdict::SilentChannel;
+            return analysis;
+        }
+
+        let (delay, delay_correlation) = self.find_delay();
+        let delay_ms = 1000.0 * delay as f32 / self.sample_rate as f32;
+        analysis.delay_ms = Some(delay_ms);
+        analysis.delay_correlation = Some(delay_correlation);
+
+        let correlation = analysis.correlation.unwrap_or(0.0);
+        analysis.verdict = if correlation >= MONO_CORRELATION {
+            StereoVerdict::Mono
+        } else if correlation <= INVERTED_CORRELATION {
+            StereoVerdict::InvertedPolarity
+        } else if delay != 0 && delay_correlation >= DELAYED_CORRELATION {
+            StereoVerdict::Delayed
+        } else {
+            StereoVerdict::Stereo
+        };
+        analysis
+    }
+
+    /// Samples the right channel lags behind the left where they correlate
+    /// best, and the correlation there.
+    fn find_delay(&self) -> (i64, f32) {
+        // The full, Hermitian cross spectrum back to a cross-correlation:
+        // correlation[m] = sum of left[n + m] * right[n].
+        let half = self.cross_spectrum.len() - 1;
+        let mut correlation: Vec<Complex<f32>> = (0..STEREO_FFT_SIZE)
+            .map(|bin| {
+                let value = match bin <= half {
+                    true => self.cross_spectrum[bin],
+                    false => self.cross_spectrum[STEREO_FFT_SIZE - bin].conj(),
+                };
+                Complex::new(value.re as f32, value.im as f32)
+            })
+            .collect();
+        FftPlanner::new()
+            .plan_fft_inverse(STEREO_FFT_SIZE)
+            .process(&mut correlation);
+
+        let full_power = |power: &[f64]| 2.0 * power[1..half].iter().sum::<f64>() + power[half];
+        let norm = (full_power(&self.left_power) * full_power(&self.right_power)).sqrt();
+        if norm <= 0.0 {
+            return (0, 0.0);
+        }
+
+        // Shifted windows overlap less, which lowers the correlation at
+        // larger lags by the window's own autocorrelation.
+        let window_correlation = |lag: usize| -> f64 {
+            self.window[lag..]
+                .iter()
+                .zip(&self.window)
+                .map(|(&a, &b)| (a * b) as f64)
+                .sum()
+        };
+        let window_energy = window_correlation(0);
+        let max_lag =
+            ((MAX_DELAY_SECONDS * self.sample_rate as f32) as usize).min(STEREO_FFT_SIZE / 8);
+        let lags: Vec<(i64, f32)> = (-(max_lag as i64)..=max_lag as i64)
+            .map(|lag| {
+                let index = lag.rem_euclid(STEREO_FFT_SIZE as i64) as usize;
+                let overlap = window_correlation(lag.unsigned_abs() as usize) / window_energy;
+                let value = correlation[index].re as f64 / norm / overlap;
+                // A peak at lag m means the right channel lags by -m.
+                (-lag, value as f32)
+            })
+            .collect();
+
+        // Periodic signals correlate as well at whole periods, the shortest
+        // of the near-equal peaks is the one meant.
+        let best = lags
+            .iter()
+            .map(|&(_, value)| value)
+            .fold(f32::NEG_INFINITY, f32::max);
+        lags.into_iter()
+            .filter(|&(_, value)| value >= best - 0.002)
+            .min_by_key(|&(lag, _)| lag.abs())
+            .map_or((0, 0.0), |(lag, value)| (lag, value.min(1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::white_noise;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// Three seconds of white noise.
+    fn noise(seed: u64) -> Vec<f32> {
+        white_noise(seed, 3 * SAMPLE_RATE as usize, 0.5)
+    }
+
+    /// `signal` delayed by `samples`, negative to make it lead.
+    fn delayed(signal: &[f32], samples: i64) -> Vec<f32> {
+        (0..signal.len() as i64)
+            .map(|n| {
+                let source = n - samples;
+                match (0..signal.len() as i64).contains(&source) {
+                    true => signal[source as usize],
+                    false => 0.0,
+                }
+            })
+            .collect()
+    }
+
+    fn analyse(left: &[f32], right: &[f32]) -> StereoAnalysis {
+        let samples: Vec<f32> = left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect();
+        let mut analyzer = StereoAnalyzer::new(SAMPLE_RATE, 2, 0, 1);
+        // In pieces, like a decoder hands them out.
+        for block in samples.chunks(2 * 1152) {
+            analyzer.push_interleaved(block);
+        }
+        analyzer.finish()
+    }
+
+    fn delay_samples(analysis: &StereoAnalysis) -> i64 {
+        (analysis.delay_ms.unwrap() * SAMPLE_RATE as f32 / 1000.0).round() as i64
+    }
+
+    #[test]
+    fn identical_channels_are_mono() {
+        let left = noise(1);
+        let analysis = analyse(&left, &left);
+        assert_eq!(analysis.verdict, StereoVerdict::Mono);
+        assert!(analysis.correlation.unwrap() > 0.999);
+        assert_eq!(delay_samples(&analysis), 0);
+        assert!(analysis.level_difference_db.unwrap().abs() < 0.01);
+    }
+
+    #[test]
+    fn inverted_channel_is_detected() {
+        let left = noise(2);
+        let right: Vec<f32> = left.iter().map(|s| -s).collect();
+        let analysis = analyse(&left, &right);
+        assert_eq!(analysis.verdict, StereoVerdict::InvertedPolarity);
+        assert!(analysis.correlation.unwrap() < -0.999);
+    }
+
+    #[test]
+    fn late_right_channel_has_a_positive_delay() {
+        let left = noise(3);
+        for samples in [1, 20] {
+            let analysis = analyse(&left, &delayed(&left, samples));
+            assert_eq!(
+                analysis.verdict,
+                StereoVerdict::Delayed,
+                "{samples} samples"
+            );
+            assert_eq!(delay_samples(&analysis), samples);
+            assert!(analysis.delay_correlation.unwrap() > 0.99);
+        }
+    }
+
+    #[test]
+    fn early_right_channel_has_a_negative_delay() {
+        let left = noise(4);
+        let analysis = analyse(&left, &delayed(&left, -5));
+        assert_eq!(analysis.verdict, StereoVerdict::Delayed);
+        assert_eq!(delay_samples(&analysis), -5);
+    }
+
+    #[test]
+    fn independent_channels_are_stereo() {
+        let analysis = analyse(&noise(5), &noise(6));
+        assert_eq!(analysis.verdict, StereoVerdict::Stereo);
+        assert!(analysis.correlation.unwrap().abs() < 0.05);
+        assert!(analysis.delay_correlation.unwrap() < 0.5);
+    }
+
+    #[test]
+    fn silence_is_reported_per_channel() {
+        let left = noise(7);
+        let silence = vec![0.0; left.len()];
+        let analysis = analyse(&left, &silence);
+        assert_eq!(analysis.verdict, StereoVerdict::SilentChannel);
+        assert!(analysis.level_difference_db.unwrap() > SILENT_CHANNEL_DB);
+
+        let analysis = analyse(&silence, &silence);
+        assert_eq!(analysis.verdict, StereoVerdict::Silent);
+        assert!(analysis.correlation_over_time.iter().all(Option::is_none));
+    }
+}
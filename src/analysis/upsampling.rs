@@ -0,0 +1,211 @@
+use super::{band_level_db, content_edge};
+use serde::Serialize;
+
+/// Width of the moving average applied to the spectrum before the edge search.
+const SMOOTHING_HZ: f32 = 200.0;
+/// The content ends where the spectrum last rises this far above its floor.
+const EDGE_ABOVE_FLOOR_DB: f32 = 12.0;
+/// Anything this far below the 1–8 kHz level counts as silence. Keeps the
+/// edge search out of the stopband skirt of resamplers when the noise floor
+/// is very low (24-bit, float).
+const MAX_RANGE_DB: f32 = 90.0;
+/// Level difference across the edge that counts as a resampler's brickwall,
+/// natural high-frequency rolloff is much gentler.
+const MIN_EDGE_DROP_DB: f32 = 30.0;
+/// Content ending below this fraction of Nyquist is worth a closer look.
+const MAX_EDGE_FRACTION: f32 = 0.95;
+/// Sample rates source material is usually produced at.
+const SOURCE_RATES: [u32; 5] = [44_100, 48_000, 88_200, 96_000, 176_400];
+
+/// Estimated bandwidth of the content compared to what the container can hold.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpsamplingAnalysis {
+    pub container_rate: u32,
+    /// Frequency where the content ends, `None` when the file is silent.
+    pub content_end_hz: Option<f32>,
+    /// Most likely original sample rate when the file looks upsampled.
+    pub source_rate: Option<u32>,
+}
+
+impl UpsamplingAnalysis {
+    pub fn is_upsampled(&self) -> bool {
+        self.source_rate.is_some()
+    }
+
+    /// Finding for the legend and status bar, `None` unless upsampling was detected.
+    pub fn summary(&self) -> Option<String> {
+        let source_rate = self.source_rate?;
+        let content_end = self.content_end_hz?;
+        Some(format!(
+            "{} container, content ends at {} ≈ {} source",
+            format_khz(self.container_rate as f32),
+            format_khz(content_end),
+            format_khz(source_rate as f32)
+        ))
+    }
+
+    pub fn to_key_values(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "upsampling.detected".into(),
+                self.is_upsampled().to_string(),
+            ),
+            (
+                "upsampling.content_end_hz".into(),
+                self.content_end_hz
+                    .map_or("N/A".to_string(), |hz| format!("{:.0}", hz)),
+            ),
+            (
+                "upsampling.source_rate".into(),
+                self.source_rate
+                    .map_or("N/A".to_string(), |rate| rate.to_string()),
+            ),
+        ]
+    }
+}
+
+/// "44.1 kHz", "96 kHz".
+fn format_khz(hz: f32) -> String {
+    let khz = format!("{:.1}", hz / 1000.0);
+    format!("{} kHz", khz.trim_end_matches(".0"))
+}
+
+pub(super) struct UpsamplingAnalyzer {
+    sample_rate: u32,
+    power_sum: Vec<f32>,
+    frames: usize,
+}
+
+impl UpsamplingAnalyzer {
+    pub(super) fn new(sample_rate: u32, bins: usize) -> Self {
+        Self {
+            sample_rate,
+            power_sum: vec![0.0; bins],
+            frames: 0,
+        }
+    }
+
+    pub(super) fn push_frame(&mut self, power: &[f32]) {
+        for (acc, &p) in self.power_sum.iter_mut().zip(power) {
+            *acc += p;
+        }
+        self.frames += 1;
+    }
+
+    pub(super) fn finish(self) -> UpsamplingAnalysis {
+        let sr = self.sample_rate;
+        let mut analysis = UpsamplingAnalysis {
+            container_rate: sr,
+            content_end_hz: None,
+            source_rate: None,
+        };
+        if self.frames == 0 || self.power_sum.iter().all(|&p| p == 0.0) {
+            return analysis;
+        }
+
+        // The long-term spectrum, individual quiet passages don't matter here.
+        let frames = self.frames as f32;
+        let mut mean: Vec<f32> = self.power_sum.iter().map(|p| p / frames).collect();
+        let reference_db = band_level_db(&mean, sr, 1_000.0, 8_000.0);
+        let min_power = 10f32.powf((reference_db - MAX_RANGE_DB) / 10.0);
+        mean.iter_mut().for_each(|p| *p = p.max(min_power));
+        let (edge_hz, _) = content_edge(&mean, sr, SMOOTHING_HZ, EDGE_ABOVE_FLOOR_DB);
+        analysis.content_end_hz = Some(edge_hz);
+
+        let nyquist = sr as f32 / 2.0;
+        if edge_hz > MAX_EDGE_FRACTION * nyquist {
+            return analysis;
+        }
+
+        let drop_db = band_level_db(&mean, sr, edge_hz - 2_000.0, edge_hz - 300.0)
+            - band_level_db(&mean, sr, edge_hz + 300.0, (edge_hz + 2_000.0).min(nyquist));
+        if drop_db < MIN_EDGE_DROP_DB {
+            return analysis;
+        }
+
+        // The lowest common rate whose Nyquist frequency still covers the content.
+        analysis.source_rate = SOURCE_RATES
+            .iter()
+            .copied()
+            .filter(|&rate| rate < sr)
+            .find(|&rate| edge_hz <= rate as f32 / 2.0 * 1.02);
+        analysis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{bin_to_hz, ANALYSIS_FFT_SIZE};
+
+    const BINS: usize = ANALYSIS_FFT_SIZE / 2 + 1;
+
+    /// Long-term analysis of frames whose level at each frequency is `db(hz)`.
+    fn analyse(sample_rate: u32, db: impl Fn(f32) -> f32) -> UpsamplingAnalysis {
+        let frame: Vec<f32> = (0..BINS)
+            .map(|bin| 10f32.powf(db(bin_to_hz(bin, sample_rate)) / 10.0))
+            .collect();
+        let mut analyzer = UpsamplingAnalyzer::new(sample_rate, BINS);
+        for _ in 0..20 {
+            analyzer.push_frame(&frame);
+        }
+        analyzer.finish()
+    }
+
+    /// -30 dB up to `edge_hz`, 100 dB lower above it.
+    fn brickwall(edge_hz: f32) -> impl Fn(f32) -> f32 {
+        move |hz| if hz <= edge_hz { -30.0 } else { -130.0 }
+    }
+
+    #[test]
+    fn cd_source_in_a_hi_res_container() {
+        let analysis = analyse(96_000, brickwall(21_000.0));
+        let end = analysis.content_end_hz.unwrap();
+        assert!((end - 21_000.0).abs() < 300.0, "{end}");
+        assert_eq!(analysis.source_rate, Some(44_100));
+        assert_eq!(
+            analysis.summary().unwrap(),
+            format!(
+                "96 kHz container, content ends at {} ≈ 44.1 kHz source",
+                format_khz(end)
+            )
+        );
+    }
+
+    #[test]
+    fn dat_source_in_a_hi_res_container() {
+        let analysis = analyse(192_000, brickwall(23_500.0));
+        assert_eq!(analysis.source_rate, Some(48_000));
+    }
+
+    #[test]
+    fn full_band_content_is_not_upsampled() {
+        let analysis = analyse(96_000, |_| -30.0);
+        assert!(!analysis.is_upsampled());
+        assert_eq!(analysis.summary(), None);
+    }
+
+    #[test]
+    fn gentle_rolloff_is_not_a_brickwall() {
+        // 1.5 dB per kHz above 10 kHz, like natural high-frequency rolloff.
+        let analysis = analyse(96_000, |hz| {
+            -30.0 - 1.5 * ((hz - 10_000.0) / 1000.0).max(0.0)
+        });
+        assert!(!analysis.is_upsampled(), "{:?}", analysis.content_end_hz);
+    }
+
+    #[test]
+    fn silence_has_no_content_end() {
+        let mut analyzer = UpsamplingAnalyzer::new(96_000, BINS);
+        analyzer.push_frame(&[0.0; BINS]);
+        let analysis = analyzer.finish();
+        assert_eq!(analysis.content_end_hz, None);
+        assert!(!analysis.is_upsampled());
+    }
+
+    #[test]
+    fn khz_drops_trailing_zero() {
+        assert_eq!(format_khz(96_000.0), "96 kHz");
+        assert_eq!(format_khz(44_100.0), "44.1 kHz");
+    }
+}
@@ -0,0 +1,646 @@
+use crate::align::Alignment;
+use crate::analysis::AnalysisReport;
+use crate::error;
+use crate::render::{RenderRequest, Renderer, Rendering};
+use crate::settings::{AppSettings, CompareLayout, SpectrogramBackend};
+use crate::utils::{self, AudioInfo};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+/// Extensions picked up from directories and glob patterns by default.
+pub const DEFAULT_EXTENSIONS: [&str; 17] = [
+    "flac", "wav", "wave", "mp3", "ogg", "oga", "opus", "m4a", "aac", "aif", "aiff", "caf", "mka",
+    "webm", "wv", "ape", "wma",
+];
+
+/// What to do when the PNG for an input already exists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExistingPolicy {
+    /// Count the file as failed and leave the PNG alone.
+    Fail,
+    Skip,
+    Overwrite,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Files, directories (searched recursively) and glob patterns.
+    pub inputs: Vec<String>,
+    pub output_dir: PathBuf,
+    /// Number of files rendered at the same time.
+    pub jobs: usize,
+    /// Lowercase extensions without the dot.
+    pub extensions: Vec<String>,
+    pub existing: ExistingPolicy,
+}
+
+/// One file to render.
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// Outcome of one file, see `FileReport::status`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Rendered,
+    Skipped,
+    Failed,
+}
+
+/// Everything known about one rendered (or failed) file, for `--report json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub input: PathBuf,
+    /// Second file of a comparison, rendered below or next to `input`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compared_with: Option<PathBuf>,
+    /// How `compared_with` lined up with `input` for a difference comparison.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alignment: Option<Alignment>,
+    pub output: PathBuf,
+    pub status: FileStatus,
+    pub error: Option<String>,
+    pub audio_info: Option<AudioInfo>,
+    /// Settings the file was rendered with, after backend fallback.
+    pub settings: AppSettings,
+    /// Size of the written PNG in pixels, legend included.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub elapsed_seconds: f64,
+    pub analysis: Option<AnalysisReport>,
+}
+
+impl FileReport {
+    fn new(input: &Path, output: &Path, settings: &AppSettings) -> Self {
+        let mut settings = settings.clone();
+        settings.backend = utils::effective_backend(&settings);
+        Self {
+            input: input.to_path_buf(),
+            compared_with: None,
+            alignment: None,
+            output: output.to_path_buf(),
+            status: FileStatus::Failed,
+            error: None,
+            audio_info: None,
+            settings,
+            width: None,
+            height: None,
+            elapsed_seconds: 0.0,
+            analysis: None,
+        }
+    }
+
+    fn failed(mut self, error: String) -> Self {
+        self.status = FileStatus::Failed;
+        self.error = Some(error);
+        self
+    }
+
+    /// The report as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|e| {
+            format!(
+                r#"{{"status":"failed","error":"cannot serialize report: {}"}}"#,
+                e
+            )
+        })
+    }
+
+    /// One human-readable line, e.g. `OK music/a.flac (16 bit, Lossless (95%))`.
+    pub fn to_text(&self) -> String {
+        match self.status {
+            FileStatus::Rendered => {
+                let findings = self
+                    .analysis
+                    .as_ref()
+                    .map(|r| format!(" ({})", r.summary().join(", ")))
+                    .unwrap_or_default();
+                format!("OK {}{}", self.input.display(), findings)
+            }
+            FileStatus::Skipped => format!("SKIP {}", self.input.display()),
+            FileStatus::Failed => format!(
+                "FAIL {}: {}",
+                self.input.display(),
+                self.error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+}
+
+/// How per-file results are printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Text,
+    /// One JSON object per file and line (JSON Lines) on stdout.
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "invalid report format '{}', expected one of: text, json",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub rendered: usize,
+    pub skipped: usize,
+    /// Input path and reason, in the order the files finished.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl BatchSummary {
+    fn add(&mut self, report: &FileReport) {
+        match report.status {
+            FileStatus::Rendered => self.rendered += 1,
+            FileStatus::Skipped => self.skipped += 1,
+            FileStatus::Failed => self.failed.push((
+                report.input.clone(),
+                report.error.clone().unwrap_or_default(),
+            )),
+        }
+    }
+
+    /// Prints the totals and every failure to stderr, keeping stdout for the reports.
+    pub fn print(&self) {
+        eprintln!(
+            "\nRendered {}, skipped {}, failed {}.",
+            self.rendered,
+            self.skipped,
+            self.failed.len()
+        );
+        if !self.failed.is_empty() {
+            eprintln!("\nFailed files:");
+            for (input, reason) in &self.failed {
+                eprintln!("  {}: {}", input.display(), reason);
+            }
+        }
+    }
+}
+
+/// Renders `input_path` with `settings`, writes the PNG to `output_path` and
+/// reports what happened.
+pub fn render_file(input_path: &Path, output_path: &Path, settings: &AppSettings) -> FileReport {
+    let start = Instant::now();
+    let report = FileReport::new(input_path, output_path, settings);
+
+    let request = RenderRequest::new(input_path.to_string_lossy(), settings.clone());
+    save_rendering(report, Renderer::new().render(&request), start)
+}
+
+/// Renders `input_path` and `compare_path` with `settings` into one PNG at
+/// `output_path`, see `Renderer::render_comparison`.
+pub fn render_comparison(
+    input_path: &Path,
+    compare_path: &Path,
+    output_path: &Path,
+    settings: &AppSettings,
+) -> FileReport {
+    let start = Instant::now();
+    let mut report = FileReport::new(input_path, output_path, settings);
+    report.compared_with = Some(compare_path.to_path_buf());
+    // The difference is always taken with the native engine.
+    if settings.compare_layout == CompareLayout::Difference {
+        report.settings.backend = SpectrogramBackend::Native;
+    }
+
+    let request = RenderRequest::new(input_path.to_string_lossy(), settings.clone());
+    let rendering = Renderer::new().render_comparison(&request, &compare_path.to_string_lossy());
+    save_rendering(report, rendering, start)
+}
+
+/// Writes the PNG of a render that started at `start` and completes its report.
+fn save_rendering(
+    mut report: FileReport,
+    rendering: error::Result<Rendering>,
+    start: Instant,
+) -> FileReport {
+    let rendering = match rendering {
+        Ok(rendering) => rendering,
+        Err(e) => {
+            report.elapsed_seconds = start.elapsed().as_secs_f64();
+            return report.failed(e.to_string());
+        }
+    };
+    report.audio_info = rendering.audio_info;
+    report.analysis = rendering.analysis;
+    report.alignment = rendering.alignment;
+    report.width = Some(rendering.image.width());
+    report.height = Some(rendering.image.height());
+
+    let saved = utils::save_png(&rendering.image, &report.output);
+    report.elapsed_seconds = start.elapsed().as_secs_f64();
+    match saved {
+        Ok(()) => {
+            report.status = FileStatus::Rendered;
+            report
+        }
+        Err(e) => report.failed(e.to_string()),
+    }
+}
+
+// ======================================================
+// Input collection
+// ======================================================
+
+/// Expands the inputs into jobs, mirroring their layout below `output_dir`.
+///
+/// Files found in a directory keep their path relative to that directory,
+/// glob matches keep theirs relative to the pattern's fixed leading part and
+/// files given directly go straight into `output_dir`. A file reached by
+/// more than one input is rendered once; two files that would be written
+/// to the same PNG are an error.
+pub fn collect_jobs(options: &BatchOptions) -> Result<Vec<BatchJob>, String> {
+    let mut jobs = Vec::new();
+
+    for input in &options.inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut files = Vec::new();
+            walk_dir(path, &mut HashSet::new(), &mut files)
+                .map_err(|e| format!("cannot read directory '{}': {}", input, e))?;
+            files.retain(|file| has_extension(file, &options.extensions));
+            files.sort();
+            for file in files {
+                let relative = file.strip_prefix(path).unwrap_or(&file).to_path_buf();
+                jobs.push(job_for(file, &relative, &options.output_dir));
+            }
+        } else if path.is_file() {
+            let relative = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+            jobs.push(job_for(path.to_path_buf(), &relative, &options.output_dir));
+        } else if is_glob(input) {
+            let base = glob_base(input);
+            let matches =
+                glob::glob(input).map_err(|e| format!("invalid pattern '{}': {}", input, e))?;
+            let mut files: Vec<PathBuf> = matches
+                .filter_map(Result::ok)
+                .filter(|file| file.is_file() && has_extension(file, &options.extensions))
+                .collect();
+            if files.is_empty() {
+                return Err(format!("no audio files match '{}'", input));
+            }
+            files.sort();
+            for file in files {
+                let relative = file.strip_prefix(&base).unwrap_or(&file).to_path_buf();
+                jobs.push(job_for(file, &relative, &options.output_dir));
+            }
+        } else {
+            return Err(format!("no such file or directory: '{}'", input));
+        }
+    }
+
+    remove_duplicates(jobs)
+}
+
+/// Drops repeated jobs for the same input and rejects different inputs
+/// that map to the same output.
+fn remove_duplicates(jobs: Vec<BatchJob>) -> Result<Vec<BatchJob>, String> {
+    let mut inputs: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut unique = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let input = fs::canonicalize(&job.input).unwrap_or_else(|_| job.input.clone());
+        match inputs.get(&job.output) {
+            Some(seen) if *seen == input => {}
+            Some(seen) => {
+                return Err(format!(
+                    "'{}' and '{}' would both be written to '{}'",
+                    seen.display(),
+                    job.input.display(),
+                    job.output.display()
+                ));
+            }
+            None => {
+                inputs.insert(job.output.clone(), input);
+                unique.push(job);
+            }
+        }
+    }
+    Ok(unique)
+}
+
+/// Collects the files below `dir`. Symlinked directories are followed, but
+/// each directory is read only once, so links back up the tree end the walk.
+fn walk_dir(
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    if !visited.insert(fs::canonicalize(dir)?) {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_dir(&path, visited, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// Directory part of a pattern before the first component with a wildcard.
+fn glob_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|c| !is_glob(&c.as_os_str().to_string_lossy()))
+        .collect()
+}
+
+/// `album/01.flac` becomes `<output_dir>/album/01.flac.png`. The audio
+/// extension is kept so `01.flac` and `01.mp3` don't overwrite each other.
+fn job_for(input: PathBuf, relative: &Path, output_dir: &Path) -> BatchJob {
+    let mut output = output_dir.join(relative).into_os_string();
+    output.push(".png");
+    BatchJob {
+        input,
+        output: PathBuf::from(output),
+    }
+}
+
+// ======================================================
+// Running
+// ======================================================
+
+/// Renders all jobs on `options.jobs` worker threads and prints one report per file.
+pub fn run_batch(
+    jobs: Vec<BatchJob>,
+    options: &BatchOptions,
+    settings: &AppSettings,
+    format: ReportFormat,
+) -> BatchSummary {
+    let total = jobs.len();
+    let queue = Arc::new(Mutex::new(
+        jobs.into_iter().enumerate().collect::<VecDeque<_>>(),
+    ));
+    let summary = Arc::new(Mutex::new(BatchSummary::default()));
+
+    let workers: Vec<_> = (0..options.jobs.clamp(1, total.max(1)))
+        .map(|_| {
+            let queue = queue.clone();
+            let summary = summary.clone();
+            let settings = settings.clone();
+            let existing = options.existing;
+            thread::spawn(move || loop {
+                let Some((index, job)) = lock(&queue).pop_front() else {
+                    break;
+                };
+                // A panic in a decoder or renderer fails its file, not the batch.
+                let report =
+                    panic::catch_unwind(AssertUnwindSafe(|| run_job(&job, existing, &settings)))
+                        .unwrap_or_else(|panic| {
+                            let reason = panic
+                                .downcast_ref::<&str>()
+                                .map(|s| s.to_string())
+                                .or_else(|| panic.downcast_ref::<String>().cloned())
+                                .unwrap_or_else(|| "unknown cause".to_string());
+                            FileReport::new(&job.input, &job.output, &settings)
+                                .failed(format!("rendering panicked: {}", reason))
+                        });
+
+                // Holding the lock keeps the output lines whole.
+                let mut summary = lock(&summary);
+                summary.add(&report);
+                match format {
+                    ReportFormat::Json => println!("{}", report.to_json()),
+                    ReportFormat::Text if report.status == FileStatus::Failed => {
+                        eprintln!("[{}/{}] {}", index + 1, total, report.to_text())
+                    }
+                    ReportFormat::Text => {
+                        println!("[{}/{}] {}", index + 1, total, report.to_text())
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        if worker.join().is_err() {
+            eprintln!("A batch worker thread panicked");
+        }
+    }
+
+    Arc::try_unwrap(summary)
+        .map(|m| m.into_inner().unwrap_or_else(|e| e.into_inner()))
+        .unwrap_or_default()
+}
+
+/// Locks `mutex` even if it is poisoned. A worker that panics while holding
+/// it (printing a report, say) leaves the queue and the summary intact.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn run_job(job: &BatchJob, existing: ExistingPolicy, settings: &AppSettings) -> FileReport {
+    if job.output.exists() {
+        match existing {
+            ExistingPolicy::Skip => {
+                let mut report = FileReport::new(&job.input, &job.output, settings);
+                report.status = FileStatus::Skipped;
+                return report;
+            }
+            ExistingPolicy::Fail => {
+                return FileReport::new(&job.input, &job.output, settings).failed(format!(
+                    "{} exists (use --skip-existing or --overwrite)",
+                    job.output.display()
+                ));
+            }
+            ExistingPolicy::Overwrite => {}
+        }
+    }
+
+    if let Some(parent) = job.output.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return FileReport::new(&job.input, &job.output, settings).failed(format!(
+                "cannot create '{}': {}",
+                parent.display(),
+                e
+            ));
+        }
+    }
+
+    render_file(&job.input, &job.output, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_wav, TempDir};
+
+    fn options(inputs: &[&Path], output_dir: &Path) -> BatchOptions {
+        BatchOptions {
+            inputs: inputs
+                .iter()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect(),
+            output_dir: output_dir.to_path_buf(),
+            jobs: 1,
+            extensions: DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+            existing: ExistingPolicy::Fail,
+        }
+    }
+
+    /// Outputs of `jobs` relative to `output_dir`.
+    fn outputs(jobs: &[BatchJob], output_dir: &Path) -> Vec<PathBuf> {
+        jobs.iter()
+            .map(|job| job.output.strip_prefix(output_dir).unwrap().to_path_buf())
+            .collect()
+    }
+
+    #[test]
+    fn directories_are_mirrored_below_the_output_dir() {
+        let dir = TempDir::new("batch-tree");
+        dir.touch("music/b.flac");
+        dir.touch("music/album/01.mp3");
+        dir.touch("music/album/02.FLAC");
+        dir.touch("music/album/cover.jpg");
+        dir.touch("music/notes.txt");
+        let out = dir.path().join("out");
+
+        let jobs = collect_jobs(&options(&[&dir.path().join("music")], &out)).unwrap();
+        assert_eq!(
+            outputs(&jobs, &out),
+            [
+                Path::new("album/01.mp3.png"),
+                Path::new("album/02.FLAC.png"),
+                Path::new("b.flac.png"),
+            ]
+        );
+        assert_eq!(jobs[0].input, dir.path().join("music/album/01.mp3"));
+
+        // A narrower extension list.
+        let mut only_flac = options(&[&dir.path().join("music")], &out);
+        only_flac.extensions = vec!["flac".to_string()];
+        let jobs = collect_jobs(&only_flac).unwrap();
+        assert_eq!(
+            outputs(&jobs, &out),
+            [Path::new("album/02.FLAC.png"), Path::new("b.flac.png")]
+        );
+    }
+
+    #[test]
+    fn globs_keep_the_path_below_their_fixed_part() {
+        let dir = TempDir::new("batch-glob");
+        dir.touch("music/a/01.flac");
+        dir.touch("music/b/01.flac");
+        dir.touch("music/b/01.txt");
+        let out = dir.path().join("out");
+
+        let pattern = dir.path().join("music/*/01.*");
+        assert_eq!(
+            glob_base(&pattern.to_string_lossy()),
+            dir.path().join("music")
+        );
+        let jobs = collect_jobs(&options(&[&pattern], &out)).unwrap();
+        assert_eq!(
+            outputs(&jobs, &out),
+            [Path::new("a/01.flac.png"), Path::new("b/01.flac.png")]
+        );
+
+        let nothing = dir.path().join("music/*.wav");
+        assert!(collect_jobs(&options(&[&nothing], &out)).is_err());
+    }
+
+    #[test]
+    fn files_given_directly_go_into_the_output_dir() {
+        let dir = TempDir::new("batch-files");
+        let file = dir.touch("music/a/01.flac");
+        let out = dir.path().join("out");
+
+        let jobs = collect_jobs(&options(&[&file], &out)).unwrap();
+        assert_eq!(outputs(&jobs, &out), [Path::new("01.flac.png")]);
+
+        let missing = dir.path().join("missing.flac");
+        assert!(collect_jobs(&options(&[&missing], &out)).is_err());
+    }
+
+    #[test]
+    fn duplicate_outputs_are_caught() {
+        let dir = TempDir::new("batch-duplicates");
+        let a = dir.touch("a/01.flac");
+        let b = dir.touch("b/01.flac");
+        let out = dir.path().join("out");
+
+        // The same file twice, once directly and once through a glob.
+        let pattern = dir.path().join("a/*.flac");
+        let jobs = collect_jobs(&options(&[&a, &pattern], &out)).unwrap();
+        assert_eq!(jobs.len(), 1);
+
+        // Two different files with the same name.
+        let error = collect_jobs(&options(&[&a, &b], &out)).unwrap_err();
+        assert!(error.contains("would both be written"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_end_the_walk() {
+        let dir = TempDir::new("batch-symlinks");
+        dir.touch("music/album/01.flac");
+        std::os::unix::fs::symlink(dir.path().join("music"), dir.path().join("music/album/up"))
+            .unwrap();
+        let out = dir.path().join("out");
+
+        let jobs = collect_jobs(&options(&[&dir.path().join("music")], &out)).unwrap();
+        assert_eq!(outputs(&jobs, &out), [Path::new("album/01.flac.png")]);
+    }
+
+    #[test]
+    fn existing_outputs_follow_the_policy() {
+        let dir = TempDir::new("batch-existing");
+        let input = write_wav("batch-existing", 8000, 1, &vec![0.1; 8000]);
+        let job = BatchJob {
+            input: input.path().to_path_buf(),
+            output: dir.touch("out/existing.wav.png"),
+        };
+        let settings = AppSettings::default();
+
+        let report = run_job(&job, ExistingPolicy::Skip, &settings);
+        assert_eq!(report.status, FileStatus::Skipped);
+
+        let report = run_job(&job, ExistingPolicy::Fail, &settings);
+        assert_eq!(report.status, FileStatus::Failed);
+        assert!(report.error.unwrap().contains("exists"));
+        assert_eq!(fs::metadata(&job.output).unwrap().len(), 0);
+
+        let report = run_job(&job, ExistingPolicy::Overwrite, &settings);
+        assert_eq!(report.status, FileStatus::Rendered, "{:?}", report.error);
+        assert!(fs::metadata(&job.output).unwrap().len() > 0);
+
+        // Without an existing file every policy renders.
+        let fresh = BatchJob {
+            input: job.input.clone(),
+            output: dir.path().join("out/new/fresh.wav.png"),
+        };
+        let report = run_job(&fresh, ExistingPolicy::Fail, &settings);
+        assert_eq!(report.status, FileStatus::Rendered, "{:?}", report.error);
+    }
+}
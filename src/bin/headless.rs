@@ -1,103 +1,90 @@
-use spek_rs::settings::AppSettings;
-use spek_rs::utils::save_color_image_as_png;
-use spek_rs::MyApp;
+use spek_rs::batch::{self, FileStatus, ReportFormat};
+use spek_rs::cli::{self, HeadlessMode};
 
 use std::env;
 use std::path::Path;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let bin = args.first().map(String::as_str).unwrap_or("headless");
 
-    if args.len() < 3 {
-        print_help(&args[0]);
+    if args.len() < 2 {
+        eprint!("{}", cli::usage(bin));
         std::process::exit(1);
     }
 
-    let input_path = args[1].clone();
-    let output_path = args[2].clone();
-
-    // -------------------------------------------------
-    // Default settings
-    // -------------------------------------------------
-    let mut settings = AppSettings::default();
+    let cli_args = match cli::parse_args(&args[1..]) {
+        Ok(Some(cli_args)) => cli_args,
+        Ok(None) => {
+            eprint!("{}", cli::usage(bin));
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n", e);
+            eprint!("{}", cli::usage(bin));
+            std::process::exit(1);
+        }
+    };
 
-    let mut width: Option<u32> = None;
-    let mut height: Option<u32> = None;
+    match cli_args.mode {
+        HeadlessMode::Single {
+            input_path,
+            compare_path,
+            output_path,
+        } => {
+            let report = match compare_path {
+                Some(compare_path) => batch::render_comparison(
+                    Path::new(&input_path),
+                    Path::new(&compare_path),
+                    Path::new(&output_path),
+                    &cli_args.settings,
+                ),
+                None => batch::render_file(
+                    Path::new(&input_path),
+                    Path::new(&output_path),
+                    &cli_args.settings,
+                ),
+            };
 
-    // -------------------------------------------------
-    // CLI flags
-    // -------------------------------------------------
-    let mut i = 3;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--width" => {
-                if i + 1 < args.len() {
-                    width = args[i + 1].parse::<u32>().ok();
-                    i += 1;
+            match cli_args.report {
+                ReportFormat::Json => println!("{}", report.to_json()),
+                ReportFormat::Text if report.status == FileStatus::Failed => {
+                    eprintln!("Error: {}", report.error.as_deref().unwrap_or_default());
                 }
-            }
-            "--height" => {
-                if i + 1 < args.len() {
-                    height = args[i + 1].parse::<u32>().ok();
-                    i += 1;
+                ReportFormat::Text => {
+                    println!("Saved PNG to {:?}", report.output);
+                    if let Some(analysis) = &report.analysis {
+                        println!("[ANALYSIS]");
+                        for (key, value) in analysis.to_key_values() {
+                            println!("{}={}", key, value);
+                        }
+                        println!("[/ANALYSIS]");
+                    }
                 }
             }
-            "--no-version" => {
-                // Wirkung kommt später in legend.rs
-                // CLI ist jetzt vorbereitet
-            }
-            "--help" | "-h" => {
-                print_help(&args[0]);
-                return;
-            }
-            unknown => {
-                eprintln!("Unknown option: {}", unknown);
-                print_help(&args[0]);
+            if report.status == FileStatus::Failed {
                 std::process::exit(1);
             }
         }
-        i += 1;
-    }
-
-    // -------------------------------------------------
-    // Apply resolution if provided
-    // -------------------------------------------------
-    if let (Some(w), Some(h)) = (width, height) {
-        settings.custom_resolution = true;
-        settings.resolution = [w, h];
-    }
-
-    // -------------------------------------------------
-    // Headless app
-    // -------------------------------------------------
-    let mut app = MyApp::new(None, Some(input_path.clone()), settings);
+        HeadlessMode::Batch(options) => {
+            let jobs = match batch::collect_jobs(&options) {
+                Ok(jobs) => jobs,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            eprintln!(
+                "Rendering {} file(s) with {} job(s)",
+                jobs.len(),
+                options.jobs
+            );
 
-    if let Some(color_image) = app.regenerate_spectrogram_headless() {
-        let path = Path::new(&output_path);
-        match save_color_image_as_png(&color_image, path) {
-            Ok(_) => println!("Saved PNG to {:?}", path),
-            Err(e) => {
-                eprintln!("Failed to save PNG: {}", e);
+            let summary = batch::run_batch(jobs, &options, &cli_args.settings, cli_args.report);
+            summary.print();
+            if !summary.failed.is_empty() {
                 std::process::exit(1);
             }
         }
-    } else {
-        eprintln!("Failed to generate spectrogram");
-        std::process::exit(1);
     }
 }
-
-fn print_help(bin: &str) {
-    eprintln!(
-        r#"Usage:
-  {bin} <input_audio> <output_png> [options]
-
-Options:
-  --width <px>        Set PNG width
-  --height <px>       Set PNG height
-  --no-version        Hide version text in legend (implemented next)
-  -h, --help          Show this help
-"#,
-        bin = bin
-    );
-}
@@ -0,0 +1,477 @@
+use crate::settings::{AppSettings, StereoView};
+use crate::utils::AudioInfo;
+use symphonia::core::audio::Channels;
+
+// ======================================================
+// Channel names
+// ======================================================
+
+/// Channels of ffmpeg's named layouts, as printed by `ffmpeg -layouts`.
+const FFMPEG_LAYOUTS: &[(&str, &str)] = &[
+    ("mono", "FC"),
+    ("stereo", "FL+FR"),
+    ("2.1", "FL+FR+LFE"),
+    ("3.0", "FL+FR+FC"),
+    ("3.0(back)", "FL+FR+BC"),
+    ("4.0", "FL+FR+FC+BC"),
+    ("quad", "FL+FR+BL+BR"),
+    ("quad(side)", "FL+FR+SL+SR"),
+    ("3.1", "FL+FR+FC+LFE"),
+    ("5.0", "FL+FR+FC+BL+BR"),
+    ("5.0(side)", "FL+FR+FC+SL+SR"),
+    ("4.1", "FL+FR+FC+LFE+BC"),
+    ("5.1", "FL+FR+FC+LFE+BL+BR"),
+    ("5.1(side)", "FL+FR+FC+LFE+SL+SR"),
+    ("6.0", "FL+FR+FC+BC+SL+SR"),
+    ("6.0(front)", "FL+FR+FLC+FRC+SL+SR"),
+    ("hexagonal", "FL+FR+FC+BL+BR+BC"),
+    ("6.1", "FL+FR+FC+LFE+BC+SL+SR"),
+    ("6.1(back)", "FL+FR+FC+LFE+BL+BR+BC"),
+    ("6.1(front)", "FL+FR+LFE+FLC+FRC+SL+SR"),
+    ("7.0", "FL+FR+FC+BL+BR+SL+SR"),
+    ("7.0(front)", "FL+FR+FC+FLC+FRC+SL+SR"),
+    ("7.1", "FL+FR+FC+LFE+BL+BR+SL+SR"),
+    ("7.1(wide)", "FL+FR+FC+LFE+BL+BR+FLC+FRC"),
+    ("7.1(wide-side)", "FL+FR+FC+LFE+FLC+FRC+SL+SR"),
+    ("octagonal", "FL+FR+FC+BL+BR+BC+SL+SR"),
+    ("downmix", "DL+DR"),
+];
+
+/// Short speaker name of one of ffmpeg's channel ids.
+fn ffmpeg_channel_name(id: &str) -> Option<&'static str> {
+    Some(match id {
+        "FL" => "L",
+        "FR" => "R",
+        "FC" => "C",
+        "LFE" => "LFE",
+        "BL" => "Lb",
+        "BR" => "Rb",
+        "FLC" => "Lc",
+        "FRC" => "Rc",
+        "BC" => "Cs",
+        "SL" => "Ls",
+        "SR" => "Rs",
+        "TC" => "Tc",
+        "TFL" => "Tfl",
+        "TFC" => "Tfc",
+        "TFR" => "Tfr",
+        "TBL" => "Tbl",
+        "TBC" => "Tbc",
+        "TBR" => "Tbr",
+        "DL" => "Ld",
+        "DR" => "Rd",
+        "WL" => "Lw",
+        "WR" => "Rw",
+        "LFE2" => "LFE2",
+        _ => return None,
+    })
+}
+
+/// Names of the channels of an ffprobe `channel_layout`, either a named
+/// layout ("5.1(side)") or channel ids ("FL+FR+LFE"). Empty when unknown.
+pub fn names_from_ffmpeg_layout(layout: &str, channels: u32) -> Vec<String> {
+    let ids = FFMPEG_LAYOUTS
+        .iter()
+        .find(|(name, _)| *name == layout)
+        .map_or(layout, |(_, ids)| ids);
+    let names: Option<Vec<&str>> = ids.split('+').map(ffmpeg_channel_name).collect();
+    finish_names(names.unwrap_or_default(), channels)
+}
+
+/// Names of the channels Symphonia reports, in their interleaved order.
+pub fn names_from_symphonia(layout: Channels) -> Vec<String> {
+    let names: Option<Vec<&str>> = layout
+        .iter()
+        .map(|channel| {
+            Some(match channel {
+                Channels::FRONT_LEFT => "L",
+                Channels::FRONT_RIGHT => "R",
+                Channels::FRONT_CENTRE => "C",
+                Channels::LFE1 => "LFE",
+                Channels::REAR_LEFT => "Lb",
+                Channels::REAR_RIGHT => "Rb",
+                Channels::FRONT_LEFT_CENTRE => "Lc",
+                Channels::FRONT_RIGHT_CENTRE => "Rc",
+                Channels::REAR_CENTRE => "Cs",
+                Channels::SIDE_LEFT => "Ls",
+                Channels::SIDE_RIGHT => "Rs",
+                Channels::TOP_CENTRE => "Tc",
+                Channels::TOP_FRONT_LEFT => "Tfl",
+                Channels::TOP_FRONT_CENTRE => "Tfc",
+                Channels::TOP_FRONT_RIGHT => "Tfr",
+                Channels::TOP_REAR_LEFT => "Tbl",
+                Channels::TOP_REAR_CENTRE => "Tbc",
+                Channels::TOP_REAR_RIGHT => "Tbr",
+                Channels::FRONT_LEFT_WIDE => "Lw",
+                Channels::FRONT_RIGHT_WIDE => "Rw",
+                Channels::LFE2 => "LFE2",
+                _ => return None,
+            })
+        })
+        .collect();
+    finish_names(names.unwrap_or_default(), layout.count() as u32)
+}
+
+/// Checks `names` against the channel count. The back pair of layouts
+/// without side channels (5.1, quad) is the surround pair, named so.
+fn finish_names(mut names: Vec<&str>, channels: u32) -> Vec<String> {
+    if names.len() != channels as usize {
+        return Vec::new();
+    }
+    if names.len() == 1 {
+        names = vec!["M"];
+    }
+    if !names.contains(&"Ls") && !names.contains(&"Rs") {
+        for name in &mut names {
+            *name = match *name {
+                "Lb" => "Ls",
+                "Rb" => "Rs",
+                other => other,
+            };
+        }
+    }
+    names.into_iter().map(String::from).collect()
+}
+
+// ======================================================
+// Channel selection
+// ======================================================
+
+/// One stacked spectrogram of a render: a weighted sum of the file's channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMix {
+    pub name: String,
+    /// Channel indices and the gains they are summed with.
+    pub gains: Vec<(usize, f32)>,
+}
+
+impl ChannelMix {
+    fn channel(name: String, index: usize) -> Self {
+        Self {
+            name,
+            gains: vec![(index, 1.0)],
+        }
+    }
+
+    /// The term of an ffmpeg `pan` output channel, "0.5*c0-0.5*c1".
+    fn ffmpeg_pan_term(&self) -> String {
+        let mut term = String::new();
+        for (n, &(index, gain)) in self.gains.iter().enumerate() {
+            if gain < 0.0 {
+                term.push('-');
+            } else if n > 0 {
+                term.push('+');
+            }
+            if gain.abs() != 1.0 {
+                term.push_str(&format!("{}*", gain.abs()));
+            }
+            term.push_str(&format!("c{}", index));
+        }
+        term
+    }
+}
+
+impl AudioInfo {
+    /// Name of channel `index`, its 1-based number when the layout is unknown.
+    pub fn channel_name(&self, index: usize) -> String {
+        self.channel_names
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| (index + 1).to_string())
+    }
+
+    /// Indices of the channels `settings.channels` selects in this file, in
+    /// file order. All of them when the selection is empty or matches none.
+    pub fn shown_channels(&self, settings: &AppSettings) -> Vec<usize> {
+        let count = self.channels.max(1) as usize;
+        let selected: Vec<usize> = (0..count)
+            .filter(|&index| {
+                settings.channels.iter().any(|entry| {
+                    entry.eq_ignore_ascii_case(&self.channel_name(index))
+                        || entry.parse::<usize>() == Ok(index + 1)
+                })
+            })
+            .collect();
+        if selected.is_empty() {
+            (0..count).collect()
+        } else {
+            selected
+        }
+    }
+
+    /// Indices of the left and right channels, the first two when the
+    /// layout does not name them. None for mono files.
+    pub fn stereo_pair(&self) -> Option<(usize, usize)> {
+        if self.channels < 2 {
+            return None;
+        }
+        let find = |name: &str| self.channel_names.iter().position(|n| n == name);
+        Some(match (find("L"), find("R")) {
+            (Some(left), Some(right)) => (left, right),
+            _ => (0, 1),
+        })
+    }
+
+    /// `settings.stereo_view` as it applies to this file, which has to have
+    /// a stereo pair for anything but `Channels`.
+    pub fn stereo_view(&self, settings: &AppSettings) -> StereoView {
+        match self.stereo_pair() {
+            Some(_) => settings.stereo_view,
+            None => StereoView::Channels,
+        }
+    }
+
+    /// The signals a render with `settings` analyses, in stacking order:
+    /// the shown channels, or the mid and side signals of the stereo view.
+    /// The stereo view ignores the channel selection.
+    pub fn channel_mixes(&self, settings: &AppSettings) -> Vec<ChannelMix> {
+        let Some((left, right)) = self.stereo_pair() else {
+            return vec![ChannelMix::channel(self.channel_name(0), 0)];
+        };
+        let mid = || ChannelMix {
+            name: "M".to_string(),
+            gains: vec![(left, 0.5), (right, 0.5)],
+        };
+        let side = || ChannelMix {
+            name: "S".to_string(),
+            gains: vec![(left, 0.5), (right, -0.5)],
+        };
+        match settings.stereo_view {
+            StereoView::Channels => self
+                .shown_channels(settings)
+                .into_iter()
+                .map(|index| ChannelMix::channel(self.channel_name(index), index))
+                .collect(),
+            StereoView::Mid => vec![mid()],
+            StereoView::Side => vec![side()],
+            StereoView::LeftRightSide => vec![
+                ChannelMix::channel(self.channel_name(left), left),
+                ChannelMix::channel(self.channel_name(right), right),
+                side(),
+            ],
+        }
+    }
+
+    /// Whether a render with `settings` analyses anything but all of this
+    /// file's channels as they are.
+    pub fn remaps_channels(&self, settings: &AppSettings) -> bool {
+        self.stereo_view(settings) != StereoView::Channels
+            || self.shown_channels(settings).len() < self.channels.max(1) as usize
+    }
+
+    /// Number of spectrograms stacked in a render with `settings`: one per
+    /// channel mix when they are split, otherwise one for their sum.
+    pub fn stacked_channels(&self, settings: &AppSettings) -> u32 {
+        match settings.splits_channels() {
+            true => self.channel_mixes(settings).len() as u32,
+            false => 1,
+        }
+    }
+
+    /// Names of the stacked spectrograms of a render with `settings`, empty
+    /// when the file's channels are mixed into one.
+    pub fn stacked_channel_names(&self, settings: &AppSettings) -> Vec<String> {
+        if self.stacked_channels(settings) < 2 && self.stereo_view(settings) == StereoView::Channels
+        {
+            return Vec::new();
+        }
+        self.channel_mixes(settings)
+            .into_iter()
+            .map(|mix| mix.name)
+            .collect()
+    }
+
+    /// ffmpeg `pan` filter producing the channel mixes, followed by a comma;
+    /// empty when the file's channels are used as they are.
+    pub fn ffmpeg_channel_filter(&self, settings: &AppSettings) -> String {
+        if !self.remaps_channels(settings) {
+            return String::new();
+        }
+        let mixes = self.channel_mixes(settings);
+        let outputs: Vec<String> = mixes
+            .iter()
+            .enumerate()
+            .map(|(output, mix)| format!("c{}={}", output, mix.ffmpeg_pan_term()))
+            .collect();
+        format!("pan={}c|{},", mixes.len(), outputs.join("|"))
+    }
+}
+
+/// Writes the `mixes` of interleaved `samples` with `channels` values per
+/// frame into `output`, interleaved the same way.
+pub fn mix_interleaved(
+    samples: &[f32],
+    channels: usize,
+    mixes: &[ChannelMix],
+    output: &mut Vec<f32>,
+) {
+    output.clear();
+    for frame in samples.chunks_exact(channels) {
+        output.extend(mixes.iter().map(|mix| {
+            mix.gains
+                .iter()
+                .map(|&(channel, gain)| frame[channel] * gain)
+                .sum::<f32>()
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(channel_names: &[&str]) -> AudioInfo {
+        AudioInfo {
+            duration: 1.0,
+            sample_rate: 48_000,
+            format: "wav".to_string(),
+            bits_per_sample: 16,
+            channels: channel_names.len() as u32,
+            channel_names: channel_names.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn selecting(channels: &[&str]) -> AppSettings {
+        AppSettings {
+            channels: channels.iter().map(|s| s.to_string()).collect(),
+            ..AppSettings::default()
+        }
+    }
+
+    #[test]
+    fn ffmpeg_layouts_are_named() {
+        // The back pair of 5.1 is its surround pair.
+        assert_eq!(
+            names_from_ffmpeg_layout("5.1", 6),
+            ["L", "R", "C", "LFE", "Ls", "Rs"]
+        );
+        assert_eq!(
+            names_from_ffmpeg_layout("5.1(side)", 6),
+            ["L", "R", "C", "LFE", "Ls", "Rs"]
+        );
+        // With side channels present the back pair keeps its name.
+        assert_eq!(
+            names_from_ffmpeg_layout("7.1", 8),
+            ["L", "R", "C", "LFE", "Lb", "Rb", "Ls", "Rs"]
+        );
+        assert_eq!(names_from_ffmpeg_layout("FL+FR+LFE", 3), ["L", "R", "LFE"]);
+        assert_eq!(names_from_ffmpeg_layout("mono", 1), ["M"]);
+    }
+
+    #[test]
+    fn unknown_or_mismatched_layouts_have_no_names() {
+        assert!(names_from_ffmpeg_layout("unknown", 2).is_empty());
+        assert!(names_from_ffmpeg_layout("FL+FR+XYZ", 3).is_empty());
+        assert!(names_from_ffmpeg_layout("", 2).is_empty());
+        // ffprobe saying 5.1 for a stereo stream.
+        assert!(names_from_ffmpeg_layout("5.1", 2).is_empty());
+        assert!(finish_names(vec!["L", "R"], 3).is_empty());
+    }
+
+    #[test]
+    fn symphonia_layouts_are_named() {
+        let layout = Channels::FRONT_LEFT
+            | Channels::FRONT_RIGHT
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT;
+        assert_eq!(
+            names_from_symphonia(layout),
+            ["L", "R", "C", "LFE", "Ls", "Rs"]
+        );
+        assert_eq!(names_from_symphonia(Channels::FRONT_LEFT), ["M"]);
+    }
+
+    #[test]
+    fn channels_are_selected_by_name_or_number() {
+        let surround = info(&["L", "R", "C", "LFE", "Ls", "Rs"]);
+        assert_eq!(surround.shown_channels(&selecting(&["lfe", "L"])), [0, 3]);
+        assert_eq!(surround.shown_channels(&selecting(&["5", "1"])), [0, 4]);
+        assert_eq!(surround.shown_channels(&selecting(&["C", "6"])), [2, 5]);
+        // Nothing selected, or nothing that matches, shows every channel.
+        assert_eq!(surround.shown_channels(&selecting(&[])), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(
+            surround.shown_channels(&selecting(&["Tfl", "9"])),
+            [0, 1, 2, 3, 4, 5]
+        );
+
+        // Without a layout the channels only have numbers.
+        let unnamed = AudioInfo {
+            channels: 4,
+            ..info(&[])
+        };
+        assert_eq!(unnamed.channel_name(2), "3");
+        assert_eq!(unnamed.shown_channels(&selecting(&["L", "3"])), [2]);
+    }
+    fn viewing(stereo_view: StereoView) -> AppSettings {
+        AppSettings {
+            stereo_view,
+            ..AppSettings::default()
+        }
+    }
+
+    #[test]
+    fn stereo_views_become_pan_filters() {
+        let stereo = info(&["L", "R"]);
+        assert_eq!(
+            stereo.ffmpeg_channel_filter(&viewing(StereoView::Mid)),
+            "pan=1c|c0=0.5*c0+0.5*c1,"
+        );
+        assert_eq!(
+            stereo.ffmpeg_channel_filter(&viewing(StereoView::Side)),
+            "pan=1c|c0=0.5*c0-0.5*c1,"
+        );
+        assert_eq!(
+            stereo.ffmpeg_channel_filter(&viewing(StereoView::LeftRightSide)),
+            "pan=3c|c0=c0|c1=c1|c2=0.5*c0-0.5*c1,"
+        );
+        assert_eq!(
+            stereo.ffmpeg_channel_filter(&viewing(StereoView::Channels)),
+            ""
+        );
+
+        // The pair is found by name wherever it sits in the file.
+        let reversed = info(&["R", "L"]);
+        assert_eq!(
+            reversed.ffmpeg_channel_filter(&viewing(StereoView::Side)),
+            "pan=1c|c0=0.5*c1-0.5*c0,"
+        );
+        // A channel selection without a stereo view picks channels.
+        let surround = info(&["L", "R", "C", "LFE", "Ls", "Rs"]);
+        assert_eq!(
+            surround.ffmpeg_channel_filter(&selecting(&["LFE", "C"])),
+            "pan=2c|c0=c2|c1=c3,"
+        );
+    }
+
+    #[test]
+    fn pan_terms_show_signs_and_gains() {
+        let mix = |gains: Vec<(usize, f32)>| ChannelMix {
+            name: String::new(),
+            gains,
+        };
+        assert_eq!(mix(vec![(3, 1.0)]).ffmpeg_pan_term(), "c3");
+        assert_eq!(mix(vec![(0, -1.0), (1, 1.0)]).ffmpeg_pan_term(), "-c0+c1");
+        assert_eq!(
+            mix(vec![(0, 0.25), (2, -0.75)]).ffmpeg_pan_term(),
+            "0.25*c0-0.75*c2"
+        );
+    }
+
+    #[test]
+    fn interleaved_frames_are_mixed() {
+        let mixes = info(&["L", "R"]).channel_mixes(&viewing(StereoView::LeftRightSide));
+        let mut output = Vec::new();
+        mix_interleaved(&[1.0, 0.5, -0.2, 0.4], 2, &mixes, &mut output);
+        let expected = [1.0, 0.5, 0.25, -0.2, 0.4, -0.3];
+        assert_eq!(output.len(), expected.len());
+        for (got, want) in output.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-6, "{:?}", output);
+        }
+
+        let mid = info(&["L", "R"]).channel_mixes(&viewing(StereoView::Mid));
+        mix_interleaved(&[1.0, 0.5, -0.2, 0.4], 2, &mid, &mut output);
+        assert_eq!(output, [0.75, 0.1]);
+    }
+}
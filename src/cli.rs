@@ -0,0 +1,428 @@
+use crate::batch::{self, BatchOptions, ExistingPolicy, ReportFormat};
+use crate::settings::{AppSettings, CompareLayout};
+use crate::utils;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Parsed command line of the headless renderer.
+#[derive(Debug, Clone)]
+pub struct HeadlessArgs {
+    pub mode: HeadlessMode,
+    pub settings: AppSettings,
+    pub report: ReportFormat,
+}
+
+#[derive(Debug, Clone)]
+pub enum HeadlessMode {
+    /// One input file rendered to one PNG, or two compared in one PNG
+    /// when `compare_path` is set.
+    Single {
+        input_path: String,
+        compare_path: Option<String>,
+        output_path: String,
+    },
+    /// Many inputs rendered into a directory, selected by `--output-dir`.
+    Batch(BatchOptions),
+}
+
+pub fn usage(bin: &str) -> String {
+    format!(
+        r#"Usage:
+  {bin} <input_audio> <output_png> [options]
+  {bin} <input_audio> <output_png> --compare <other_audio> [options]
+  {bin} --output-dir <dir> <input>... [options]
+
+Spectrogram:
+  --palette <name>        intensity, fire, ice, rainbow (default: intensity)
+  --win-func <name>       Window function (default: hann): rect, bartlett, hann,
+                          hamming, blackman, welch, flattop, bharris, bnuttall,
+                          bhann, sine, nuttall, lanczos, gauss, tukey, dolph,
+                          cauchy, parzen, poisson, bohman, kaiser
+  --scale <name>          linear, log, fourthrt, fifthrt (default: log)
+  --gain <n>              Amplitude gain, 0..128 (default: 1)
+  --saturation <n>        Colour saturation, -10..10 (default: 1)
+  --db-floor <dB>         Level at the bottom of the palette (default: -120)
+  --db-ceiling <dB>       Level at the top of the palette (default: 0)
+  --auto-range            Fit floor and ceiling to the file's noise floor and peak
+                          (uses the native engine)
+  --limit <dB>            ffmpeg-style alias of --db-ceiling
+  --drange <dB>           ffmpeg-style dynamic range, sets --db-floor to limit - drange
+  --freq-scale <name>     Frequency axis: linear, log, mel, bark, erb (default: linear)
+  --fscale <name>         ffmpeg-style alias of --freq-scale (lin, log)
+  --freq-min <hz>         Lowest frequency shown (default: 0)
+  --freq-max <hz>         Highest frequency shown (default: Nyquist)
+  --view <name>           stft, cqt (default: stft). cqt is a constant-Q view with
+                          a note axis and always uses the native engine
+  --cqt-bins <n>          Constant-Q bins per octave, 6..96 (default: 24)
+  --cqt-min <hz>          Lowest constant-Q frequency (default: 32.7, C1)
+  --cqt-max <hz>          Highest constant-Q frequency (default: 4186, C8)
+  --split-channels        Draw each channel separately
+  --channels <list>       Channels to draw, by name or 1-based number, e.g.
+                          L,R,LFE or 1,2,4 (default: all)
+  --stereo <view>         channels, mid, side, lr-side (default: channels). mid
+                          and side draw (L+R)/2 and (L-R)/2, lr-side stacks the
+                          side signal under L and R
+  --start <time>          Render from this point on, in seconds or [h:]m:s
+                          (e.g. 90, 1:30, 1:02:30.5). The time axis shows
+                          timestamps of the whole file
+  --end <time>            Render up to this point (default: end of file)
+
+Comparison:
+  --compare <file>        Render <file> as B next to the input (A) with the same
+                          settings, size and levels, into one PNG. B follows A's
+                          time range; the analysis is skipped
+  --compare-layout <name> stacked (one legend for both), side-by-side, or
+                          difference: B minus A in dB after lining B up with A
+                          by cross-correlation (native engine) (default: stacked)
+  --diff-range <dB>       Difference at the ends of the difference palette,
+                          1..120 (default: 20)
+
+Engine:
+  --backend <name>        ffmpeg, native (default: native)
+  --fft-size <n>          FFT length for the native backend, 256..16384, power of two
+  --overlap <n>           Window overlap for the native backend and live mode, 0..0.95
+
+Output:
+  --width <px>            Spectrogram width (default: 500)
+  --height <px>           Spectrogram height (default: 320)
+  --no-legend             Save the bare spectrogram without legend
+  --no-version            Hide version text in legend
+  --no-analysis           Skip the transcode, bit-depth and stereo checks
+  --correlation-strip     Draw the left/right correlation over time under the
+                          time axis (stereo files)
+  --report <format>       text, json (default: text). json prints one object
+                          per file with audio info, settings, size, timing,
+                          analysis and errors; log messages go to stderr
+
+Batch (with --output-dir):
+  --output-dir <dir>      Render every input into <dir>, mirroring the input tree.
+                          Inputs may be files, directories (searched recursively)
+                          or quoted glob patterns such as "music/**/*.flac"
+  --jobs <n>              Files rendered in parallel (default: number of CPUs)
+  --extensions <list>     Comma-separated extensions picked up from directories
+                          and patterns (default: flac, wav, mp3, ogg, opus, m4a, ...)
+  --skip-existing         Leave PNGs that already exist alone
+  --overwrite             Replace PNGs that already exist
+                          (default: report them as failed)
+
+  --config <file>         Start from the settings in a spek-rs config.toml
+  -h, --help              Show this help
+
+Values may also be given as --option=value.
+"#,
+        bin = bin
+    )
+}
+
+/// Parses the arguments after the program name.
+///
+/// Returns `Ok(None)` when help was requested.
+pub fn parse_args(args: &[String]) -> Result<Option<HeadlessArgs>, String> {
+    let mut settings = AppSettings::default();
+    let mut positional = Vec::new();
+    let mut width: Option<u32> = None;
+    let mut height: Option<u32> = None;
+
+    let mut output_dir: Option<PathBuf> = None;
+    let mut jobs = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut extensions: Vec<String> = batch::DEFAULT_EXTENSIONS
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    let mut existing = ExistingPolicy::Fail;
+    let mut report = ReportFormat::Text;
+    let mut drange: Option<f32> = None;
+    let mut compare_path: Option<String> = None;
+
+    // --config is applied first so the other options can override it.
+    if let Some(path) = find_config(args)? {
+        settings = AppSettings::load_from(Path::new(&path)).map_err(|e| e.to_string())?;
+    }
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" {
+            return Ok(None);
+        }
+        if !arg.starts_with("--") {
+            if arg.starts_with('-') && arg.len() > 1 {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            positional.push(arg.clone());
+            continue;
+        }
+
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let mut value = || -> Result<String, String> {
+            inline_value
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+
+        match name {
+            "--palette" => settings.color_scheme = value()?.parse()?,
+            "--win-func" => settings.win_func = value()?.parse()?,
+            "--scale" => settings.scale = value()?.parse()?,
+            "--gain" => settings.gain = parse_number(name, &value()?, 0.0, 128.0)?,
+            "--saturation" => settings.saturation = parse_number(name, &value()?, -10.0, 10.0)?,
+            "--db-floor" => settings.db_floor = parse_number(name, &value()?, -200.0, 20.0)?,
+            "--db-ceiling" => settings.db_ceiling = parse_number(name, &value()?, -200.0, 20.0)?,
+            "--auto-range" => settings.auto_db_range = true,
+            "--limit" => settings.db_ceiling = parse_number(name, &value()?, -200.0, 20.0)?,
+            "--drange" => drange = Some(parse_number(name, &value()?, 10.0, 200.0)?),
+            "--freq-scale" | "--fscale" => settings.frequency_scale = value()?.parse()?,
+            "--freq-min" => settings.freq_min_hz = parse_number(name, &value()?, 0.0, 96000.0)?,
+            "--freq-max" => settings.freq_max_hz = parse_number(name, &value()?, 0.0, 96000.0)?,
+            "--view" => settings.view = value()?.parse()?,
+            "--cqt-bins" => settings.cqt_bins_per_octave = parse_number(name, &value()?, 6, 96)?,
+            "--cqt-min" => settings.cqt_min_hz = parse_number(name, &value()?, 8.0, 24000.0)?,
+            "--cqt-max" => settings.cqt_max_hz = parse_number(name, &value()?, 16.0, 96000.0)?,
+            "--split-channels" => settings.split_channels = true,
+            "--channels" => {
+                settings.channels = value()?
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+                if settings.channels.is_empty() {
+                    return Err(format!("{} needs at least one channel", name));
+                }
+            }
+            "--stereo" => settings.stereo_view = value()?.parse()?,
+            "--start" => settings.start_time = parse_time(name, &value()?)?,
+            "--end" => settings.end_time = parse_time(name, &value()?)?,
+            "--compare" => compare_path = Some(value()?),
+            "--compare-layout" => {
+                settings.compare_layout = value()?.parse()?;
+                if settings.compare_layout == CompareLayout::Flip {
+                    return Err(format!(
+                        "{} flip needs the viewer, use stacked, side-by-side or difference",
+                        name
+                    ));
+                }
+            }
+            "--diff-range" => {
+                settings.difference_range = parse_number(name, &value()?, 1.0, 120.0)?
+            }
+            "--backend" => settings.backend = value()?.parse()?,
+            "--fft-size" => {
+                let fft_size: u32 = parse_number(name, &value()?, 256, 16384)?;
+                if !fft_size.is_power_of_two() {
+                    return Err(format!("{} must be a power of two, got {}", name, fft_size));
+                }
+                settings.fft_size = fft_size;
+            }
+            "--overlap" => settings.overlap = parse_number(name, &value()?, 0.0, 0.95)?,
+            "--width" => width = Some(parse_number(name, &value()?, 1, 16384)?),
+            "--height" => height = Some(parse_number(name, &value()?, 1, 16384)?),
+            "--no-legend" => settings.legend = false,
+            "--no-version" => settings.show_version_in_legend = false,
+            "--no-analysis" => settings.analyze_audio = false,
+            "--correlation-strip" => settings.correlation_strip = true,
+            "--report" => report = value()?.parse()?,
+            "--output-dir" => output_dir = Some(PathBuf::from(value()?)),
+            "--jobs" => jobs = parse_number(name, &value()?, 1, 256)?,
+            "--extensions" => {
+                extensions = value()?
+                    .split(',')
+                    .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+                    .filter(|e| !e.is_empty())
+                    .collect();
+                if extensions.is_empty() {
+                    return Err(format!("{} needs at least one extension", name));
+                }
+            }
+            "--skip-existing" => existing = ExistingPolicy::Skip,
+            "--overwrite" => existing = ExistingPolicy::Overwrite,
+            "--config" => {
+                value()?;
+            }
+            "--help" => return Ok(None),
+            unknown => return Err(format!("unknown option '{}'", unknown)),
+        }
+    }
+
+    // Relative to the ceiling, wherever it appears on the command line.
+    if let Some(drange) = drange {
+        settings.db_floor = settings.db_ceiling - drange;
+    }
+    if settings.db_floor >= settings.db_ceiling {
+        return Err(format!(
+            "--db-floor must be below --db-ceiling, got {} and {} dB",
+            settings.db_floor, settings.db_ceiling
+        ));
+    }
+    if settings.cqt_min_hz * 2.0 > settings.cqt_max_hz {
+        return Err(format!(
+            "--cqt-max must be at least an octave above --cqt-min, got {} and {} Hz",
+            settings.cqt_min_hz, settings.cqt_max_hz
+        ));
+    }
+
+    if settings.freq_max_hz > 0.0 && settings.freq_min_hz >= settings.freq_max_hz {
+        return Err(format!(
+            "--freq-min must be below --freq-max, got {} and {} Hz",
+            settings.freq_min_hz, settings.freq_max_hz
+        ));
+    }
+
+    if settings.end_time > 0.0 && settings.start_time >= settings.end_time {
+        return Err(format!(
+            "--start must be before --end, got {} and {} s",
+            settings.start_time, settings.end_time
+        ));
+    }
+
+    // A single dimension keeps the other one from the config (or the default).
+    if width.is_some() || height.is_some() {
+        let [default_width, default_height] = if settings.custom_resolution {
+            settings.resolution
+        } else {
+            AppSettings::default().resolution
+        };
+        settings.png_width = width.unwrap_or(default_width);
+        settings.png_height = height.unwrap_or(default_height);
+    }
+
+    let mode = match output_dir {
+        Some(output_dir) => {
+            if positional.is_empty() {
+                return Err("--output-dir needs at least one input".to_string());
+            }
+            if compare_path.is_some() {
+                return Err("--compare renders a single PNG, not --output-dir".to_string());
+            }
+            HeadlessMode::Batch(BatchOptions {
+                inputs: positional,
+                output_dir,
+                jobs,
+                extensions,
+                existing,
+            })
+        }
+        None => {
+            let [input_path, output_path] = <[String; 2]>::try_from(positional).map_err(|p| {
+                format!(
+                    "expected <input_audio> <output_png>, got {} positional argument(s)",
+                    p.len()
+                )
+            })?;
+            HeadlessMode::Single {
+                input_path,
+                compare_path,
+                output_path,
+            }
+        }
+    };
+
+    Ok(Some(HeadlessArgs {
+        mode,
+        settings,
+        report,
+    }))
+}
+
+fn find_config(args: &[String]) -> Result<Option<String>, String> {
+    for (i, arg) in args.iter().enumerate() {
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(Some(path.to_string()));
+        }
+        if arg == "--config" {
+            return args
+                .get(i + 1)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| "--config needs a value".to_string());
+        }
+    }
+    Ok(None)
+}
+
+fn parse_time(name: &str, value: &str) -> Result<f64, String> {
+    utils::parse_time(value).ok_or_else(|| {
+        format!(
+            "{} expects seconds or [h:]m:s, e.g. 90 or 1:30, got '{}'",
+            name, value
+        )
+    })
+}
+
+fn parse_number<T>(name: &str, value: &str, min: T, max: T) -> Result<T, String>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display + Copy,
+{
+    let number: T = value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got '{}'", name, value))?;
+    // `contains` also rejects NaN.
+    if !(min..=max).contains(&number) {
+        return Err(format!(
+            "{} must be between {} and {}, got {}",
+            name, min, max, value
+        ));
+    }
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_accept_seconds_and_clock_notation() {
+        assert_eq!(parse_time("--start", "90"), Ok(90.0));
+        assert_eq!(parse_time("--start", "1.5"), Ok(1.5));
+        assert_eq!(parse_time("--start", "1:30"), Ok(90.0));
+        assert_eq!(parse_time("--start", " 0:05 "), Ok(5.0));
+        assert_eq!(parse_time("--end", "1:02:03.5"), Ok(3723.5));
+    }
+
+    #[test]
+    fn malformed_times_are_rejected() {
+        for value in ["", "abc", "-5", "1:", "1:2:3:4", "nan", "inf", "0:-1"] {
+            assert!(parse_time("--start", value).is_err(), "'{value}'");
+        }
+        assert_eq!(
+            parse_time("--end", "soon"),
+            Err("--end expects seconds or [h:]m:s, e.g. 90 or 1:30, got 'soon'".to_string())
+        );
+    }
+
+    #[test]
+    fn numbers_are_checked_against_inclusive_bounds() {
+        assert_eq!(parse_number("--gain", "2.5", 0.0, 128.0), Ok(2.5));
+        assert_eq!(parse_number("--gain", "0", 0.0, 128.0), Ok(0.0));
+        assert_eq!(parse_number("--gain", "128", 0.0, 128.0), Ok(128.0));
+        assert_eq!(
+            parse_number("--gain", "300", 0.0, 128.0),
+            Err("--gain must be between 0 and 128, got 300".to_string())
+        );
+        assert_eq!(parse_number("--cqt-bins", "12", 6, 96), Ok(12));
+    }
+
+    #[test]
+    fn non_numbers_are_rejected() {
+        assert!(parse_number("--gain", "NaN", 0.0, 128.0).is_err());
+        assert!(parse_number("--cqt-bins", "7.5", 6, 96).is_err());
+        assert_eq!(
+            parse_number("--saturation", "lots", -10.0, 10.0),
+            Err("--saturation expects a number, got 'lots'".to_string())
+        );
+    }
+
+    #[test]
+    fn parsed_times_reach_the_settings() {
+        let args: Vec<String> = ["in.flac", "out.png", "--start", "1:30", "--end=2:00"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let parsed = parse_args(&args).unwrap().unwrap();
+        assert_eq!(parsed.settings.start_time, 90.0);
+        assert_eq!(parsed.settings.end_time, 120.0);
+        assert!(parse_args(&["in.flac".into(), "--start".into(), "x".into()]).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::settings::{AppSettings, SpectogramWinFunc};
+use crate::spectrogram;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f32::consts::PI;
+
+/// Kernel coefficients below this fraction of a kernel's peak are dropped.
+const KERNEL_THRESHOLD: f32 = 1e-3;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// ======================================================
+// Parameters
+// ======================================================
+
+/// Bin layout of the constant-Q view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConstantQParams {
+    pub bins_per_octave: u32,
+    /// Centre frequency of the lowest bin.
+    pub min_hz: f32,
+    /// Upper limit for the centre of the highest bin.
+    pub max_hz: f32,
+}
+
+impl ConstantQParams {
+    pub fn from_settings(settings: &AppSettings) -> Self {
+        Self {
+            bins_per_octave: settings.cqt_bins_per_octave,
+            min_hz: settings.cqt_min_hz,
+            max_hz: settings.cqt_max_hz,
+        }
+    }
+
+    /// Ratio of centre frequency to bandwidth, the same for every bin.
+    pub fn q(&self) -> f32 {
+        1.0 / (2f32.powf(1.0 / self.bins_per_octave.max(1) as f32) - 1.0)
+    }
+
+    /// Centre frequencies of the bins at `sample_rate`, lowest first.
+    ///
+    /// The range is clamped so the highest bin stays below the Nyquist
+    /// frequency; there are always at least two bins.
+    pub fn frequencies(&self, sample_rate: u32) -> Vec<f32> {
+        let bins_per_octave = self.bins_per_octave.max(1) as f32;
+        let nyquist = sample_rate as f32 / 2.0;
+        let max_hz = self.max_hz.min(nyquist / (1.0 + 1.0 / self.q()));
+        let min_hz = self.min_hz.clamp(1.0, max_hz / 2.0);
+        let count = (bins_per_octave * (max_hz / min_hz).log2() + 1e-3).floor() as usize + 1;
+        (0..count.max(2))
+            .map(|k| min_hz * 2f32.powf(k as f32 / bins_per_octave))
+            .collect()
+    }
+}
+
+// ======================================================
+// Spectral kernel
+// ======================================================
+
+/// Constant-Q transform evaluated on the FFT of one frame (Brown & Puckette, 1992).
+///
+/// Every bin has its own window, `q` periods of its centre frequency long, and
+/// is computed as the inner product of the frame's spectrum with the sparse
+/// spectrum of that window.
+pub struct ConstantQKernel {
+    /// Length of the frames, enough for the longest (lowest) window.
+    pub fft_size: usize,
+    /// Per bin: the first FFT bin used and the coefficients from there on.
+    bins: Vec<(usize, Vec<Complex<f32>>)>,
+}
+
+impl ConstantQKernel {
+    pub fn new(params: ConstantQParams, win_func: SpectogramWinFunc, sample_rate: u32) -> Self {
+        let q = params.q();
+        let frequencies = params.frequencies(sample_rate);
+        let window_length = |hz: f32| ((q * sample_rate as f32 / hz).ceil() as usize).max(2);
+        let fft_size = window_length(frequencies[0]).next_power_of_two();
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+
+        let mut buffer = vec![Complex::new(0.0, 0.0); fft_size];
+        let bins = frequencies
+            .iter()
+            .map(|&hz| {
+                let length = window_length(hz).min(fft_size);
+                let window = spectrogram::window(win_func, length);
+                // Normalised so a full-scale sine reads 0 dBFS, like the STFT.
+                let window_sum: f32 = window.iter().sum();
+                let omega = 2.0 * PI * hz / sample_rate as f32;
+
+                // Centred in the frame, so every bin is aligned with the frame centre.
+                buffer.fill(Complex::new(0.0, 0.0));
+                let start = (fft_size - length) / 2;
+                for (n, w) in window.iter().enumerate() {
+                    let phase = omega * (n as f32 - length as f32 / 2.0);
+                    buffer[start + n] = Complex::from_polar(w / window_sum, phase);
+                }
+                fft.process(&mut buffer);
+
+                // <x, k> = <X, K> / N, so keep conj(K) / N around its peak.
+                let peak = buffer.iter().fold(0.0f32, |acc, v| acc.max(v.norm()));
+                let threshold = peak * KERNEL_THRESHOLD;
+                let first = buffer.iter().position(|v| v.norm() >= threshold);
+                let last = buffer.iter().rposition(|v| v.norm() >= threshold);
+                match (first, last) {
+                    (Some(first), Some(last)) => {
+                        let coefficients = buffer[first..=last]
+                            .iter()
+                            .map(|v| v.conj() / fft_size as f32)
+                            .collect();
+                        (first, coefficients)
+                    }
+                    _ => (0, Vec::new()),
+                }
+            })
+            .collect();
+
+        Self { fft_size, bins }
+    }
+
+    pub fn bin_count(&self) -> usize {
+        self.bins.len()
+    }
+
+    /// Power of every bin of a frame, given the frame's FFT.
+    pub fn powers(&self, spectrum: &[Complex<f32>], powers: &mut [f32]) {
+        for (power, (first, coefficients)) in powers.iter_mut().zip(&self.bins) {
+            let value: Complex<f32> = spectrum[*first..]
+                .iter()
+                .zip(coefficients)
+                .map(|(x, k)| x * k)
+                .sum();
+            // A sine of amplitude A gives |value| = A / 2.
+            *power = 4.0 * value.norm_sqr();
+        }
+    }
+}
+
+// ======================================================
+// Notes
+// ======================================================
+
+/// Frequency of a MIDI note number, A4 (69) being 440 Hz.
+pub fn note_frequency(note: i32) -> f32 {
+    440.0 * 2f32.powf((note - 69) as f32 / 12.0)
+}
+
+/// Nearest MIDI note number of `hz`.
+pub fn note_number(hz: f32) -> i32 {
+    (69.0 + 12.0 * (hz.max(1.0) / 440.0).log2()).round() as i32
+}
+
+/// Scientific pitch name of the note nearest to `hz`, e.g. "A4" or "C#2".
+pub fn note_name(hz: f32) -> String {
+    let note = note_number(hz);
+    let octave = note.div_euclid(12) - 1;
+    format!("{}{}", NOTE_NAMES[note.rem_euclid(12) as usize], octave)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(bins_per_octave: u32, min_hz: f32, max_hz: f32) -> ConstantQParams {
+        ConstantQParams {
+            bins_per_octave,
+            min_hz,
+            max_hz,
+        }
+    }
+
+    #[test]
+    fn full_scale_a4_reads_zero_dbfs() {
+        let sample_rate = 48_000;
+        // A3 to A5, so 440 Hz is bin 24.
+        let kernel = ConstantQKernel::new(
+            params(24, 220.0, 880.0),
+            SpectogramWinFunc::Hann,
+            sample_rate,
+        );
+        let mut spectrum: Vec<Complex<f32>> = (0..kernel.fft_size)
+            .map(|n| {
+                let t = n as f32 / sample_rate as f32;
+                Complex::new((2.0 * PI * 440.0 * t).sin(), 0.0)
+            })
+            .collect();
+        FftPlanner::new()
+            .plan_fft_forward(kernel.fft_size)
+            .process(&mut spectrum);
+
+        let mut powers = vec![0.0; kernel.bin_count()];
+        kernel.powers(&spectrum, &mut powers);
+        let db: Vec<f32> = powers.iter().map(|p| 10.0 * p.log10()).collect();
+        assert!(db[24].abs() < 0.5, "440 Hz bin at {:.2} dBFS", db[24]);
+        // A semitone away is well down.
+        assert!(db[22] < -6.0 && db[26] < -6.0, "{:?}", &db[20..29]);
+    }
+
+    #[test]
+    fn frequencies_stay_below_nyquist() {
+        for sample_rate in [8000, 22_050, 44_100, 96_000] {
+            for bins_per_octave in [12, 24, 48] {
+                let params = params(bins_per_octave, 32.7, 20_000.0);
+                let frequencies = params.frequencies(sample_rate);
+                let nyquist = sample_rate as f32 / 2.0;
+                let top = frequencies[frequencies.len() - 1];
+                // The whole band of the highest bin fits.
+                assert!(top * (1.0 + 1.0 / params.q()) <= nyquist * 1.0001);
+                assert!(frequencies.windows(2).all(|pair| pair[0] < pair[1]));
+                assert!((frequencies[0] - 32.7).abs() < 1e-3);
+            }
+        }
+
+        // A range past Nyquist is moved below it.
+        let frequencies = params(24, 10_000.0, 20_000.0).frequencies(8000);
+        assert!(frequencies.len() >= 2);
+        assert!(
+            frequencies.iter().all(|&hz| hz < 4000.0),
+            "{:?}",
+            frequencies
+        );
+    }
+
+    #[test]
+    fn notes_are_named() {
+        assert_eq!(note_name(440.0), "A4");
+        assert_eq!(note_name(261.63), "C4");
+        assert_eq!(note_name(32.703), "C1");
+        assert_eq!(note_name(69.3), "C#2");
+        // Nearest note, a quarter tone either way.
+        assert_eq!(note_name(452.0), "A4");
+        assert_eq!(note_name(428.0), "A4");
+        assert_eq!(note_number(440.0), 69);
+        assert!((note_frequency(60) - 261.63).abs() < 0.01);
+    }
+}
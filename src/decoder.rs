@@ -0,0 +1,590 @@
+use crate::channels;
+use crate::error::{Error, Result};
+use crate::utils::{self, AudioInfo};
+use ffmpeg_sidecar::child::FfmpegChild;
+use ffmpeg_sidecar::command::{ffmpeg_is_installed, FfmpegCommand};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::ChildStdout;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{
+    CodecParameters, CodecRegistry, Decoder, DecoderOptions, CODEC_TYPE_NULL,
+};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+/// Source of decoded audio as interleaved 32-bit float PCM.
+pub trait AudioDecoder {
+    fn info(&self) -> &AudioInfo;
+
+    /// Replaces the contents of `buffer` with the next block of interleaved samples.
+    ///
+    /// Returns `Ok(false)` once the stream is exhausted.
+    fn next_block(&mut self, buffer: &mut Vec<f32>) -> Result<bool>;
+
+    /// Moves to `seconds` from the start, so the next block starts there.
+    fn seek(&mut self, seconds: f64) -> Result<()>;
+}
+
+/// Opens `input_path` with the in-process decoder, falling back to ffmpeg for
+/// formats Symphonia cannot handle (e.g. WMA).
+pub fn open(input_path: &str) -> Result<Box<dyn AudioDecoder>> {
+    let reason = match SymphoniaDecoder::open(input_path) {
+        Ok(decoder) => return Ok(Box::new(decoder)),
+        Err(Error::UnsupportedFormat { reason, .. }) => reason,
+        Err(e) => return Err(e),
+    };
+
+    if utils::ffmpeg_available() {
+        eprintln!("Falling back to ffmpeg for decoding: {}", input_path);
+        return FfmpegDecoder::open(input_path).map(|d| Box::new(d) as Box<dyn AudioDecoder>);
+    }
+
+    Err(Error::UnsupportedFormat {
+        path: input_path.to_string(),
+        reason: format!("{}, and ffmpeg is not installed", reason),
+    })
+}
+
+fn unsupported(input_path: &str, reason: impl Into<String>) -> Error {
+    Error::UnsupportedFormat {
+        path: input_path.to_string(),
+        reason: reason.into(),
+    }
+}
+
+// ======================================================
+// Symphonia (in-process)
+// ======================================================
+
+pub struct SymphoniaDecoder {
+    path: String,
+    info: AudioInfo,
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    /// Unit of the track's timestamps, see `ts_to_frames`.
+    time_base: Option<TimeBase>,
+    sample_buffer: Option<SampleBuffer<f32>>,
+    /// Timestamp a seek asked for. Packets may start before it, the samples
+    /// up to it are dropped.
+    seek_ts: Option<u64>,
+}
+
+fn open_format(input_path: &str) -> Result<Box<dyn FormatReader>> {
+    let file =
+        File::open(input_path).map_err(|e| Error::io(format!("cannot open {}", input_path), e))?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = Path::new(input_path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map(|probed| probed.format)
+        .map_err(|e| unsupported(input_path, e.to_string()))
+}
+
+/// Symphonia's codecs plus the ones it lacks (Opus).
+fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+    CODECS.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut registry);
+        registry.register_all::<crate::opus::OpusDecoder>();
+        registry
+    })
+}
+
+/// A container opened by `probe`, positioned at the start of the stream.
+struct Probed {
+    info: AudioInfo,
+    format: Box<dyn FormatReader>,
+    track_id: u32,
+    params: CodecParameters,
+}
+
+/// Frame counts of files whose header has none, keyed by path, size and
+/// modification time, so probing a file again does not scan it again.
+type FrameCountKey = (PathBuf, u64, Option<SystemTime>);
+
+fn scanned_frame_counts() -> &'static Mutex<HashMap<FrameCountKey, u64>> {
+    static COUNTS: OnceLock<Mutex<HashMap<FrameCountKey, u64>>> = OnceLock::new();
+    COUNTS.get_or_init(Default::default)
+}
+
+fn frame_count_key(input_path: &str) -> Option<FrameCountKey> {
+    let metadata = std::fs::metadata(input_path).ok()?;
+    Some((
+        PathBuf::from(input_path),
+        metadata.len(),
+        metadata.modified().ok(),
+    ))
+}
+
+/// Converts a timestamp or duration in `time_base` units into frames at
+/// `sample_rate`. Most containers count in frames already, Matroska counts
+/// in milliseconds, for one.
+fn ts_to_frames(ts: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+    match time_base {
+        Some(tb) => {
+            let scaled = ts as u128 * tb.numer as u128 * sample_rate as u128;
+            let denom = tb.denom.max(1) as u128;
+            ((scaled + denom / 2) / denom) as u64
+        }
+        None => ts,
+    }
+}
+
+/// Sums up the packet durations of `track_id`, then rewinds `format` to the
+/// start (or reopens it when the container cannot seek).
+fn scan_frame_count(
+    input_path: &str,
+    format: &mut Box<dyn FormatReader>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    sample_rate: u32,
+) -> Result<u64> {
+    let key = frame_count_key(input_path);
+    if let Some(key) = &key {
+        if let Some(&n) = scanned_frame_counts().lock().unwrap().get(key) {
+            return Ok(n);
+        }
+    }
+
+    let mut duration = 0;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() == track_id {
+            duration += packet.dur;
+        }
+    }
+    let n = ts_to_frames(duration, time_base, sample_rate);
+
+    let rewound = format.seek(SeekMode::Accurate, SeekTo::TimeStamp { ts: 0, track_id });
+    if rewound.is_err() {
+        *format = open_format(input_path)?;
+    }
+
+    if let Some(key) = key {
+        scanned_frame_counts().lock().unwrap().insert(key, n);
+    }
+    Ok(n)
+}
+
+/// Opens `input_path` and reads the stream parameters of its first audio
+/// track without decoding it.
+fn probe(input_path: &str) -> Result<Probed> {
+    let mut format = open_format(input_path)?;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| unsupported(input_path, "no audio track"))?;
+    let params = track.codec_params.clone();
+    let track_id = track.id;
+
+    // The decoder must exist, otherwise the format is only half supported.
+    let codec = codecs()
+        .get_codec(params.codec)
+        .ok_or_else(|| unsupported(input_path, "unsupported codec"))?;
+    let sample_rate = params
+        .sample_rate
+        .ok_or_else(|| unsupported(input_path, "unknown sample rate"))?;
+    let layout = params
+        .channels
+        .ok_or_else(|| unsupported(input_path, "unknown channel layout"))?;
+
+    // No frame count in the header, sum up the packet durations instead.
+    let n_frames = match params.n_frames {
+        Some(n) => n,
+        None => scan_frame_count(
+            input_path,
+            &mut format,
+            track_id,
+            params.time_base,
+            sample_rate,
+        )?,
+    };
+
+    // Raw PCM reports its sample format as the codec, show the container instead.
+    let format_name = if codec.short_name.starts_with("pcm") {
+        Path::new(input_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_else(|| codec.short_name.to_string())
+    } else {
+        codec.short_name.to_string()
+    };
+
+    let info = AudioInfo {
+        duration: n_frames as f64 / sample_rate as f64,
+        sample_rate,
+        format: format_name,
+        bits_per_sample: params.bits_per_sample.unwrap_or(0),
+        channels: layout.count() as u32,
+        channel_names: channels::names_from_symphonia(layout),
+    };
+    Ok(Probed {
+        info,
+        format,
+        track_id,
+        params,
+    })
+}
+
+/// Reads the stream parameters of the first audio track without decoding it.
+pub fn probe_with_symphonia(input_path: &str) -> Result<AudioInfo> {
+    probe(input_path).map(|probed| probed.info)
+}
+
+impl SymphoniaDecoder {
+    pub fn open(input_path: &str) -> Result<Self> {
+        let probed = probe(input_path)?;
+        let decoder = codecs()
+            .make(&probed.params, &DecoderOptions::default())
+            .map_err(|e| unsupported(input_path, e.to_string()))?;
+
+        Ok(Self {
+            path: input_path.to_string(),
+            info: probed.info,
+            format: probed.format,
+            decoder,
+            track_id: probed.track_id,
+            time_base: probed.params.time_base,
+            sample_buffer: None,
+            seek_ts: None,
+        })
+    }
+}
+
+impl AudioDecoder for SymphoniaDecoder {
+    fn info(&self) -> &AudioInfo {
+        &self.info
+    }
+
+    fn next_block(&mut self, buffer: &mut Vec<f32>) -> Result<bool> {
+        buffer.clear();
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(false);
+                }
+                Err(e) => {
+                    return Err(Error::Decode {
+                        path: self.path.clone(),
+                        reason: e.to_string(),
+                    });
+                }
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let capacity = decoded.capacity() as u64;
+                    let sample_buffer = match &mut self.sample_buffer {
+                        Some(sb) if sb.capacity() >= decoded.capacity() * spec.channels.count() => {
+                            sb
+                        }
+                        _ => self.sample_buffer.insert(SampleBuffer::new(capacity, spec)),
+                    };
+                    sample_buffer.copy_interleaved_ref(decoded);
+                    let samples = sample_buffer.samples();
+                    let frames = samples.len() / spec.channels.count().max(1);
+                    let skip = match self.seek_ts {
+                        Some(ts) => {
+                            let early = ts.saturating_sub(packet.ts());
+                            (ts_to_frames(early, self.time_base, spec.rate) as usize).min(frames)
+                        }
+                        None => 0,
+                    };
+                    if skip < frames {
+                        self.seek_ts = None;
+                    }
+                    buffer.extend_from_slice(&samples[skip * spec.channels.count()..]);
+                    if !buffer.is_empty() {
+                        return Ok(true);
+                    }
+                }
+                // Corrupt packets are skipped, like ffmpeg does.
+                Err(SymphoniaError::DecodeError(e)) => {
+                    eprintln!("Skipping undecodable packet: {}", e);
+                }
+                Err(e) => {
+                    return Err(Error::Decode {
+                        path: self.path.clone(),
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn seek(&mut self, seconds: f64) -> Result<()> {
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::from(seconds.max(0.0)),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|e| Error::Decode {
+                path: self.path.clone(),
+                reason: format!("cannot seek to {:.3} s: {}", seconds, e),
+            })?;
+        self.decoder.reset();
+        self.seek_ts = Some(seeked.required_ts);
+        Ok(())
+    }
+}
+
+// ======================================================
+// ffmpeg (external process, fallback)
+// ======================================================
+
+pub struct FfmpegDecoder {
+    path: String,
+    info: AudioInfo,
+    child: FfmpegChild,
+    stdout: ChildStdout,
+    read_buf: Vec<u8>,
+    leftover: Vec<u8>,
+    finished: bool,
+}
+
+impl FfmpegDecoder {
+    pub fn open(input_path: &str) -> Result<Self> {
+        let info = utils::get_audio_info_with_ffprobe(input_path)?;
+        let (child, stdout) = Self::spawn(input_path, 0.0)?;
+
+        Ok(Self {
+            path: input_path.to_string(),
+            info,
+            child,
+            stdout,
+            read_buf: vec![0; 64 * 1024],
+            leftover: Vec::new(),
+            finished: false,
+        })
+    }
+
+    /// Starts an ffmpeg decoding `input_path` from `start` seconds on.
+    fn spawn(input_path: &str, start: f64) -> Result<(FfmpegChild, ChildStdout)> {
+        let mut cmd_builder = match ffmpeg_is_installed() {
+            true => FfmpegCommand::new(),
+            false => FfmpegCommand::new_with_path(utils::get_ffmpeg_paths().ffmpeg),
+        };
+
+        let start = start.max(0.0).to_string();
+        cmd_builder.args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            // Before -i, so ffmpeg seeks in the input instead of decoding up to it.
+            "-ss",
+            &start,
+            "-i",
+            input_path,
+            "-map",
+            "0:a:0",
+            "-f",
+            "f32le",
+            "-acodec",
+            "pcm_f32le",
+            "-",
+        ]);
+
+        let mut child = cmd_builder
+            .spawn()
+            .map_err(|e| Error::io("failed to spawn ffmpeg", e))?;
+        let stdout = child.take_stdout().ok_or_else(|| {
+            Error::io(
+                "failed to read ffmpeg output",
+                std::io::Error::other("no stdout"),
+            )
+        })?;
+        Ok((child, stdout))
+    }
+
+    fn kill(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.child.kill() {
+                eprintln!("Failed to kill ffmpeg process: {}", e);
+            }
+            let _ = self.child.wait();
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        let status = self
+            .child
+            .wait()
+            .map_err(|e| Error::io("failed to wait for ffmpeg", e))?;
+        if !status.success() {
+            let mut stderr_output = String::new();
+            if let Some(mut stderr) = self.child.take_stderr() {
+                stderr.read_to_string(&mut stderr_output).ok();
+            }
+            return Err(Error::Ffmpeg {
+                stderr: stderr_output,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl AudioDecoder for FfmpegDecoder {
+    fn info(&self) -> &AudioInfo {
+        &self.info
+    }
+
+    fn next_block(&mut self, buffer: &mut Vec<f32>) -> Result<bool> {
+        buffer.clear();
+        if self.finished {
+            return Ok(false);
+        }
+
+        loop {
+            match self.stdout.read(&mut self.read_buf) {
+                Ok(0) => {
+                    self.finish()?;
+                    return Ok(false);
+                }
+                Ok(n) => {
+                    self.leftover.extend_from_slice(&self.read_buf[..n]);
+                    let channels = self.info.channels.max(1) as usize;
+                    if take_whole_frames(&mut self.leftover, channels, buffer) {
+                        return Ok(true);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::io("failed to read ffmpeg output", e)),
+            }
+        }
+    }
+
+    fn seek(&mut self, seconds: f64) -> Result<()> {
+        // ffmpeg cannot be told to seek while it runs, start a new one.
+        self.kill();
+        let (child, stdout) = Self::spawn(&self.path, seconds)?;
+        self.child = child;
+        self.stdout = stdout;
+        self.leftover.clear();
+        self.finished = false;
+        Ok(())
+    }
+}
+
+/// Moves the whole frames of little-endian f32 samples at the start of
+/// `bytes` into `buffer`. A partial frame stays behind for the next read, as
+/// pipe reads don't end on frame boundaries. Returns whether any were moved.
+fn take_whole_frames(bytes: &mut Vec<u8>, channels: usize, buffer: &mut Vec<f32>) -> bool {
+    let frame_bytes = 4 * channels;
+    let usable = bytes.len() - bytes.len() % frame_bytes;
+    if usable == 0 {
+        return false;
+    }
+    buffer.extend(
+        bytes[..usable]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+    );
+    bytes.drain(..usable);
+    true
+}
+
+impl Drop for FfmpegDecoder {
+    fn drop(&mut self) {
+        // Stopped early (cancelled or error), don't leave ffmpeg running.
+        self.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_wav;
+
+    #[test]
+    fn timestamps_are_converted_to_frames() {
+        // Matroska counts milliseconds.
+        assert_eq!(ts_to_frames(20, Some(TimeBase::new(1, 1000)), 48_000), 960);
+        assert_eq!(
+            ts_to_frames(441, Some(TimeBase::new(1, 44_100)), 44_100),
+            441
+        );
+        assert_eq!(ts_to_frames(3, Some(TimeBase::new(1, 48_000)), 16_000), 1);
+        assert_eq!(ts_to_frames(123, None, 48_000), 123);
+    }
+
+    #[test]
+    fn seeking_starts_at_the_requested_frame() {
+        // Every sample holds its own frame number / 8000.
+        let ramp: Vec<f32> = (0..8000).map(|i| i as f32 / 8000.0).collect();
+        let file = write_wav("decoder-seek", 8000, 1, &ramp);
+        let mut decoder = open(file.path_str()).unwrap();
+        decoder.seek(0.5).unwrap();
+        let mut block = Vec::new();
+        assert!(decoder.next_block(&mut block).unwrap());
+        assert_eq!(block[0], ramp[4000]);
+    }
+
+    #[test]
+    fn pipe_reads_are_cut_at_frame_boundaries() {
+        for channels in [3, 6] {
+            let samples: Vec<f32> = (0..channels * 1000).map(|i| i as f32).collect();
+            let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+            // Reads of an odd size, like 64 KiB ones against a 24-byte frame.
+            let (mut leftover, mut decoded, mut buffer) = (Vec::new(), Vec::new(), Vec::new());
+            for read in bytes.chunks(1001) {
+                leftover.extend_from_slice(read);
+                buffer.clear();
+                if take_whole_frames(&mut leftover, channels, &mut buffer) {
+                    assert_eq!(buffer.len() % channels, 0);
+                    decoded.extend_from_slice(&buffer);
+                }
+                assert!(leftover.len() < 4 * channels);
+            }
+            assert!(leftover.is_empty());
+            assert_eq!(decoded, samples);
+        }
+    }
+
+    #[test]
+    fn partial_frames_wait_for_the_next_read() {
+        let mut leftover: Vec<u8> = [1.0f32, 2.0].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut buffer = Vec::new();
+        assert!(!take_whole_frames(&mut leftover, 3, &mut buffer));
+        assert_eq!(leftover.len(), 8);
+        leftover.extend_from_slice(&3.0f32.to_le_bytes());
+        assert!(take_whole_frames(&mut leftover, 3, &mut buffer));
+        assert_eq!(buffer, [1.0, 2.0, 3.0]);
+    }
+}
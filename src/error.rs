@@ -0,0 +1,115 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while probing, decoding, rendering or saving.
+#[derive(Debug)]
+pub enum Error {
+    /// No input file was given.
+    NoInput,
+    /// ffmpeg/ffprobe is required for this operation but could not be found.
+    FfmpegMissing,
+    /// Neither the built-in decoder nor ffmpeg can read the file.
+    UnsupportedFormat { path: String, reason: String },
+    /// The file has no audio or its duration could not be determined.
+    ZeroDuration { path: String },
+    /// The selected time range starts at or after the end of the audio.
+    EmptyTimeRange { path: String, start: f64, end: f64 },
+    /// The operation was stopped through its cancel token.
+    Cancelled,
+    /// The built-in decoder failed in the middle of the stream.
+    Decode { path: String, reason: String },
+    /// ffmpeg or ffprobe exited with an error.
+    Ffmpeg { stderr: String },
+    /// ffprobe succeeded but its output was missing fields.
+    Probe { path: String, missing: String },
+    /// Spawning a process or reading its output failed.
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    /// The image ffmpeg produced could not be decoded.
+    ImageDecode(image::ImageError),
+    /// Writing a PNG failed.
+    ImageSave {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// Pixel data did not match the image dimensions.
+    ImageBuffer { width: u32, height: u32 },
+    /// The sound device could not be opened or stopped working.
+    AudioOutput(String),
+    /// The legend font could not be loaded.
+    Font(String),
+    /// Reading, parsing or writing the settings file failed.
+    Config { path: PathBuf, reason: String },
+    /// The settings could not be serialized.
+    ConfigSerialize(toml::ser::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoInput => write!(f, "no input file"),
+            Self::FfmpegMissing => write!(f, "ffmpeg is not installed"),
+            Self::UnsupportedFormat { path, reason } => {
+                write!(f, "unsupported format: {} ({})", path, reason)
+            }
+            Self::ZeroDuration { path } => write!(f, "no audio or zero duration: {}", path),
+            Self::EmptyTimeRange { path, start, end } => write!(
+                f,
+                "time range starts at {} s, but the audio ends at {} s: {}",
+                start, end, path
+            ),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Decode { path, reason } => write!(f, "failed to decode {}: {}", path, reason),
+            Self::Ffmpeg { stderr } => {
+                let stderr = stderr.trim();
+                if stderr.is_empty() {
+                    write!(f, "ffmpeg exited with an error")
+                } else {
+                    write!(f, "ffmpeg error: {}", stderr)
+                }
+            }
+            Self::Probe { path, missing } => {
+                write!(f, "ffprobe reported no {} for {}", missing, path)
+            }
+            Self::Io { context, source } => write!(f, "{}: {}", context, source),
+            Self::ImageDecode(e) => write!(f, "failed to decode ffmpeg's image: {}", e),
+            Self::ImageSave { path, source } => {
+                write!(f, "failed to save {}: {}", path.display(), source)
+            }
+            Self::ImageBuffer { width, height } => {
+                write!(f, "pixel data does not fit a {}x{} image", width, height)
+            }
+            Self::AudioOutput(reason) => write!(f, "audio output failed: {}", reason),
+            Self::Font(reason) => write!(f, "failed to load the legend font: {}", reason),
+            Self::Config { path, reason } => {
+                write!(f, "settings file {}: {}", path.display(), reason)
+            }
+            Self::ConfigSerialize(e) => write!(f, "failed to serialize settings: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::ImageDecode(e) => Some(e),
+            Self::ImageSave { source, .. } => Some(source),
+            Self::ConfigSerialize(e) => Some(e),
+            _ => None,
+        }
+    }
+}
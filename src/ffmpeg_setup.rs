@@ -3,7 +3,6 @@ use ffmpeg_sidecar::{self, command::ffmpeg_is_installed};
 use crate::ui::FfmpegSetup;
 use crate::utils::get_ffmpeg_paths;
 use eframe::egui;
-use image;
 
 pub fn setup_ffmpeg() -> eframe::Result<()> {
     if ffmpeg_is_installed() {
@@ -21,7 +20,7 @@ pub fn setup_ffmpeg() -> eframe::Result<()> {
     println!("FFmpeg is not installed.");
 
     let dialog_description = format!(
-        "FFmpeg is not found. It is required for this application to function. Do you want to download it automatically?\n\nIt will be installed in: {}\n\nDownload may take a few minutes, and the application might appear unresponsive during this time.",
+        "FFmpeg is not found. Without it only the built-in decoder and spectrogram engine are available. Do you want to download it automatically?\n\nIt will be installed in: {}\n\nDownload may take a few minutes, and the application might appear unresponsive during this time.",
         ff_paths.directory.to_string_lossy()
     );
 
@@ -63,10 +62,11 @@ pub fn setup_ffmpeg() -> eframe::Result<()> {
     .ok();
 
     if !ff_paths.ffmpeg.exists() || !ff_paths.ffprobe.exists() {
-        panic!(
-            "Installation failed. FFmpeg executable not found at expected path: {}",
+        // Not fatal: the built-in decoder and engine still handle common formats.
+        eprintln!(
+            "FFmpeg executable not found at expected path: {}. Continuing with the built-in decoder.",
             ff_paths.directory.display()
-        )
+        );
     }
 
     Ok(())
@@ -0,0 +1,386 @@
+use crate::cqt::{self, ConstantQParams};
+use crate::settings::{AppSettings, FrequencyScale, SpectrogramView};
+use image::RgbaImage;
+
+/// Lower edge of the `Log` axis, everything below is left out.
+pub const LOG_MIN_HZ: f32 = 20.0;
+
+/// Maps frequencies to vertical positions in the spectrogram.
+///
+/// Positions run from 0.0 at the bottom edge (`min_hz`) to 1.0 at the top
+/// edge (`max_hz`). The native engine, the ffmpeg row remapping and the legend
+/// ticks all go through this, so the image and its labels always agree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyAxis {
+    pub scale: FrequencyScale,
+    pub min_hz: f32,
+    pub max_hz: f32,
+    /// Label ticks with note names (C4) instead of frequencies.
+    pub note_labels: bool,
+}
+
+impl FrequencyAxis {
+    /// Axis from 0 Hz (`LOG_MIN_HZ` for `Log`) up to the Nyquist frequency.
+    pub fn new(scale: FrequencyScale, sample_rate: u32) -> Self {
+        Self::band(scale, sample_rate, 0.0, 0.0)
+    }
+
+    /// Axis from `min_hz` to `max_hz`, clamped to the Nyquist frequency.
+    /// A `max_hz` of 0 stands for the Nyquist frequency.
+    pub fn band(scale: FrequencyScale, sample_rate: u32, min_hz: f32, max_hz: f32) -> Self {
+        let nyquist = (sample_rate as f32 / 2.0).max(1.0);
+        let max_hz = if max_hz > 0.0 {
+            max_hz.min(nyquist)
+        } else {
+            nyquist
+        };
+        let min_hz = min_hz.clamp(0.0, (max_hz - 1.0).max(0.0));
+        let min_hz = match scale {
+            FrequencyScale::Log => min_hz.max(LOG_MIN_HZ.min(max_hz / 2.0)),
+            _ => min_hz,
+        };
+        Self {
+            scale,
+            min_hz,
+            max_hz,
+            note_labels: false,
+        }
+    }
+
+    /// Axis of images rendered with `settings`. The constant-Q view runs
+    /// logarithmically between its lowest and highest bin, labelled in notes.
+    pub fn for_settings(settings: &AppSettings, sample_rate: u32) -> Self {
+        match settings.view {
+            SpectrogramView::Stft => Self::band(
+                settings.frequency_scale,
+                sample_rate,
+                settings.freq_min_hz,
+                settings.freq_max_hz,
+            ),
+            SpectrogramView::ConstantQ => {
+                let frequencies = ConstantQParams::from_settings(settings).frequencies(sample_rate);
+                Self {
+                    scale: FrequencyScale::Log,
+                    min_hz: frequencies[0],
+                    max_hz: frequencies[frequencies.len() - 1],
+                    note_labels: true,
+                }
+            }
+        }
+    }
+
+    pub fn is_linear(&self) -> bool {
+        self.scale == FrequencyScale::Linear
+    }
+
+    /// Position of `hz` on the axis, 0.0 at the bottom and 1.0 at the top.
+    pub fn position(&self, hz: f32) -> f32 {
+        let lo = warp(self.scale, self.min_hz);
+        let hi = warp(self.scale, self.max_hz);
+        (warp(self.scale, hz.clamp(self.min_hz, self.max_hz)) - lo) / (hi - lo)
+    }
+
+    /// Frequency at `position`, the inverse of `position`.
+    pub fn frequency(&self, position: f32) -> f32 {
+        let lo = warp(self.scale, self.min_hz);
+        let hi = warp(self.scale, self.max_hz);
+        unwarp(self.scale, lo + position.clamp(0.0, 1.0) * (hi - lo))
+    }
+
+    /// Frequency range covered by `row` of `rows`, row 0 being the lowest.
+    pub fn row_range(&self, row: usize, rows: usize) -> (f32, f32) {
+        (
+            self.frequency(row as f32 / rows as f32),
+            self.frequency((row + 1) as f32 / rows as f32),
+        )
+    }
+
+    /// Round frequencies to label on an axis `length` pixels tall, at least
+    /// `min_spacing` pixels apart.
+    pub fn ticks(&self, length: f32, min_spacing: f32) -> Vec<f32> {
+        let candidates = if self.note_labels {
+            // Every C, plus the other naturals when the octaves are tall enough.
+            let octave = length / (self.max_hz / self.min_hz).log2().max(1.0);
+            let steps: &[i32] = if octave >= 7.0 * min_spacing {
+                &[0, 2, 4, 5, 7, 9, 11]
+            } else if octave >= 2.0 * min_spacing {
+                &[0, 7]
+            } else {
+                &[0]
+            };
+            let first = cqt::note_number(self.min_hz).div_euclid(12) * 12;
+            (first..=cqt::note_number(self.max_hz))
+                .filter(|note| steps.contains(&(note.rem_euclid(12))))
+                .map(cqt::note_frequency)
+                .collect()
+        } else if self.is_linear() || self.max_hz < self.min_hz * 10.0 {
+            // Steps of 1, 2 or 5 × 10^n across the band, as many as fit. Bands
+            // narrower than a decade are close enough to linear on any scale;
+            // the step fits the top, where the warped scales are densest.
+            let max_ticks = (length / min_spacing).floor().max(1.0);
+            let top_step = self.max_hz - self.frequency(1.0 - min_spacing / length);
+            let step = nice_step(((self.max_hz - self.min_hz) / max_ticks).max(top_step));
+            let first = (self.min_hz / step).ceil() as usize;
+            let last = (self.max_hz / step).floor() as usize;
+            (first..=last).map(|i| i as f32 * step).collect()
+        } else {
+            // 1, 2 and 5 of every decade, spread out by the warped axis.
+            let mut values = Vec::new();
+            let mut decade = 1.0;
+            while decade <= self.max_hz {
+                for factor in [1.0, 2.0, 5.0] {
+                    values.push(decade * factor);
+                }
+                decade *= 10.0;
+            }
+            if self.min_hz == 0.0 {
+                values.insert(0, 0.0);
+            }
+            values
+        };
+
+        let mut ticks: Vec<f32> = Vec::new();
+        let mut last_y = f32::NEG_INFINITY;
+        for hz in candidates {
+            // Note frequencies are rounded, allow for that at the edges.
+            if hz < self.min_hz * 0.999 || hz > self.max_hz * 1.001 {
+                continue;
+            }
+            let y = self.position(hz) * length;
+            if y - last_y >= min_spacing {
+                ticks.push(hz);
+                last_y = y;
+            }
+        }
+        ticks
+    }
+
+    /// Label of a tick at `hz`.
+    pub fn label(&self, hz: f32) -> String {
+        if self.note_labels {
+            cqt::note_name(hz)
+        } else {
+            format_hz(hz)
+        }
+    }
+}
+
+/// Frequency in the units of the scale: Hz, ln(Hz), mel, Bark or ERB-rate.
+fn warp(scale: FrequencyScale, hz: f32) -> f32 {
+    match scale {
+        FrequencyScale::Linear => hz,
+        FrequencyScale::Log => hz.max(f32::MIN_POSITIVE).ln(),
+        FrequencyScale::Mel => 2595.0 * (1.0 + hz / 700.0).log10(),
+        // Traunmüller (1990).
+        FrequencyScale::Bark => 26.81 * hz / (1960.0 + hz) - 0.53,
+        // Glasberg & Moore (1990).
+        FrequencyScale::Erb => 21.4 * (1.0 + 0.00437 * hz).log10(),
+    }
+}
+
+fn unwarp(scale: FrequencyScale, value: f32) -> f32 {
+    match scale {
+        FrequencyScale::Linear => value,
+        FrequencyScale::Log => value.exp(),
+        FrequencyScale::Mel => 700.0 * (10f32.powf(value / 2595.0) - 1.0),
+        FrequencyScale::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+        FrequencyScale::Erb => (10f32.powf(value / 21.4) - 1.0) / 0.00437,
+    }
+}
+
+/// Smallest step of 1, 2 or 5 × 10^n that is at least `min_step`.
+fn nice_step(min_step: f32) -> f32 {
+    let magnitude = 10f32.powf(min_step.max(1.0).log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= min_step)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// "500 Hz", "1 kHz", "1.5 kHz", "15.25 kHz".
+pub fn format_hz(hz: f32) -> String {
+    if hz < 1000.0 {
+        format!("{:.0} Hz", hz)
+    } else {
+        // Enough decimals for the finest tick steps of a narrow band.
+        let khz = format!("{:.3}", hz / 1000.0);
+        format!("{} kHz", khz.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
+
+/// Resamples the rows of a linear-frequency spectrogram onto `axis`.
+///
+/// `image` holds `channels` spectrograms stacked top to bottom, each running
+/// linearly from `source.0` Hz (bottom) to `source.1` Hz (top), as ffmpeg
+/// draws them. The result has `rows_per_channel` rows per channel.
+pub fn remap_rows(
+    image: &RgbaImage,
+    axis: &FrequencyAxis,
+    source: (f32, f32),
+    channels: u32,
+    rows_per_channel: u32,
+) -> RgbaImage {
+    let channels = channels.max(1);
+    let source_rows = image.height() / channels;
+    let mut output = RgbaImage::new(image.width(), rows_per_channel * channels);
+    if source_rows == 0 {
+        return output;
+    }
+
+    let (source_min, source_max) = source;
+    let scale = source_rows as f32 / (source_max - source_min).max(f32::MIN_POSITIVE);
+    for channel in 0..channels {
+        // Source rows count from the bottom of the channel, image rows from the top.
+        let source_row =
+            |row: u32| channel * source_rows + source_rows - 1 - row.min(source_rows - 1);
+        for row in 0..rows_per_channel {
+            let (lo_hz, hi_hz) = axis.row_range(row as usize, rows_per_channel as usize);
+            let (lo, hi) = ((lo_hz - source_min) * scale, (hi_hz - source_min) * scale);
+            let dest = channel * rows_per_channel + rows_per_channel - 1 - row;
+
+            for x in 0..image.width() {
+                let mut sum = [0f32; 4];
+                if hi - lo >= 1.0 {
+                    // Several source rows: average them.
+                    let start = lo.round() as u32;
+                    let end = (hi.round() as u32).max(start + 1);
+                    for source in start..end {
+                        for (acc, &v) in sum
+                            .iter_mut()
+                            .zip(&image.get_pixel(x, source_row(source)).0)
+                        {
+                            *acc += v as f32 / (end - start) as f32;
+                        }
+                    }
+                } else {
+                    // Less than a source row: interpolate between the two nearest.
+                    let centre = ((lo + hi) / 2.0 - 0.5).max(0.0);
+                    let frac = centre.fract();
+                    let below = image.get_pixel(x, source_row(centre as u32)).0;
+                    let above = image.get_pixel(x, source_row(centre as u32 + 1)).0;
+                    for (acc, (&b, &a)) in sum.iter_mut().zip(below.iter().zip(&above)) {
+                        *acc = b as f32 * (1.0 - frac) + a as f32 * frac;
+                    }
+                }
+                output.put_pixel(x, dest, image::Rgba(sum.map(|v| v.round() as u8)));
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Full-range and band axes of every scale at 48 kHz.
+    fn axes() -> Vec<FrequencyAxis> {
+        FrequencyScale::VALUES
+            .into_iter()
+            .flat_map(|scale| {
+                [
+                    FrequencyAxis::new(scale, 48_000),
+                    FrequencyAxis::band(scale, 48_000, 100.0, 3000.0),
+                    FrequencyAxis::band(scale, 48_000, 2000.0, 2500.0),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn positions_and_frequencies_round_trip() {
+        for axis in axes() {
+            assert_eq!(axis.position(axis.min_hz), 0.0, "{:?}", axis);
+            assert!(
+                (axis.position(axis.max_hz) - 1.0).abs() < 1e-5,
+                "{:?}",
+                axis
+            );
+            for i in 0..=20 {
+                let hz = axis.min_hz + (axis.max_hz - axis.min_hz) * i as f32 / 20.0;
+                let back = axis.frequency(axis.position(hz));
+                assert!(
+                    (back - hz).abs() <= 1e-3 * hz.max(1.0),
+                    "{:?}: {} Hz came back as {} Hz",
+                    axis,
+                    hz,
+                    back
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bark_matches_traunmueller() {
+        // 1 kHz is about 8.5 Bark, 24 kHz is past the top critical band.
+        assert!((warp(FrequencyScale::Bark, 1000.0) - 8.527).abs() < 1e-3);
+        for hz in [20.0, 1000.0, 24_000.0] {
+            let bark = warp(FrequencyScale::Bark, hz);
+            assert!((unwarp(FrequencyScale::Bark, bark) - hz).abs() < 1e-2 * hz);
+        }
+    }
+
+    #[test]
+    fn ticks_stay_on_the_axis_and_apart() {
+        for axis in axes() {
+            for (length, min_spacing) in [(100.0, 20.0), (400.0, 30.0), (1200.0, 24.0)] {
+                let ticks = axis.ticks(length, min_spacing);
+                assert!(!ticks.is_empty(), "{:?} at {} px", axis, length);
+                for &hz in &ticks {
+                    assert!(
+                        (axis.min_hz..=axis.max_hz).contains(&hz),
+                        "{:?}: {} Hz",
+                        axis,
+                        hz
+                    );
+                }
+                for pair in ticks.windows(2) {
+                    let gap = (axis.position(pair[1]) - axis.position(pair[0])) * length;
+                    assert!(gap >= min_spacing - 1e-3, "{:?}: {:?}", axis, pair);
+                }
+            }
+        }
+    }
+
+    /// 1 pixel wide stack of `channels` plots of 100 rows from 0 to 1000 Hz,
+    /// dark except for the row 500..510 Hz of channel `bright_channel`.
+    fn bright_row_image(channels: u32, bright_channel: u32) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(1, 100 * channels, Rgba([0, 0, 0, 255]));
+        // Rows count from the top, frequencies from the bottom.
+        image.put_pixel(
+            0,
+            bright_channel * 100 + 99 - 50,
+            Rgba([255, 255, 255, 255]),
+        );
+        image
+    }
+
+    /// Image row with the brightest pixel.
+    fn brightest_row(image: &RgbaImage) -> u32 {
+        (0..image.height())
+            .max_by_key(|&y| image.get_pixel(0, y).0[0])
+            .unwrap()
+    }
+
+    #[test]
+    fn remapped_rows_keep_their_frequency() {
+        // 10 Hz rows, 500 Hz is row 10 from the bottom, 9 from the top.
+        let linear = FrequencyAxis::band(FrequencyScale::Linear, 2000, 400.0, 600.0);
+        let remapped = remap_rows(&bright_row_image(1, 0), &linear, (0.0, 1000.0), 1, 20);
+        assert_eq!(remapped.height(), 20);
+        assert_eq!(brightest_row(&remapped), 9);
+        assert_eq!(remapped.get_pixel(0, 9).0[0], 255);
+        assert_eq!(remapped.get_pixel(0, 8).0[0], 0);
+        assert_eq!(remapped.get_pixel(0, 10).0[0], 0);
+
+        // On a log axis the row holding 505 Hz lights up, in the second channel.
+        let log = FrequencyAxis::band(FrequencyScale::Log, 2000, 100.0, 1000.0);
+        let rows = 50;
+        let remapped = remap_rows(&bright_row_image(2, 1), &log, (0.0, 1000.0), 2, rows);
+        let row = (log.position(505.0) * rows as f32) as u32;
+        assert_eq!(brightest_row(&remapped), rows + rows - 1 - row);
+        assert!((0..rows).all(|y| remapped.get_pixel(0, y).0[0] == 0));
+    }
+}
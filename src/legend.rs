@@ -1,7 +1,10 @@
+use crate::analysis::{AnalysisReport, StereoAnalysis};
+use crate::error::{Error, Result};
+use crate::frequency::FrequencyAxis;
 use crate::palettes;
-use crate::utils::AudioInfo;
+use crate::spectrogram::{self, DbRange};
+use crate::utils::{self, AudioInfo};
 use ab_glyph::{Font, FontVec, PxScale};
-use font_kit::source::SystemSource;
 use image::{Rgba, RgbaImage};
 use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_text_mut};
 use imageproc::rect::Rect;
@@ -11,11 +14,13 @@ pub const BOTTOM_MARGIN: u32 = 64;
 pub const LEFT_MARGIN: u32 = 80;
 pub const RIGHT_MARGIN: u32 = 100;
 
+/// Draws ticks from `start` to `end` seconds, labelled with timestamps of the file.
+#[allow(clippy::too_many_arguments)]
 fn draw_time_scale(
     image: &mut RgbaImage,
     spec_width: u32,
     spec_height: u32,
-    duration: f64,
+    (start, end): (f64, f64),
     font: &FontVec,
     scale: PxScale,
     color: Rgba<u8>,
@@ -24,8 +29,8 @@ fn draw_time_scale(
 ) {
     let num_ticks = 10;
     for i in 0..=num_ticks {
-        let fraction = i as f32 / num_ticks as f32;
-        let x = (LEFT_MARGIN - 1) as f32 + fraction * (spec_width as f32 + 1.0); // "- 1" so it starts with border
+        let fraction = i as f64 / num_ticks as f64;
+        let x = (LEFT_MARGIN - 1) as f32 + fraction as f32 * (spec_width as f32 + 1.0); // "- 1" so it starts with border
 
         let (y_start, y_end, label_y) = if is_top {
             let y_start = TOP_MARGIN as f32 - 6.0;
@@ -41,10 +46,9 @@ fn draw_time_scale(
         draw_line_segment_mut(image, (x, y_start), (x, y_end), color);
 
         if draw_labels {
-            let time_sec = duration * fraction as f64;
-            let minutes = (time_sec / 60.0).floor() as u32;
-            let seconds = (time_sec % 60.0).floor() as u32;
-            let label = format!("{}:{:02}", minutes, seconds);
+            let time_sec = start + (end - start) * fraction;
+            let label =
+                utils::format_time(time_sec, (end - start) / num_ticks as f64, end >= 3600.0);
             let (text_width, _) = imageproc::drawing::text_size(scale, font, &label);
             draw_text_mut(
                 image,
@@ -59,36 +63,39 @@ fn draw_time_scale(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_freq_scale(
     image: &mut RgbaImage,
     spec_width: u32,
     spec_height: u32,
-    audio_info: AudioInfo,
+    axis: FrequencyAxis,
     font: &FontVec,
     scale: PxScale,
     color: Rgba<u8>,
-    split_channels: bool,
+    stacks: u32,
 ) {
-    let max_freq_khz = (audio_info.sample_rate / 2) as f32 / 1000.0;
-    let draw_multi_channel = audio_info.channels > 1 && split_channels;
-
-    let channel_count = if draw_multi_channel { 2 } else { 1 };
-    let height_per_channel = if draw_multi_channel {
-        spec_height / 2
-    } else {
-        spec_height
-    };
+    let channel_count = stacks.max(1);
+    let draw_multi_channel = channel_count > 1;
+    let height_per_channel = spec_height / channel_count;
+
+    // Labels of a tick at the top of the lower channel and one at the
+    // bottom of the upper channel would overlap.
+    let (_, label_height) = imageproc::drawing::text_size(scale, font, "0 kHz");
+    let min_spacing = label_height as f32 + 8.0;
+    let ticks = axis.ticks(height_per_channel as f32, min_spacing);
+    let top_tick = ticks.last().copied();
+    let bottom_labelled = ticks.first().is_some_and(|&hz| axis.position(hz) < 0.05);
 
     for channel in 0..channel_count {
         let y_offset = TOP_MARGIN + (channel * height_per_channel);
-        let num_ticks = if draw_multi_channel { 5 } else { 10 };
-        for i in 0..=num_ticks {
-            let fraction = i as f32 / num_ticks as f32;
+        for &hz in &ticks {
+            let fraction = axis.position(hz);
             let y = (y_offset - 1) as f32 + (1.0 - fraction) * (height_per_channel + 1) as f32;
+            let is_channel_top = Some(hz) == top_tick && fraction > 0.95;
 
             let x_start_left = LEFT_MARGIN as f32 - 6.0;
 
-            if !(draw_multi_channel && channel == 1 && i == num_ticks) {
+            if !(draw_multi_channel && channel > 0 && is_channel_top) {
                 // Left ticks
                 let x_end_left = LEFT_MARGIN as f32 - 1.0;
                 draw_line_segment_mut(image, (x_start_left, y), (x_end_left, y), color);
@@ -100,11 +107,12 @@ fn draw_freq_scale(
             }
 
             // Freq labels
-            if draw_multi_channel && channel == 1 && i == num_ticks {
-                // Skip max freq label for bottom channel to avoid overlap
+            let below_upper_label =
+                bottom_labelled && (1.0 - fraction) * (height_per_channel as f32) < min_spacing;
+            if draw_multi_channel && channel > 0 && (is_channel_top || below_upper_label) {
+                // Skip labels of lower channels too close to the upper one's bottom label
             } else {
-                let freq_khz = fraction * max_freq_khz;
-                let label = format!("{:.0} kHz", freq_khz);
+                let label = axis.label(hz);
                 let (text_width, text_height) = imageproc::drawing::text_size(scale, font, &label);
                 draw_text_mut(
                     image,
@@ -120,26 +128,122 @@ fn draw_freq_scale(
     }
 }
 
-fn draw_dbfs_scale(
+/// Writes the names of the stacked channels into the right margin, beside
+/// the middle of each. They repeat when there are more stacks than names,
+/// as for the channels of both files of a comparison.
+fn draw_channel_names(
     image: &mut RgbaImage,
     spec_width: u32,
     spec_height: u32,
+    stacks: u32,
+    names: &[String],
+    font: &FontVec,
+    color: Rgba<u8>,
+) {
+    if names.is_empty() {
+        return;
+    }
+    let scale = PxScale::from(11.0);
+    let stacks = stacks.max(1);
+    let height_per_stack = spec_height as f32 / stacks as f32;
+    // Between the right ticks and the level gradient.
+    let x = (LEFT_MARGIN + spec_width + 8) as i32;
+    let max_width = 24;
+    for stack in 0..stacks {
+        let name = truncate_text(font, scale, &names[stack as usize % names.len()], max_width);
+        let (_, text_height) = imageproc::drawing::text_size(scale, font, &name);
+        let y = TOP_MARGIN as f32 + height_per_stack * (stack as f32 + 0.5);
+        draw_text_mut(
+            image,
+            color,
+            x,
+            (y - text_height as f32 / 2.0) as i32 - 2,
+            scale,
+            font,
+            &name,
+        );
+    }
+}
+
+/// Draws the stereo correlation of each block of `stereo` over `time_range`
+/// as a strip under the time axis: green where the channels correlate, amber
+/// where they are unrelated and red where one is inverted, grey where silent.
+/// The blocks start `analysis_start` seconds into the file.
+#[allow(clippy::too_many_arguments)]
+fn draw_correlation_strip(
+    image: &mut RgbaImage,
+    spec_width: u32,
+    spec_height: u32,
+    stereo: &StereoAnalysis,
+    analysis_start: f64,
+    (start, end): (f64, f64),
+    font: &FontVec,
+    color: Rgba<u8>,
+) {
+    // Below the "Time" label, inside the bottom margin.
+    let y = (TOP_MARGIN + spec_height + 54) as i32;
+    let height = 7;
+    let block_seconds = stereo.block_seconds.max(f32::EPSILON) as f64;
+    for x in 0..spec_width {
+        let time = start + (end - start) * (x as f64 + 0.5) / spec_width as f64;
+        let block = (time - analysis_start) / block_seconds;
+        let correlation = (block >= 0.0)
+            .then(|| stereo.correlation_over_time.get(block as usize))
+            .flatten()
+            .copied()
+            .flatten();
+        let rgb = match correlation {
+            None => [60.0, 60.0, 60.0],
+            Some(c) if c >= 0.0 => [230.0 * (1.0 - c), 200.0, 0.0],
+            Some(c) => [230.0, 200.0 * (1.0 + c), 0.0],
+        };
+        draw_filled_rect_mut(
+            image,
+            Rect::at((LEFT_MARGIN + x) as i32, y).of_size(1, height),
+            Rgba([rgb[0] as u8, rgb[1] as u8, rgb[2] as u8, 255u8]),
+        );
+    }
+
+    let scale = PxScale::from(11.0);
+    let label = "L/R corr.";
+    let (text_width, text_height) = imageproc::drawing::text_size(scale, font, label);
+    draw_text_mut(
+        image,
+        color,
+        (LEFT_MARGIN - text_width - 6) as i32,
+        y + (height as i32 - text_height as i32) / 2 - 2,
+        scale,
+        font,
+        label,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_level_scale(
+    image: &mut RgbaImage,
+    spec_width: u32,
+    spec_height: u32,
+    levels: LevelScale,
     font: &FontVec,
     scale: PxScale,
     color: Rgba<u8>,
 ) {
-    let db_range: f32 = -120.0;
-    let num_ticks = 10;
     let gradient_x = LEFT_MARGIN as f32 + spec_width as f32 + 34.0;
     let gradient_width = 10.0;
     let label_x = gradient_x + gradient_width + 5.0;
 
-    for i in 0..=num_ticks {
-        let fraction = i as f32 / num_ticks as f32;
+    let (_, label_height) = imageproc::drawing::text_size(scale, font, "-120");
+    let ticks = levels.ticks(spec_height as f32, label_height as f32 + 8.0);
+    for db_level in ticks {
+        let fraction = levels.position(db_level);
         let y = (TOP_MARGIN - 1) as f32 + (1.0 - fraction) * (spec_height + 1) as f32;
 
-        let db_level = (fraction - 1.0) * db_range.abs();
-        let label = format!("{:.0}", db_level);
+        // Avoid "-0" for levels rounding to zero.
+        let label = match levels {
+            LevelScale::Dbfs { .. } => format!("{:.0}", db_level.round() + 0.0),
+            LevelScale::Difference(_) if db_level.round() == 0.0 => "0".to_string(),
+            LevelScale::Difference(_) => format!("{:+.0}", db_level.round()),
+        };
 
         let (_, text_height) = imageproc::drawing::text_size(scale, font, &label);
         draw_text_mut(
@@ -179,6 +283,37 @@ fn truncate_text(font: &FontVec, scale: PxScale, text: &str, max_width: u32) ->
     String::new()
 }
 
+/// First installed font that has a glyph for `ch`.
+#[cfg(feature = "system-fonts")]
+fn find_system_font(ch: char) -> Option<FontVec> {
+    let all_fonts = font_kit::source::SystemSource::new().all_fonts().ok()?;
+    for handle in all_fonts {
+        if let Ok(font) = handle.load() {
+            if font.glyph_for_char(ch).is_some() {
+                // println!(
+                //     "Found fallback font '{}' for character '{}'",
+                //     font.postscript_name()
+                //         .unwrap_or_else(|| "Unknown".to_string()),
+                //     ch
+                // );
+                if let Some(font_data) = font.copy_font_data() {
+                    if let Ok(font_vec) = FontVec::try_from_vec(font_data.to_vec()) {
+                        return Some(font_vec);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Without system fonts, characters the bundled font lacks are drawn as boxes.
+#[cfg(not(feature = "system-fonts"))]
+fn find_system_font(_ch: char) -> Option<FontVec> {
+    None
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_text_with_fallback(
     image: &mut RgbaImage,
     color: Rgba<u8>,
@@ -191,7 +326,6 @@ fn draw_text_with_fallback(
 ) {
     let text_to_draw = truncate_text(primary_font, scale, text, max_width);
 
-    let source = SystemSource::new();
     let mut current_x = x as f32;
     let mut last_fallback: Option<FontVec> = None; // The cache
 
@@ -211,33 +345,15 @@ fn draw_text_with_fallback(
 
             // If not found in cache, scan.
             if !found {
-                if let Ok(all_fonts) = source.all_fonts() {
-                    for handle in all_fonts {
-                        if let Ok(font) = handle.load() {
-                            if font.glyph_for_char(ch).is_some() {
-                                // println!(
-                                //     "Found fallback font '{}' for character '{}'",
-                                //     font.postscript_name()
-                                //         .unwrap_or_else(|| "Unknown".to_string()),
-                                //     ch
-                                // );
-                                if let Some(font_data) = font.copy_font_data() {
-                                    if let Ok(font_vec) = FontVec::try_from_vec(font_data.to_vec())
-                                    {
-                                        last_fallback = Some(font_vec);
-                                        selected_font = last_fallback.as_ref().unwrap();
-                                        found = true;
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
+                if let Some(font_vec) = find_system_font(ch) {
+                    last_fallback = Some(font_vec);
+                    selected_font = last_fallback.as_ref().unwrap();
+                    found = true;
                 }
             }
 
             if !found {
-                println!(
+                eprintln!(
                     "Could not find any fallback font on the system for character: '{}'",
                     ch
                 );
@@ -259,23 +375,6 @@ fn draw_text_with_fallback(
     }
 }
 
-fn yuv8bit_to_rgb(y: f32, u: f32, v: f32) -> Rgba<u8> {
-    // Formula for full-range YUV [0,255] to RGB [0,255]
-    let u = u - 128.0;
-    let v = v - 128.0;
-
-    let r = y + 1.402 * v;
-    let g = y - 0.344136 * u - 0.714136 * v;
-    let b = y + 1.772 * u;
-
-    Rgba([
-        r.clamp(0.0, 255.0) as u8,
-        g.clamp(0.0, 255.0) as u8,
-        b.clamp(0.0, 255.0) as u8,
-        255,
-    ])
-}
-
 pub fn draw_gradient_line_mut(
     image: &mut RgbaImage,
     start: (f32, f32),
@@ -294,15 +393,7 @@ pub fn draw_gradient_line_mut(
 
     if steps < 1.0 {
         if (x0 as u32) < image.width() && (y0 as u32) < image.height() {
-            let (_, y_interp, u_interp, v_interp) = palette[0];
-            let y_8bit = y_interp * 255.0;
-            let u_8bit = 128.0 + u_interp * 255.0 * saturation;
-            let v_8bit = 128.0 + v_interp * 255.0 * saturation;
-            let color = yuv8bit_to_rgb(
-                y_8bit.clamp(0.0, 255.0),
-                u_8bit.clamp(0.0, 255.0),
-                v_8bit.clamp(0.0, 255.0),
-            );
+            let color = palettes::color_at(palette, palette[0].0, saturation);
             for i in 0..thickness {
                 let x = (x0 as u32) + i;
                 if x < image.width() {
@@ -319,42 +410,7 @@ pub fn draw_gradient_line_mut(
         let x_pos = (x0 + t * dx).round() as u32;
         let y_pos = (y0 + t * dy).round() as u32;
 
-        // Find the segment in the palette that `a` falls into
-        let mut end_idx = 1;
-        while end_idx < palette.len() - 1 && palette[end_idx].0 < a {
-            end_idx += 1;
-        }
-        let start_idx = end_idx - 1;
-
-        let start_stop = palette[start_idx];
-        let end_stop = palette[end_idx];
-
-        let (start_a, start_y, start_u, start_v) = start_stop;
-        let (end_a, end_y, end_u, end_v) = end_stop;
-
-        // Calculate interpolation factor within the segment
-        let lerp_frac = if (end_a - start_a).abs() < f32::EPSILON {
-            0.0
-        } else {
-            (a - start_a) / (end_a - start_a)
-        };
-
-        // Interpolate Y, U, V
-        let y_interp = start_y * (1.0 - lerp_frac) + end_y * lerp_frac;
-        let u_interp = start_u * (1.0 - lerp_frac) + end_u * lerp_frac;
-        let v_interp = start_v * (1.0 - lerp_frac) + end_v * lerp_frac;
-
-        // Construct 8-bit YUV pixel, applying saturation, to match ffmpeg's internal pipeline
-        let y_8bit = y_interp * 255.0;
-        let u_8bit = 128.0 + u_interp * 255.0 * saturation;
-        let v_8bit = 128.0 + v_interp * 255.0 * saturation;
-
-        // Clip YUV components before conversion, which is crucial for high saturation
-        let color = yuv8bit_to_rgb(
-            y_8bit.clamp(0.0, 255.0),
-            u_8bit.clamp(0.0, 255.0),
-            v_8bit.clamp(0.0, 255.0),
-        );
+        let color = palettes::color_at(palette, a, saturation);
 
         // Draw a horizontal line for thickness
         for k in 0..thickness {
@@ -366,10 +422,90 @@ pub fn draw_gradient_line_mut(
     }
 }
 
-use crate::settings::SpectrogramColorScheme;
+use crate::settings::{FrequencyScale, SpectrogramColorScheme, SpectrogramScale};
+
+/// What the colours of a plot stand for, and so the palette and the
+/// labels of the level scale on the right.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelScale {
+    /// Levels in dBFS over `range`, mapped to the palette by `scale`.
+    Dbfs {
+        color_scheme: SpectrogramColorScheme,
+        range: DbRange,
+        scale: SpectrogramScale,
+    },
+    /// Level differences from minus to plus this many dB, with the
+    /// difference palette.
+    Difference(f32),
+}
+
+impl LevelScale {
+    fn palette(&self) -> &'static [(f32, f32, f32, f32)] {
+        match self {
+            Self::Dbfs { color_scheme, .. } => palettes::get_palette(*color_scheme),
+            Self::Difference(_) => palettes::difference_palette(),
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            Self::Dbfs { .. } => "dBFS",
+            Self::Difference(_) => "±dB",
+        }
+    }
+
+    /// Palette position (0.0..1.0) of a level or difference.
+    pub fn position(&self, db: f32) -> f32 {
+        match *self {
+            Self::Dbfs { range, scale, .. } => range.position(db, scale),
+            Self::Difference(range) => spectrogram::difference_position(db, range),
+        }
+    }
+
+    /// Round values to label on a scale `length` pixels tall, at least
+    /// `min_spacing` pixels apart.
+    fn ticks(&self, length: f32, min_spacing: f32) -> Vec<f32> {
+        match *self {
+            Self::Dbfs { range, scale, .. } => range.ticks(scale, length, min_spacing),
+            Self::Difference(range) => difference_ticks(range, length, min_spacing),
+        }
+    }
+}
+
+/// Differences to label on a scale spanning `range` dB either way: evenly
+/// spaced around zero, with the ends when they still fit.
+fn difference_ticks(range: f32, length: f32, min_spacing: f32) -> Vec<f32> {
+    let pixels_per_db = length / (2.0 * range.max(f32::EPSILON));
+    let step = [1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0]
+        .into_iter()
+        .find(|step| step * pixels_per_db >= min_spacing)
+        .unwrap_or(100.0);
+    let mut ticks = Vec::new();
+    let mut db = (range / step).floor() * step;
+    while db >= -range {
+        ticks.push(db);
+        db -= step;
+    }
+    let Some(&top) = ticks.first() else {
+        return ticks;
+    };
+    if (range - top) * pixels_per_db >= min_spacing {
+        ticks.insert(0, range);
+        ticks.push(-range);
+    }
+    ticks
+}
 
 /// Creates an image with a legend template.
 /// The spectrogram itself will be drawn on top of this template later.
+/// `stacks` spectrograms (channels or files) are stacked in the plot, each
+/// with its own frequency scale and, when there are `channel_names`, the
+/// name of its channel. Without a `frequency_axis` the frequency
+/// scale is linear, without a `time_range` the time scale covers the whole
+/// file. The palette and level scale on the right follow `levels`. With
+/// `correlation_strip`, the stereo correlation found by the analysis is
+/// drawn under the time axis.
+#[allow(clippy::too_many_arguments)]
 pub fn draw_legend(
     spec_width: u32,
     spec_height: u32,
@@ -377,10 +513,15 @@ pub fn draw_legend(
     ffmpeg_settings: &str,
     audio_info: Option<AudioInfo>,
     saturation: f32,
-    color_scheme: SpectrogramColorScheme,
-    split_channels: bool,
+    stacks: u32,
+    channel_names: &[String],
     show_version: bool,
-) -> RgbaImage {
+    analysis: Option<&AnalysisReport>,
+    correlation_strip: bool,
+    frequency_axis: Option<FrequencyAxis>,
+    time_range: Option<(f64, f64)>,
+    levels: LevelScale,
+) -> Result<RgbaImage> {
     let final_width = spec_width + LEFT_MARGIN + RIGHT_MARGIN;
     let final_height = spec_height + TOP_MARGIN + BOTTOM_MARGIN;
 
@@ -406,10 +547,7 @@ pub fn draw_legend(
     draw_line_segment_mut(&mut image, bottom_right, bottom_left, white);
     draw_line_segment_mut(&mut image, bottom_left, top_left, white);
 
-    // Load font
-    let font_data = include_bytes!("../assets/DejaVuLGCSans.ttf");
-    let font =
-        FontVec::try_from_vec(font_data.to_vec()).expect("Error constructing Font from bytes");
+    let font = load_font()?;
 
     let font_normal = PxScale::from(16.0);
     let font_small = PxScale::from(13.0);
@@ -434,9 +572,14 @@ pub fn draw_legend(
         let mut details = Vec::new();
         details.push(info.format.to_uppercase());
         details.push(format!("{} Hz", info.sample_rate));
-        if info.bits_per_sample > 0 {
+        // The analysis reports the bit depth itself, declared and effective.
+        let verified_bits = analysis.is_some_and(|report| report.bit_depth.is_some());
+        if info.bits_per_sample > 0 && !verified_bits {
             details.push(format!("{} bit", info.bits_per_sample));
         }
+        if let Some(report) = analysis {
+            details.extend(report.summary());
+        }
         let audio_details = details.join(", ");
         if !ffmpeg_settings.is_empty() {
             display_string = format!("{}, {}", audio_details, ffmpeg_settings);
@@ -470,8 +613,8 @@ pub fn draw_legend(
         );
     }
 
-    // dBFS gradient (right)
-    let dbfs_label = "dBFS";
+    // Level gradient (right)
+    let dbfs_label = levels.unit();
     let (text_width, _) = imageproc::drawing::text_size(font_small, &font, dbfs_label);
     let gradient_center_x = (LEFT_MARGIN + spec_width + 34 + 5) as i32;
     draw_text_mut(
@@ -495,19 +638,22 @@ pub fn draw_legend(
         "Time",
     );
 
-    // dBFS vertical gradient line on the right
-    let palette = palettes::get_palette(color_scheme);
+    // Level vertical gradient line on the right
+    let palette = levels.palette();
     let line_x = (LEFT_MARGIN + spec_width + 34) as f32;
     let start_point = (line_x, TOP_MARGIN as f32);
     let end_point = (line_x, (TOP_MARGIN + spec_height) as f32);
     draw_gradient_line_mut(&mut image, start_point, end_point, palette, saturation, 10);
 
     if let Some(info) = audio_info {
+        let axis = frequency_axis
+            .unwrap_or_else(|| FrequencyAxis::new(FrequencyScale::Linear, info.sample_rate));
+        let time_range = time_range.unwrap_or((0.0, info.duration));
         draw_time_scale(
             &mut image,
             spec_width,
             spec_height,
-            info.duration,
+            time_range,
             &font,
             font_scales,
             text_color,
@@ -518,32 +664,104 @@ pub fn draw_legend(
             &mut image,
             spec_width,
             spec_height,
-            info.duration,
+            time_range,
             &font,
             font_scales,
             text_color,
             true,  // top
             false, // draw_labels
         );
+        if let Some((report, stereo)) = analysis
+            .and_then(|report| Some((report, report.stereo.as_ref()?)))
+            .filter(|_| correlation_strip)
+        {
+            draw_correlation_strip(
+                &mut image,
+                spec_width,
+                spec_height,
+                stereo,
+                report.start_seconds,
+                time_range,
+                &font,
+                text_color,
+            );
+        }
         draw_freq_scale(
             &mut image,
             spec_width,
             spec_height,
-            info,
+            axis,
             &font,
             font_scales,
             text_color,
-            split_channels,
+            stacks,
         );
     }
+    draw_channel_names(
+        &mut image,
+        spec_width,
+        spec_height,
+        stacks,
+        channel_names,
+        &font,
+        text_color,
+    );
 
-    draw_dbfs_scale(
+    draw_level_scale(
         &mut image,
         spec_width,
         spec_height,
+        levels,
         &font,
         font_scales,
         text_color,
     );
-    image
+    Ok(image)
+}
+
+/// The bundled legend font.
+fn load_font() -> Result<FontVec> {
+    let font_data = include_bytes!("../assets/DejaVuLGCSans.ttf");
+    FontVec::try_from_vec(font_data.to_vec()).map_err(|e| Error::Font(e.to_string()))
+}
+
+/// Tags one spectrogram of a comparison with `label` ("A" or "B") in the
+/// top-left corner of its plot, which starts at `x`, `y`.
+pub fn draw_comparison_label(image: &mut RgbaImage, x: u32, y: u32, label: &str) -> Result<()> {
+    let font = load_font()?;
+    let scale = PxScale::from(16.0);
+    let (text_width, text_height) = imageproc::drawing::text_size(scale, &font, label);
+    draw_filled_rect_mut(
+        image,
+        Rect::at(x as i32, y as i32).of_size(text_width + 8, text_height + 8),
+        Rgba([0u8, 0u8, 0u8, 255u8]),
+    );
+    draw_text_mut(
+        image,
+        Rgba([255u8, 255u8, 255u8, 255u8]),
+        x as i32 + 4,
+        y as i32 + 2,
+        scale,
+        &font,
+        label,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difference_ticks_are_symmetric() {
+        let ticks = difference_ticks(20.0, 400.0, 60.0);
+        assert_eq!(ticks, [20.0, 10.0, 0.0, -10.0, -20.0]);
+    }
+
+    #[test]
+    fn difference_ticks_survive_invalid_ranges() {
+        for range in [-10.0, 0.0, f32::NAN] {
+            assert!(difference_ticks(range, 400.0, 30.0).len() <= 1, "{}", range);
+        }
+    }
 }
@@ -1,14 +1,35 @@
 // src/lib.rs
 
 // Core-Module
-pub mod ffmpeg_setup;
+pub mod align;
+pub mod analysis;
+pub mod batch;
+pub mod channels;
+pub mod cli;
+pub mod cqt;
+pub mod decoder;
+pub mod error;
+pub mod frequency;
 pub mod legend;
+pub mod opus;
 pub mod palettes;
+pub mod playback;
+pub mod render;
 pub mod settings;
+pub mod spectrogram;
 pub mod utils;
 
-// UI-Modul (Ordner src/ui/)
+#[cfg(test)]
+mod test_support;
+
+// UI-Modul (Ordner src/ui/), nur mit dem Feature "gui"
+#[cfg(feature = "gui")]
+pub mod ffmpeg_setup;
+#[cfg(feature = "gui")]
 pub mod ui;
 
 // Wichtig: MyApp aus ui::mod.rs nach außen exportieren
+pub use error::{Error, Result};
+pub use render::{RenderRequest, Renderer, Rendering};
+#[cfg(feature = "gui")]
 pub use ui::MyApp;
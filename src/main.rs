@@ -1,14 +1,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use eframe::egui;
-use image;
 use std::env;
 use std::process::{Command, Stdio};
 
-use spek_rs::MyApp;
 use spek_rs::ffmpeg_setup;
 use spek_rs::settings;
 use spek_rs::utils;
+use spek_rs::{MyApp, RenderRequest, Renderer};
 
 fn main() -> eframe::Result {
     ffmpeg_setup::setup_ffmpeg()?;
@@ -48,19 +47,24 @@ fn main() -> eframe::Result {
     // HEADLESS PNG MODE
     // -----------------------------
     if let Some(output_png) = png_output {
-        let mut app = MyApp::new(None, input_path.clone(), app_settings);
-
-        match app.regenerate_spectrogram_headless() {
-            Some(image) => {
-                utils::save_color_image_as_png(
-    &image,
-    std::path::Path::new(&output_png),
-)
-.expect("Failed to save PNG");
-                println!("Saved spectrogram to {}", output_png);
+        let Some(input_path) = input_path else {
+            eprintln!(
+                "Failed to generate spectrogram: {}",
+                spek_rs::Error::NoInput
+            );
+            return Ok(());
+        };
+        let request = RenderRequest::new(input_path, app_settings);
+
+        match Renderer::new().render(&request) {
+            Ok(rendering) => {
+                match utils::save_png(&rendering.image, std::path::Path::new(&output_png)) {
+                    Ok(()) => println!("Saved spectrogram to {}", output_png),
+                    Err(e) => eprintln!("{}", e),
+                }
             }
-            None => {
-                eprintln!("Failed to generate spectrogram");
+            Err(e) => {
+                eprintln!("Failed to generate spectrogram: {}", e);
             }
         }
 
@@ -0,0 +1,325 @@
+use opus_decoder::{OpusError, OpusMultistreamDecoder};
+use symphonia::core::audio::{
+    AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels, Signal, SignalSpec,
+};
+use symphonia::core::codecs::{
+    CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS,
+};
+use symphonia::core::errors::{decode_error, unsupported_error, Result};
+use symphonia::core::formats::Packet;
+use symphonia::core::support_codec;
+
+// ======================================================
+// Opus (in-process)
+// ======================================================
+
+/// Opus always decodes at 48 kHz.
+const SAMPLE_RATE: u32 = 48_000;
+/// Frames of the longest Opus packet, 120 ms.
+const MAX_PACKET_FRAMES: usize = 5760;
+
+/// The identification header of an Ogg Opus stream (RFC 7845, section 5.1),
+/// which the Ogg reader hands over as the codec's extra data.
+#[derive(Debug, Clone, PartialEq)]
+struct OpusHead {
+    channels: usize,
+    /// Output gain in dB, to apply to every decoded sample.
+    gain_db: f32,
+    streams: usize,
+    coupled_streams: usize,
+    /// Decoded channel of each output channel.
+    mapping: Vec<u8>,
+}
+
+fn parse_head(data: &[u8]) -> Option<OpusHead> {
+    if data.len() < 19 || &data[..8] != b"OpusHead" {
+        return None;
+    }
+    let channels = data[9] as usize;
+    let gain_db = i16::from_le_bytes([data[16], data[17]]) as f32 / 256.0;
+    let (streams, coupled_streams, mapping) = match data[18] {
+        // Mono or stereo in a single stream, no mapping table.
+        0 if (1..=2).contains(&channels) => (1, channels - 1, (0..channels as u8).collect()),
+        0 => return None,
+        _ => {
+            let table = data.get(21..21 + channels)?;
+            (data[19] as usize, data[20] as usize, table.to_vec())
+        }
+    };
+    Some(OpusHead {
+        channels,
+        gain_db,
+        streams,
+        coupled_streams,
+        mapping,
+    })
+}
+
+/// Buffer plane of each Opus output channel. Opus uses the Vorbis channel
+/// order, Symphonia's buffers order the planes by channel bit.
+fn plane_order(layout: Channels) -> Option<Vec<usize>> {
+    const FL: Channels = Channels::FRONT_LEFT;
+    const FR: Channels = Channels::FRONT_RIGHT;
+    const FC: Channels = Channels::FRONT_CENTRE;
+    const LFE: Channels = Channels::LFE1;
+    const RL: Channels = Channels::REAR_LEFT;
+    const RR: Channels = Channels::REAR_RIGHT;
+    const RC: Channels = Channels::REAR_CENTRE;
+    const SL: Channels = Channels::SIDE_LEFT;
+    const SR: Channels = Channels::SIDE_RIGHT;
+
+    let order: &[Channels] = match layout.count() {
+        1 => &[FL],
+        2 => &[FL, FR],
+        3 => &[FL, FC, FR],
+        4 => &[FL, FR, RL, RR],
+        5 => &[FL, FC, FR, RL, RR],
+        6 => &[FL, FC, FR, RL, RR, LFE],
+        7 => &[FL, FC, FR, SL, SR, RC, LFE],
+        8 => &[FL, FC, FR, SL, SR, RL, RR, LFE],
+        _ => return None,
+    };
+    order
+        .iter()
+        .map(|&channel| layout.iter().position(|c| c == channel))
+        .collect()
+}
+
+/// Symphonia decoder for Opus streams, registered next to the built-in
+/// codecs (see `decoder::codecs`). Like Symphonia's MP3 and AAC decoders it
+/// leaves the encoder delay (pre-skip) in the output.
+pub struct OpusDecoder {
+    params: CodecParameters,
+    decoder: OpusMultistreamDecoder,
+    gain: f32,
+    /// Buffer plane of each output channel, see `plane_order`.
+    planes: Vec<usize>,
+    /// Interleaved output of the last packet.
+    pcm: Vec<f32>,
+    buf: AudioBuffer<f32>,
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _: &DecoderOptions) -> Result<Self> {
+        let head = match params.extra_data.as_deref().and_then(parse_head) {
+            Some(head) => head,
+            None => return unsupported_error("opus: invalid identification header"),
+        };
+        let layout = match params.channels {
+            Some(layout) if layout.count() == head.channels => layout,
+            _ => return unsupported_error("opus: channel layout does not match the header"),
+        };
+        let planes = match plane_order(layout) {
+            Some(planes) => planes,
+            None => return unsupported_error("opus: unsupported channel layout"),
+        };
+        let decoder = match OpusMultistreamDecoder::new(
+            SAMPLE_RATE,
+            head.channels,
+            head.streams,
+            head.coupled_streams,
+            &head.mapping,
+        ) {
+            Ok(decoder) => decoder,
+            Err(_) => return unsupported_error("opus: invalid channel mapping"),
+        };
+
+        let spec = SignalSpec::new(SAMPLE_RATE, layout);
+        Ok(Self {
+            params: params.clone(),
+            decoder,
+            gain: 10f32.powf(head.gain_db / 20.0),
+            planes,
+            pcm: vec![0.0; MAX_PACKET_FRAMES * head.channels],
+            buf: AudioBuffer::new(MAX_PACKET_FRAMES as u64, spec),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus")]
+    }
+
+    fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        self.buf.clear();
+        let frames = match self
+            .decoder
+            .decode_float(&packet.data, &mut self.pcm, false)
+        {
+            Ok(frames) => frames,
+            Err(OpusError::InvalidPacket) => return decode_error("opus: invalid packet"),
+            Err(_) => return decode_error("opus: cannot decode packet"),
+        };
+
+        self.buf.render_reserved(Some(frames));
+        let channels = self.planes.len();
+        for (channel, &plane) in self.planes.iter().enumerate() {
+            let samples = self.pcm[channel..].iter().step_by(channels);
+            for (out, sample) in self.buf.chan_mut(plane).iter_mut().zip(samples) {
+                *out = sample * self.gain;
+            }
+        }
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        Default::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buf.as_audio_buffer_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder;
+    use crate::test_support::TempFile;
+    use crate::utils::AudioInfo;
+
+    fn head(channels: u8, gain: i16, family: u8, table: &[u8]) -> Vec<u8> {
+        let mut data = b"OpusHead".to_vec();
+        data.extend_from_slice(&[1, channels]);
+        data.extend_from_slice(&312u16.to_le_bytes());
+        data.extend_from_slice(&44_100u32.to_le_bytes());
+        data.extend_from_slice(&gain.to_le_bytes());
+        data.push(family);
+        data.extend_from_slice(table);
+        data
+    }
+
+    /// CRC of Ogg pages: polynomial 0x04c11db7, no reflection, no final xor.
+    fn ogg_crc(data: &[u8]) -> u32 {
+        data.iter().fold(0u32, |crc, &byte| {
+            (0..8).fold(crc ^ (byte as u32) << 24, |crc, _| {
+                if crc & 0x8000_0000 != 0 {
+                    crc << 1 ^ 0x04c1_1db7
+                } else {
+                    crc << 1
+                }
+            })
+        })
+    }
+
+    fn ogg_page(flags: u8, granule: u64, sequence: u32, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let mut page = b"OggS".to_vec();
+        page.extend_from_slice(&[0, flags]);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    #[test]
+    fn heads_are_parsed() {
+        let stereo = parse_head(&head(2, -256, 0, &[])).unwrap();
+        assert_eq!(
+            stereo,
+            OpusHead {
+                channels: 2,
+                gain_db: -1.0,
+                streams: 1,
+                coupled_streams: 1,
+                mapping: vec![0, 1],
+            }
+        );
+
+        let surround = parse_head(&head(6, 0, 1, &[4, 2, 0, 4, 1, 2, 3, 5])).unwrap();
+        assert_eq!(surround.streams, 4);
+        assert_eq!(surround.coupled_streams, 2);
+        assert_eq!(surround.mapping, [0, 4, 1, 2, 3, 5]);
+
+        // Three channels need a mapping table, a truncated table is rejected.
+        assert_eq!(parse_head(&head(3, 0, 0, &[])), None);
+        assert_eq!(parse_head(&head(6, 0, 1, &[4, 2, 0, 4])), None);
+        assert_eq!(parse_head(b"OpusTags"), None);
+    }
+
+    #[test]
+    fn vorbis_order_maps_to_symphonia_planes() {
+        // Planes FL FR FC LFE RL RR, Opus sends FL FC FR RL RR LFE.
+        let layout = Channels::FRONT_LEFT
+            | Channels::FRONT_CENTRE
+            | Channels::FRONT_RIGHT
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT
+            | Channels::LFE1;
+        assert_eq!(plane_order(layout), Some(vec![0, 2, 1, 4, 5, 3]));
+
+        let stereo = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+        assert_eq!(plane_order(stereo), Some(vec![0, 1]));
+        // A layout that is not a Vorbis one.
+        assert_eq!(plane_order(Channels::FRONT_LEFT | Channels::LFE1), None);
+    }
+
+    /// Ogg Opus file of 50 CELT packets of 20 ms (TOC 0xfc, fullband
+    /// stereo) with all-zero payloads, which decode to a faint signal.
+    fn write_opus(name: &str, gain: i16) -> TempFile {
+        let packet = [vec![0xfc], vec![0; 80]].concat();
+        let tags = [b"OpusTags".as_slice(), &[0; 8]].concat();
+        let mut bytes = ogg_page(0x02, 0, 0, &[head(2, gain, 0, &[])]);
+        bytes.extend(ogg_page(0x00, 0, 1, &[tags]));
+        bytes.extend(ogg_page(0x04, 312 + 50 * 960, 2, &vec![packet; 50]));
+
+        let file = TempFile::new(&format!("{}.opus", name));
+        std::fs::write(file.path(), bytes).unwrap();
+        file
+    }
+
+    fn decode_all(file: &TempFile) -> (AudioInfo, Vec<f32>) {
+        let mut decoder = decoder::open(file.path_str()).unwrap();
+        let (mut samples, mut block) = (Vec::new(), Vec::new());
+        while decoder.next_block(&mut block).unwrap() {
+            samples.extend_from_slice(&block);
+        }
+        (decoder.info().clone(), samples)
+    }
+
+    #[test]
+    fn ogg_opus_files_decode_in_process() {
+        let (info, samples) = decode_all(&write_opus("decode", 0));
+        assert_eq!(info.format, "opus");
+        assert_eq!(info.sample_rate, 48_000);
+        assert_eq!(info.channels, 2);
+        assert_eq!(samples.len(), 50 * 960 * 2);
+        assert!(samples.iter().all(|s| s.is_finite()));
+        assert!(samples.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn output_gain_is_applied() {
+        let (_, plain) = decode_all(&write_opus("plain", 0));
+        // -6.02 dB in Q7.8, about half the amplitude.
+        let (_, halved) = decode_all(&write_opus("halved", -1541));
+        assert_eq!(plain.len(), halved.len());
+        for (p, h) in plain.iter().zip(&halved) {
+            assert!(
+                (p * 0.5 - h).abs() < 1e-4 * p.abs() + 1e-7,
+                "{} vs {}",
+                p,
+                h
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::settings::SpectrogramColorScheme;
+use image::Rgba;
 
 // ======================================================
 // Palette definitions (YUV)
@@ -33,6 +34,16 @@ const COOL: &[(f32, f32, f32, f32)] = &[
     (1.0, 1.0, 0.0, 0.0),
 ];
 
+/// Diverging palette for level differences: blue below, grey at and red
+/// above the middle.
+const DIFFERENCE: &[(f32, f32, f32, f32)] = &[
+    (0.0, 0.75, 0.25, -0.15),
+    (0.2, 0.37, 0.36, -0.15),
+    (0.5, 0.5, 0.0, 0.0),
+    (0.8, 0.45, -0.19, 0.39),
+    (1.0, 0.85, -0.2, 0.2),
+];
+
 // ======================================================
 // Public API
 // ======================================================
@@ -47,3 +58,65 @@ pub fn get_palette(
         SpectrogramColorScheme::Ice => COOL,
     }
 }
+
+/// The palette of difference plots, which map no change to the middle.
+pub fn difference_palette() -> &'static [(f32, f32, f32, f32)] {
+    DIFFERENCE
+}
+
+/// Looks up the colour for intensity `a` (0.0..1.0) in a YUV palette.
+///
+/// Mirrors ffmpeg's showspectrum pipeline: the palette is interpolated in YUV,
+/// saturation scales the chroma and the result is clipped before conversion.
+pub fn color_at(palette: &[(f32, f32, f32, f32)], a: f32, saturation: f32) -> Rgba<u8> {
+    let a = a.clamp(0.0, 1.0);
+
+    // Find the segment in the palette that `a` falls into
+    let mut end_idx = 1;
+    while end_idx < palette.len() - 1 && palette[end_idx].0 < a {
+        end_idx += 1;
+    }
+    let start_idx = end_idx - 1;
+
+    let (start_a, start_y, start_u, start_v) = palette[start_idx];
+    let (end_a, end_y, end_u, end_v) = palette[end_idx];
+
+    // Calculate interpolation factor within the segment
+    let lerp_frac = if (end_a - start_a).abs() < f32::EPSILON {
+        0.0
+    } else {
+        (a - start_a) / (end_a - start_a)
+    };
+
+    let y_interp = start_y * (1.0 - lerp_frac) + end_y * lerp_frac;
+    let u_interp = start_u * (1.0 - lerp_frac) + end_u * lerp_frac;
+    let v_interp = start_v * (1.0 - lerp_frac) + end_v * lerp_frac;
+
+    let y_8bit = y_interp * 255.0;
+    let u_8bit = 128.0 + u_interp * 255.0 * saturation;
+    let v_8bit = 128.0 + v_interp * 255.0 * saturation;
+
+    // Clip YUV components before conversion, which is crucial for high saturation
+    yuv8bit_to_rgb(
+        y_8bit.clamp(0.0, 255.0),
+        u_8bit.clamp(0.0, 255.0),
+        v_8bit.clamp(0.0, 255.0),
+    )
+}
+
+pub fn yuv8bit_to_rgb(y: f32, u: f32, v: f32) -> Rgba<u8> {
+    // Formula for full-range YUV [0,255] to RGB [0,255]
+    let u = u - 128.0;
+    let v = v - 128.0;
+
+    let r = y + 1.402 * v;
+    let g = y - 0.344136 * u - 0.714136 * v;
+    let b = y + 1.772 * u;
+
+    Rgba([
+        r.clamp(0.0, 255.0) as u8,
+        g.clamp(0.0, 255.0) as u8,
+        b.clamp(0.0, 255.0) as u8,
+        255,
+    ])
+}
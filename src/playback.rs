@@ -0,0 +1,750 @@
+use crate::decoder;
+use crate::error::{Error, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the playback thread waits for a command while it has nothing to play.
+const IDLE_POLL: Duration = Duration::from_millis(20);
+/// Audio a real-time sink buffers ahead of what is heard.
+const BUFFER_SECONDS: f64 = 0.2;
+
+/// Destination of played audio: a sound device, a file or nothing.
+///
+/// Sinks are opened on the playback thread, so they don't need to be `Send`.
+pub trait AudioSink {
+    /// Queues interleaved samples, blocking while the output's buffer is full.
+    fn write(&mut self, samples: &[f32]) -> Result<()>;
+
+    /// Frames heard since the sink was opened or last cleared.
+    fn played_frames(&mut self) -> u64;
+
+    /// Drops the samples that were queued but not played yet, and starts
+    /// counting played frames from zero again.
+    fn clear(&mut self);
+
+    /// Stops or resumes taking samples from the queue.
+    fn set_paused(&mut self, paused: bool);
+}
+
+/// Opens a sink for audio with the given sample rate and channel count.
+pub type SinkFactory = Box<dyn FnOnce(u32, usize) -> Result<Box<dyn AudioSink>> + Send>;
+
+// ======================================================
+// Null and WAV sinks
+// ======================================================
+
+/// Discards the audio. In real time it plays at the speed a sound device
+/// would, otherwise everything written counts as played at once.
+pub struct NullSink {
+    sample_rate: u32,
+    channels: usize,
+    realtime: bool,
+    paused: bool,
+    written: u64,
+    played: f64,
+    last_tick: Option<Instant>,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32, channels: usize, realtime: bool) -> Self {
+        Self {
+            sample_rate,
+            channels: channels.max(1),
+            realtime,
+            paused: false,
+            written: 0,
+            played: 0.0,
+            last_tick: None,
+        }
+    }
+
+    /// Moves the played position on by the time since the last call.
+    fn tick(&mut self) {
+        if !self.realtime {
+            self.played = self.written as f64;
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused {
+                let frames = (now - last_tick).as_secs_f64() * self.sample_rate as f64;
+                self.played = (self.played + frames).min(self.written as f64);
+            }
+        }
+        self.last_tick = Some(now);
+    }
+}
+
+impl AudioSink for NullSink {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.tick();
+        self.written += (samples.len() / self.channels) as u64;
+        let buffer_frames = BUFFER_SECONDS * self.sample_rate as f64;
+        while self.realtime && self.written as f64 - self.played > buffer_frames {
+            thread::sleep(Duration::from_millis(5));
+            self.tick();
+        }
+        Ok(())
+    }
+
+    fn played_frames(&mut self) -> u64 {
+        self.tick();
+        self.played as u64
+    }
+
+    fn clear(&mut self) {
+        self.written = 0;
+        self.played = 0.0;
+        self.last_tick = None;
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.tick();
+        self.paused = paused;
+    }
+}
+
+/// Writes the audio to a 32-bit float WAV file, as fast as it comes.
+///
+/// Seeking clears nothing, the file simply continues with the new position.
+pub struct WavSink {
+    path: String,
+    writer: BufWriter<File>,
+    channels: usize,
+    data_bytes: u64,
+    played: u64,
+}
+
+impl WavSink {
+    pub fn create(path: &Path, sample_rate: u32, channels: usize) -> Result<Self> {
+        let context = || format!("cannot write {}", path.display());
+        let file = File::create(path).map_err(|e| Error::io(context(), e))?;
+        let mut sink = Self {
+            path: path.display().to_string(),
+            writer: BufWriter::new(file),
+            channels: channels.max(1),
+            data_bytes: 0,
+            played: 0,
+        };
+        sink.write_header(sample_rate)
+            .map_err(|e| Error::io(context(), e))?;
+        Ok(sink)
+    }
+
+    /// RIFF header for IEEE float samples. The sizes are patched in `finish`.
+    fn write_header(&mut self, sample_rate: u32) -> std::io::Result<()> {
+        let channels = self.channels as u16;
+        let block_align = channels * 4;
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(b"WAVEfmt ")?;
+        w.write_all(&18u32.to_le_bytes())?;
+        w.write_all(&3u16.to_le_bytes())?; // WAVE_FORMAT_IEEE_FLOAT
+        w.write_all(&channels.to_le_bytes())?;
+        w.write_all(&sample_rate.to_le_bytes())?;
+        w.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        w.write_all(&block_align.to_le_bytes())?;
+        w.write_all(&32u16.to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?;
+        w.write_all(b"data")?;
+        w.write_all(&0u32.to_le_bytes())
+    }
+
+    /// Fills in the chunk sizes and flushes the file.
+    pub fn finish(&mut self) -> Result<()> {
+        let data_bytes = self.data_bytes.min(u32::MAX as u64 - 46) as u32;
+        let patch = |w: &mut BufWriter<File>| -> std::io::Result<()> {
+            w.flush()?;
+            let file = w.get_mut();
+            file.seek(SeekFrom::Start(4))?;
+            file.write_all(&(data_bytes + 38).to_le_bytes())?;
+            file.seek(SeekFrom::Start(42))?;
+            file.write_all(&data_bytes.to_le_bytes())?;
+            file.seek(SeekFrom::End(0))?;
+            Ok(())
+        };
+        patch(&mut self.writer).map_err(|e| Error::io(format!("cannot write {}", self.path), e))
+    }
+}
+
+impl AudioSink for WavSink {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        for sample in samples {
+            self.writer
+                .write_all(&sample.to_le_bytes())
+                .map_err(|e| Error::io(format!("cannot write {}", self.path), e))?;
+        }
+        self.data_bytes += samples.len() as u64 * 4;
+        self.played += (samples.len() / self.channels) as u64;
+        Ok(())
+    }
+
+    fn played_frames(&mut self) -> u64 {
+        self.played
+    }
+
+    fn clear(&mut self) {
+        self.played = 0;
+    }
+
+    fn set_paused(&mut self, _paused: bool) {}
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+// ======================================================
+// Sound device (cpal)
+// ======================================================
+
+#[cfg(feature = "playback")]
+pub use device::CpalSink;
+
+#[cfg(feature = "playback")]
+mod device {
+    use super::{AudioSink, BUFFER_SECONDS};
+    use crate::error::{Error, Result};
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{FromSample, SampleFormat, SizedSample};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// State shared with the device's callback.
+    #[derive(Default)]
+    struct Shared {
+        /// Interleaved samples in the device's format.
+        queue: VecDeque<f32>,
+        /// Device frames taken from the queue.
+        played: u64,
+        paused: bool,
+        error: Option<String>,
+    }
+
+    /// Plays through the default output device of the system.
+    ///
+    /// Audio the device cannot take as it is gets its channels mapped and is
+    /// resampled linearly.
+    pub struct CpalSink {
+        _stream: cpal::Stream,
+        shared: Arc<Mutex<Shared>>,
+        source_channels: usize,
+        device_channels: usize,
+        /// Source frames per device frame.
+        ratio: f64,
+        capacity: usize,
+        resampler: Resampler,
+        converted: Vec<f32>,
+    }
+
+    fn output_error(reason: impl ToString) -> Error {
+        Error::AudioOutput(reason.to_string())
+    }
+
+    impl CpalSink {
+        pub fn open(sample_rate: u32, channels: usize) -> Result<Self> {
+            let device = cpal::default_host()
+                .default_output_device()
+                .ok_or_else(|| output_error("no output device"))?;
+
+            // The file's own format if the device supports it, else the device's default.
+            let exact = device
+                .supported_output_configs()
+                .map_err(output_error)?
+                .filter(|c| {
+                    c.channels() as usize == channels
+                        && (c.min_sample_rate().0..=c.max_sample_rate().0).contains(&sample_rate)
+                })
+                .max_by_key(|c| c.sample_format() == SampleFormat::F32)
+                .map(|c| c.with_sample_rate(cpal::SampleRate(sample_rate)));
+            let supported = match exact {
+                Some(config) => config,
+                None => device.default_output_config().map_err(output_error)?,
+            };
+            let config = supported.config();
+
+            let shared = Arc::new(Mutex::new(Shared::default()));
+            let stream = match supported.sample_format() {
+                SampleFormat::F32 => build_stream::<f32>(&device, &config, &shared),
+                SampleFormat::I16 => build_stream::<i16>(&device, &config, &shared),
+                SampleFormat::U16 => build_stream::<u16>(&device, &config, &shared),
+                SampleFormat::I32 => build_stream::<i32>(&device, &config, &shared),
+                format => {
+                    return Err(output_error(format!(
+                        "unsupported sample format {}",
+                        format
+                    )))
+                }
+            }?;
+            stream.play().map_err(output_error)?;
+
+            let device_channels = config.channels as usize;
+            let device_rate = config.sample_rate.0;
+            Ok(Self {
+                _stream: stream,
+                shared,
+                source_channels: channels.max(1),
+                device_channels,
+                ratio: sample_rate as f64 / device_rate as f64,
+                capacity: (BUFFER_SECONDS * device_rate as f64) as usize * device_channels,
+                resampler: Resampler::new(sample_rate as f64 / device_rate as f64),
+                converted: Vec::new(),
+            })
+        }
+
+        fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+            self.shared.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    fn build_stream<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        shared: &Arc<Mutex<Shared>>,
+    ) -> Result<cpal::Stream> {
+        let channels = config.channels as usize;
+        let data_shared = shared.clone();
+        let error_shared = shared.clone();
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _| {
+                    let Ok(mut shared) = data_shared.lock() else {
+                        return;
+                    };
+                    let mut taken = 0;
+                    for sample in data.iter_mut() {
+                        let value = match shared.paused {
+                            true => None,
+                            false => shared.queue.pop_front(),
+                        };
+                        taken += value.is_some() as u64;
+                        *sample = value.map_or(T::EQUILIBRIUM, T::from_sample);
+                    }
+                    shared.played += taken / channels as u64;
+                },
+                move |e| {
+                    if let Ok(mut shared) = error_shared.lock() {
+                        shared.error = Some(e.to_string());
+                    }
+                },
+                None,
+            )
+            .map_err(output_error)
+    }
+
+    impl AudioSink for CpalSink {
+        fn write(&mut self, samples: &[f32]) -> Result<()> {
+            let mapped = map_channels(samples, self.source_channels, self.device_channels);
+            self.converted.clear();
+            self.resampler
+                .process(&mapped, self.device_channels, &mut self.converted);
+
+            let mut pending = &self.converted[..];
+            while !pending.is_empty() {
+                let mut shared = self.lock();
+                if let Some(error) = shared.error.take() {
+                    return Err(output_error(error));
+                }
+                let room = self.capacity.saturating_sub(shared.queue.len());
+                let n = room.min(pending.len());
+                shared.queue.extend(&pending[..n]);
+                pending = &pending[n..];
+                drop(shared);
+                if !pending.is_empty() {
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+            Ok(())
+        }
+
+        fn played_frames(&mut self) -> u64 {
+            (self.lock().played as f64 * self.ratio) as u64
+        }
+
+        fn clear(&mut self) {
+            let mut shared = self.lock();
+            shared.queue.clear();
+            shared.played = 0;
+            drop(shared);
+            self.resampler = Resampler::new(self.ratio);
+        }
+
+        fn set_paused(&mut self, paused: bool) {
+            self.lock().paused = paused;
+        }
+    }
+
+    /// Copies the source channels onto the device's: mono is spread to every
+    /// channel, a mono device gets the average, extra channels stay silent.
+    fn map_channels(samples: &[f32], source: usize, device: usize) -> Vec<f32> {
+        if source == device {
+            return samples.to_vec();
+        }
+        let mut mapped = Vec::with_capacity(samples.len() / source * device);
+        for frame in samples.chunks_exact(source) {
+            for channel in 0..device {
+                mapped.push(match (source, device) {
+                    (1, _) => frame[0],
+                    (_, 1) => frame.iter().sum::<f32>() / source as f32,
+                    _ => frame.get(channel).copied().unwrap_or(0.0),
+                });
+            }
+        }
+        mapped
+    }
+
+    /// Linear interpolation from one sample rate to another, across blocks.
+    struct Resampler {
+        /// Source frames per output frame.
+        step: f64,
+        /// Position of the next output frame, in frames of the coming block;
+        /// -1 is the last frame of the previous block.
+        position: f64,
+        previous: Vec<f32>,
+    }
+
+    impl Resampler {
+        fn new(step: f64) -> Self {
+            Self {
+                step,
+                position: 0.0,
+                previous: Vec::new(),
+            }
+        }
+
+        fn process(&mut self, input: &[f32], channels: usize, output: &mut Vec<f32>) {
+            if self.step == 1.0 {
+                output.extend_from_slice(input);
+                return;
+            }
+            if self.previous.len() != channels {
+                self.previous = vec![0.0; channels];
+            }
+            let frames = input.len() / channels;
+            let frame = |i: isize| match i {
+                -1 => &self.previous[..],
+                i => &input[i as usize * channels..(i as usize + 1) * channels],
+            };
+            while self.position + 1.0 < frames as f64 {
+                let index = self.position.floor();
+                let fraction = (self.position - index) as f32;
+                let (a, b) = (frame(index as isize), frame(index as isize + 1));
+                output.extend(a.iter().zip(b).map(|(a, b)| a + (b - a) * fraction));
+                self.position += self.step;
+            }
+            if frames > 0 {
+                self.previous = frame(frames as isize - 1).to_vec();
+                self.position -= frames as f64;
+            }
+        }
+    }
+}
+
+// ======================================================
+// Player
+// ======================================================
+
+enum Command {
+    Play,
+    Pause,
+    Seek(f64),
+}
+
+/// What the playback thread reports back.
+#[derive(Default)]
+struct Status {
+    position: f64,
+    playing: bool,
+    error: Option<Error>,
+    /// Commands sent but not handled yet. Until they are, the position and
+    /// state the player set when sending them are kept.
+    pending: usize,
+}
+
+/// Plays a file on a background thread that owns the decoder and the sink.
+///
+/// The player starts paused at the beginning. Dropping it stops the thread.
+pub struct Player {
+    input_path: String,
+    commands: Sender<Command>,
+    status: Arc<Mutex<Status>>,
+}
+
+impl Player {
+    pub fn new(input_path: &str, open_sink: SinkFactory) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let status = Arc::new(Mutex::new(Status::default()));
+
+        let thread_status = status.clone();
+        let path = input_path.to_string();
+        thread::spawn(move || {
+            let result = (|| {
+                let decoder = decoder::open(&path)?;
+                let info = decoder.info().clone();
+                let sink = open_sink(info.sample_rate, info.channels as usize)?;
+                run(decoder, sink, &receiver, &thread_status)
+            })();
+            let mut status = lock(&thread_status);
+            status.playing = false;
+            if let Err(e) = result {
+                status.error = Some(e);
+            }
+        });
+
+        Self {
+            input_path: input_path.to_string(),
+            commands,
+            status,
+        }
+    }
+
+    /// File being played.
+    pub fn input_path(&self) -> &str {
+        &self.input_path
+    }
+
+    pub fn play(&self) {
+        self.send(Command::Play, |status| status.playing = true);
+    }
+
+    pub fn pause(&self) {
+        self.send(Command::Pause, |status| status.playing = false);
+    }
+
+    /// Continues playback at `seconds` from the start of the file.
+    pub fn seek(&self, seconds: f64) {
+        let seconds = seconds.max(0.0);
+        self.send(Command::Seek(seconds), |status| status.position = seconds);
+    }
+
+    /// Sends `command`, showing its outcome in the status right away.
+    fn send(&self, command: Command, expected: impl FnOnce(&mut Status)) {
+        let mut status = lock(&self.status);
+        if self.commands.send(command).is_ok() {
+            status.pending += 1;
+            expected(&mut status);
+        }
+    }
+
+    /// Position of what is being heard, in seconds from the start of the file.
+    pub fn position(&self) -> f64 {
+        lock(&self.status).position
+    }
+
+    pub fn is_playing(&self) -> bool {
+        lock(&self.status).playing
+    }
+
+    /// Why playback stopped, reported once.
+    pub fn take_error(&self) -> Option<Error> {
+        lock(&self.status).error.take()
+    }
+}
+
+fn lock(status: &Mutex<Status>) -> std::sync::MutexGuard<'_, Status> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Body of the playback thread, returns when the player is dropped.
+fn run(
+    mut decoder: Box<dyn decoder::AudioDecoder>,
+    mut sink: Box<dyn AudioSink>,
+    commands: &mpsc::Receiver<Command>,
+    status: &Mutex<Status>,
+) -> Result<()> {
+    let info = decoder.info().clone();
+    let channels = (info.channels as usize).max(1);
+    let sample_rate = info.sample_rate as f64;
+
+    let mut buffer = Vec::new();
+    let mut start = 0.0;
+    let mut written = 0u64;
+    let mut playing = false;
+    let mut end_of_file = false;
+    sink.set_paused(true);
+
+    loop {
+        // Don't wait for commands while there is audio to decode.
+        let command = if playing && !end_of_file {
+            commands.try_recv().map_err(|e| match e {
+                mpsc::TryRecvError::Empty => RecvTimeoutError::Timeout,
+                mpsc::TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            })
+        } else {
+            commands.recv_timeout(IDLE_POLL)
+        };
+        match command {
+            Ok(Command::Play) => {
+                if end_of_file && sink.played_frames() >= written {
+                    // Played to the end, start over.
+                    decoder.seek(0.0)?;
+                    sink.clear();
+                    (start, written, end_of_file) = (0.0, 0, false);
+                }
+                playing = true;
+                sink.set_paused(false);
+            }
+            Ok(Command::Pause) => {
+                playing = false;
+                sink.set_paused(true);
+            }
+            Ok(Command::Seek(seconds)) => {
+                let seconds = seconds.min(info.duration);
+                decoder.seek(seconds)?;
+                sink.clear();
+                (start, written, end_of_file) = (seconds, 0, false);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        if command.is_ok() {
+            lock(status).pending -= 1;
+        }
+
+        if playing && !end_of_file {
+            if decoder.next_block(&mut buffer)? {
+                sink.write(&buffer)?;
+                written += (buffer.len() / channels) as u64;
+            } else {
+                end_of_file = true;
+            }
+        }
+
+        let played = sink.played_frames();
+        if playing && end_of_file && played >= written {
+            playing = false;
+            sink.set_paused(true);
+        }
+        let mut status = lock(status);
+        if status.pending == 0 {
+            status.position = (start + played as f64 / sample_rate).min(info.duration);
+            status.playing = playing;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempFile;
+
+    const SAMPLE_RATE: u32 = 8_000;
+
+    /// Writes `frames` frames of a stereo ramp through a `WavSink`, in blocks.
+    fn write_ramp(path: &Path, frames: usize) -> Vec<f32> {
+        let samples: Vec<f32> = (0..frames * 2)
+            .map(|i| (i % 200) as f32 / 100.0 - 1.0)
+            .collect();
+        let mut sink = WavSink::create(path, SAMPLE_RATE, 2).unwrap();
+        for block in samples.chunks(300) {
+            sink.write(block).unwrap();
+        }
+        assert_eq!(sink.played_frames(), frames as u64);
+        sink.finish().unwrap();
+        samples
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Waits up to five seconds for `condition`.
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    fn null_sink() -> SinkFactory {
+        Box::new(|sample_rate, channels| {
+            Ok(Box::new(NullSink::new(sample_rate, channels, false)) as Box<dyn AudioSink>)
+        })
+    }
+
+    #[test]
+    fn wav_sink_patches_the_chunk_sizes() {
+        let file = TempFile::new("sizes.wav");
+        write_ramp(file.path(), 1000);
+        let bytes = std::fs::read(file.path()).unwrap();
+
+        let data_bytes = 1000 * 2 * 4;
+        assert_eq!(bytes.len(), 46 + data_bytes);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[38..42], b"data");
+        assert_eq!(u32_at(&bytes, 42) as usize, data_bytes);
+    }
+
+    #[test]
+    fn wav_sink_output_decodes_to_the_written_samples() {
+        let file = TempFile::new("roundtrip.wav");
+        let written = write_ramp(file.path(), 1000);
+        let mut decoder = decoder::open(file.path_str()).unwrap();
+        assert_eq!(decoder.info().sample_rate, SAMPLE_RATE);
+        assert_eq!(decoder.info().channels, 2);
+
+        let (mut decoded, mut block) = (Vec::new(), Vec::new());
+        while decoder.next_block(&mut block).unwrap() {
+            decoded.extend_from_slice(&block);
+        }
+        assert_eq!(decoded, written);
+    }
+
+    #[test]
+    fn player_plays_seeks_and_stops_at_the_end() {
+        let file = TempFile::new("player.wav");
+        write_ramp(file.path(), SAMPLE_RATE as usize);
+        let player = Player::new(file.path_str(), null_sink());
+
+        // Starts paused at the beginning.
+        assert!(!player.is_playing());
+        assert_eq!(player.position(), 0.0);
+
+        // A non-realtime sink plays everything at once, up to the end of the file.
+        player.play();
+        assert!(wait_for(|| !player.is_playing()));
+        assert!(
+            (player.position() - 1.0).abs() < 1e-6,
+            "{}",
+            player.position()
+        );
+
+        // A seek while stopped moves the position without playing.
+        player.seek(0.25);
+        assert_eq!(player.position(), 0.25);
+        thread::sleep(Duration::from_millis(50));
+        assert!(!player.is_playing());
+        assert_eq!(player.position(), 0.25);
+
+        // Playing from there ends at the end again.
+        player.play();
+        assert!(wait_for(|| !player.is_playing()));
+        assert!((player.position() - 1.0).abs() < 1e-6);
+        assert!(player.take_error().is_none());
+    }
+
+    #[test]
+    fn player_reports_unreadable_files() {
+        let player = Player::new("/nonexistent/file.wav", null_sink());
+        assert!(wait_for(|| player.take_error().is_some()));
+        assert!(!player.is_playing());
+    }
+}
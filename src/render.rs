@@ -0,0 +1,762 @@
+use crate::align::{self, Alignment};
+use crate::analysis::{self, AnalysisReport};
+use crate::error::{Error, Result};
+use crate::frequency::{self, FrequencyAxis};
+use crate::legend::{self, LevelScale};
+use crate::settings::{
+    AppSettings, CompareLayout, FrequencyScale, SpectrogramBackend, SpectrogramView, StereoView,
+};
+use crate::spectrogram::{self, DbRange, MagnitudeMatrix, MIN_DB};
+use crate::utils::{self, AudioInfo};
+use image::RgbaImage;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Spectrogram size used when neither a PNG size nor a custom resolution is set.
+pub const DEFAULT_SIZE: (u32, u32) = (500, 320);
+
+/// One file to render with one set of settings.
+#[derive(Debug, Clone)]
+pub struct RenderRequest {
+    pub input_path: String,
+    pub settings: AppSettings,
+    /// Size of the spectrogram itself; the legend adds its margins around it.
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderRequest {
+    /// Takes the size from `png_width`/`png_height`, then from the custom
+    /// resolution, then `DEFAULT_SIZE`.
+    pub fn new(input_path: impl Into<String>, settings: AppSettings) -> Self {
+        let (width, height) = if settings.png_width > 0 && settings.png_height > 0 {
+            (settings.png_width, settings.png_height)
+        } else if settings.custom_resolution {
+            (settings.resolution[0], settings.resolution[1])
+        } else {
+            DEFAULT_SIZE
+        };
+        Self {
+            input_path: input_path.into(),
+            settings,
+            width,
+            height,
+        }
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Renders only `start` to `end` seconds of the file, `end` 0 meaning its end.
+    pub fn with_time_range(mut self, start: f64, end: f64) -> Self {
+        self.settings.start_time = start;
+        self.settings.end_time = end;
+        self
+    }
+}
+
+/// A finished render and what was learned about the file on the way.
+#[derive(Debug, Clone)]
+pub struct Rendering {
+    /// The spectrogram, inside its legend when `settings.legend` is set.
+    pub image: RgbaImage,
+    pub audio_info: Option<AudioInfo>,
+    /// `None` when analysis is disabled or failed.
+    pub analysis: Option<AnalysisReport>,
+    /// Levels the palette spans, measured from the file with `auto_db_range`.
+    pub db_range: DbRange,
+    /// How the second file lined up with the first, for difference comparisons.
+    pub alignment: Option<Alignment>,
+}
+
+/// Renders spectrograms to images without a window.
+#[derive(Debug, Clone, Default)]
+pub struct Renderer {
+    cancel_token: Arc<AtomicBool>,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Setting the token makes a running `render` return `Error::Cancelled`.
+    pub fn cancel_token(&self) -> Arc<AtomicBool> {
+        self.cancel_token.clone()
+    }
+
+    pub fn render(&self, request: &RenderRequest) -> Result<Rendering> {
+        let settings = &request.settings;
+        let info = utils::get_audio_info(&request.input_path)?;
+        // Only the rendered part, a short excerpt of a long file shouldn't decode all of it.
+        let (start, end) = settings.time_range(info.duration);
+
+        // ffmpeg never draws its own legend here, the custom one is used instead.
+        let mut spectrogram_settings = settings.clone();
+        spectrogram_settings.legend = false;
+        let (spectrogram, db_range, analysis) =
+            if utils::effective_backend(&spectrogram_settings) == SpectrogramBackend::Native {
+                // The analysers share the decode of the native engine.
+                let mut analyzer = settings
+                    .analyze_audio
+                    .then(|| analysis::FileAnalyzer::new(&info, start));
+                let matrix = utils::generate_magnitudes(
+                    &request.input_path,
+                    &spectrogram_settings,
+                    request.width,
+                    request.height,
+                    analyzer.as_mut(),
+                    self.cancel_token.clone(),
+                )?;
+                (
+                    spectrogram::colorize(&matrix, &spectrogram_settings),
+                    DbRange::for_matrix(&matrix, &spectrogram_settings),
+                    analyzer.map(analysis::FileAnalyzer::finish),
+                )
+            } else {
+                let analysis = match settings.analyze_audio {
+                    true => match analysis::analyze_range(
+                        &request.input_path,
+                        start,
+                        end,
+                        self.cancel_token.clone(),
+                    ) {
+                        Ok(report) => Some(report),
+                        Err(Error::Cancelled) => return Err(Error::Cancelled),
+                        // A failed analysis only leaves its findings out of the legend.
+                        Err(e) => {
+                            eprintln!("Analysis failed: {}", e);
+                            None
+                        }
+                    },
+                    false => None,
+                };
+                let (image, db_range) = utils::generate_spectrogram_in_memory(
+                    &request.input_path,
+                    &info,
+                    &spectrogram_settings,
+                    request.width,
+                    request.height,
+                    self.cancel_token.clone(),
+                )?;
+                (image, db_range, analysis)
+            };
+        let audio_info = Some(info);
+
+        let image = if settings.legend {
+            let mut image = draw_legend(
+                &request.input_path,
+                settings,
+                request.width,
+                request.height,
+                audio_info.clone(),
+                analysis.as_ref(),
+                db_range,
+            )?;
+            composite(&mut image, &spectrogram);
+            image
+        } else {
+            spectrogram
+        };
+
+        Ok(Rendering {
+            image,
+            audio_info,
+            analysis,
+            db_range,
+            alignment: None,
+        })
+    }
+
+    /// Renders `request` and `other_path` with the same settings, size and
+    /// levels into one image, arranged by `settings.compare_layout`.
+    ///
+    /// The analysis is skipped, the legend has no room for two reports.
+    pub fn render_comparison(
+        &self,
+        request: &RenderRequest,
+        other_path: &str,
+    ) -> Result<Rendering> {
+        let comparison = render_comparison_spectrograms(
+            &request.input_path,
+            other_path,
+            &request.settings,
+            request.width,
+            request.height,
+            self.cancel_token.clone(),
+        )?;
+        let image = compose_comparison(
+            &comparison,
+            &request.settings,
+            request.settings.compare_layout,
+            false,
+        )?
+        .image;
+
+        Ok(Rendering {
+            image,
+            audio_info: comparison.audio_info[0].clone(),
+            analysis: None,
+            db_range: comparison.db_range,
+            alignment: comparison
+                .difference
+                .as_ref()
+                .map(|difference| difference.alignment),
+        })
+    }
+}
+
+// ======================================================
+// Comparison
+// ======================================================
+
+/// Spectrograms of two files rendered for comparison, without legend.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub input_paths: [String; 2],
+    pub audio_info: [Option<AudioInfo>; 2],
+    pub spectrograms: [RgbaImage; 2],
+    /// Levels of the native engine, for reading them back.
+    pub magnitudes: Option<[MagnitudeMatrix; 2]>,
+    /// Levels the palette spans in both.
+    pub db_range: DbRange,
+    /// Rendered for the difference layout only.
+    pub difference: Option<Difference>,
+}
+
+/// Level difference between the two files of a comparison.
+#[derive(Debug, Clone)]
+pub struct Difference {
+    /// B minus A in dB, `NAN` where B has no audio after the shift.
+    pub matrix: MagnitudeMatrix,
+    /// B is shifted by `alignment.shift()` to line up with A.
+    pub alignment: Alignment,
+}
+
+/// A composed comparison and where its plots are.
+#[derive(Debug, Clone)]
+pub struct ComparisonImage {
+    pub image: RgbaImage,
+    /// Index of the file (0 for A, 1 for B) and top-left pixel of each
+    /// plot shown; every plot is as large as one spectrogram.
+    pub plots: Vec<(usize, (u32, u32))>,
+}
+
+/// Settings the second file of a comparison is rendered with: the first
+/// file's time range and, when it was fitted to the first file, level range.
+pub fn comparison_settings(
+    settings: &AppSettings,
+    audio_info: Option<&AudioInfo>,
+    db_range: DbRange,
+) -> AppSettings {
+    let mut settings = settings.clone();
+    if let Some(info) = audio_info {
+        (settings.start_time, settings.end_time) = settings.time_range(info.duration);
+    }
+    if settings.auto_db_range {
+        let gain_db = 20.0 * settings.gain.max(1e-6).log10();
+        settings.auto_db_range = false;
+        settings.db_floor = db_range.floor + gain_db;
+        settings.db_ceiling = db_range.ceiling + gain_db;
+    }
+    settings
+}
+
+/// Settings both files of a difference comparison are rendered with, so
+/// their levels can be subtracted pixel by pixel: always the native engine,
+/// channels split only when both show as many, and the frequency band of the
+/// lower sample rate.
+pub fn difference_settings(
+    settings: &AppSettings,
+    audio_info: &[Option<AudioInfo>; 2],
+) -> AppSettings {
+    let mut settings = settings.clone();
+    settings.backend = SpectrogramBackend::Native;
+    if let [Some(first), Some(second)] = audio_info {
+        if first.channel_mixes(&settings).len() != second.channel_mixes(&settings).len() {
+            settings.split_channels = false;
+            settings.stereo_view = StereoView::Channels;
+        }
+        let nyquist = first.sample_rate.min(second.sample_rate) as f32 / 2.0;
+        if first.sample_rate != second.sample_rate
+            && (settings.freq_max_hz <= 0.0 || settings.freq_max_hz > nyquist)
+        {
+            settings.freq_max_hz = nyquist;
+        }
+    }
+    settings
+}
+
+/// Renders the spectrograms of `first_path` and `second_path` at
+/// `width`×`height` each, the second following the first's time and level
+/// range so the two line up.
+///
+/// For the difference layout the second file is also shifted in time to
+/// line up with the first, and the difference of their levels is kept.
+pub fn render_comparison_spectrograms(
+    first_path: &str,
+    second_path: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    cancel_token: Arc<AtomicBool>,
+) -> Result<Comparison> {
+    let first_info = utils::get_audio_info(first_path)?;
+    let second_info = utils::get_audio_info(second_path)?;
+
+    let audio_info = [Some(first_info.clone()), Some(second_info.clone())];
+    let with_difference = settings.compare_layout == CompareLayout::Difference;
+
+    // The legend is drawn around both, ffmpeg must not draw its own.
+    let mut first_settings = match with_difference {
+        true => difference_settings(settings, &audio_info),
+        false => settings.clone(),
+    };
+    first_settings.legend = false;
+    first_settings.horizontal = false;
+
+    let mut difference = None;
+    let (spectrograms, magnitudes, db_range) =
+        if utils::effective_backend(&first_settings) == SpectrogramBackend::Native {
+            let first = utils::generate_magnitudes(
+                first_path,
+                &first_settings,
+                width,
+                height,
+                None,
+                cancel_token.clone(),
+            )?;
+            let db_range = DbRange::for_matrix(&first, &first_settings);
+            let second_settings = comparison_settings(&first_settings, Some(&first_info), db_range);
+            let second = if with_difference {
+                let alignment = align::find_offset(
+                    first_path,
+                    second_path,
+                    second_settings.start_time,
+                    cancel_token.clone(),
+                )?;
+                if !alignment.is_reliable() {
+                    eprintln!(
+                        "{} and {} do not seem to hold the same audio, comparing them unshifted",
+                        first_path, second_path
+                    );
+                }
+                let (second, columns) = shifted_magnitudes(
+                    second_path,
+                    &second_settings,
+                    second_info.duration,
+                    alignment.shift(),
+                    width,
+                    height,
+                    cancel_token,
+                )?;
+                difference = Some(Difference {
+                    matrix: difference_matrix(&first, &second, columns, db_range.floor),
+                    alignment,
+                });
+                second
+            } else {
+                utils::generate_magnitudes(
+                    second_path,
+                    &second_settings,
+                    width,
+                    height,
+                    None,
+                    cancel_token,
+                )?
+            };
+            (
+                [
+                    spectrogram::colorize(&first, &first_settings),
+                    spectrogram::colorize(&second, &second_settings),
+                ],
+                Some([first, second]),
+                db_range,
+            )
+        } else {
+            let (first, db_range) = utils::generate_spectrogram_in_memory(
+                first_path,
+                &first_info,
+                &first_settings,
+                width,
+                height,
+                cancel_token.clone(),
+            )?;
+            let second_settings = comparison_settings(&first_settings, Some(&first_info), db_range);
+            let (second, _) = utils::generate_spectrogram_in_memory(
+                second_path,
+                &second_info,
+                &second_settings,
+                width,
+                height,
+                cancel_token,
+            )?;
+            ([first, second], None, db_range)
+        };
+
+    Ok(Comparison {
+        input_paths: [first_path.to_string(), second_path.to_string()],
+        audio_info,
+        spectrograms,
+        magnitudes,
+        db_range,
+        difference,
+    })
+}
+
+/// Levels of `input_path` over the explicit time range of `settings`, read
+/// `shift` seconds later in the file. Only the returned columns hold audio,
+/// the ones before the start or past the `duration` of the file are silent.
+fn shifted_magnitudes(
+    input_path: &str,
+    settings: &AppSettings,
+    duration: f64,
+    shift: f64,
+    width: u32,
+    height: u32,
+    cancel_token: Arc<AtomicBool>,
+) -> Result<(MagnitudeMatrix, Range<usize>)> {
+    let (start, end) = (settings.start_time, settings.end_time);
+    if shift == 0.0 || end <= start {
+        let matrix =
+            utils::generate_magnitudes(input_path, settings, width, height, None, cancel_token)?;
+        return Ok((matrix, 0..width as usize));
+    }
+
+    let (shifted_start, shifted_end) = ((start + shift).max(0.0), (end + shift).min(duration));
+    let column = |seconds: f64| {
+        let fraction = (seconds - shift - start) / (end - start);
+        ((fraction * width as f64).round().max(0.0) as usize).min(width as usize)
+    };
+    let columns = column(shifted_start)..column(shifted_end);
+    if columns.is_empty() {
+        return Err(Error::EmptyTimeRange {
+            path: input_path.to_string(),
+            start: shifted_start,
+            end: shifted_end,
+        });
+    }
+
+    let mut shifted_settings = settings.clone();
+    shifted_settings.start_time = shifted_start;
+    shifted_settings.end_time = shifted_end;
+    let part = utils::generate_magnitudes(
+        input_path,
+        &shifted_settings,
+        columns.len() as u32,
+        height,
+        None,
+        cancel_token,
+    )?;
+
+    let width = width as usize;
+    let mut db = vec![MIN_DB; part.channels * part.height * width];
+    for (line, levels) in part.db.chunks_exact(part.width).enumerate() {
+        db[line * width + columns.start..line * width + columns.end].copy_from_slice(levels);
+    }
+    Ok((MagnitudeMatrix { width, db, ..part }, columns))
+}
+
+/// `second` minus `first` in dB, both held at `floor` or above so bins
+/// silent in both do not differ by their noise. Columns outside
+/// `second_columns` are `NAN`.
+fn difference_matrix(
+    first: &MagnitudeMatrix,
+    second: &MagnitudeMatrix,
+    second_columns: Range<usize>,
+    floor: f32,
+) -> MagnitudeMatrix {
+    let floor = floor.max(MIN_DB);
+    let mut db = Vec::with_capacity(first.db.len());
+    for channel in 0..first.channels {
+        // Shapes match unless the channel layouts differ, then B's last channel is used.
+        let second_channel = channel.min(second.channels - 1);
+        for row in 0..first.height {
+            let second_row = row * second.height / first.height.max(1);
+            for column in 0..first.width {
+                if !second_columns.contains(&column) || column >= second.width {
+                    db.push(f32::NAN);
+                    continue;
+                }
+                let a = first.get(channel, row, column).max(floor);
+                let b = second.get(second_channel, second_row, column).max(floor);
+                db.push(b - a);
+            }
+        }
+    }
+    MagnitudeMatrix {
+        width: first.width,
+        height: first.height,
+        channels: first.channels,
+        sample_rate: first.sample_rate,
+        db,
+    }
+}
+
+/// Arranges a comparison in `layout`, inside legends when `settings.legend`
+/// is set. The flip layout shows B when `show_second` is set, otherwise A.
+pub fn compose_comparison(
+    comparison: &Comparison,
+    settings: &AppSettings,
+    layout: CompareLayout,
+    show_second: bool,
+) -> Result<ComparisonImage> {
+    let [first, _] = &comparison.spectrograms;
+    let (width, height) = first.dimensions();
+    let (left, top) = match settings.legend {
+        true => (legend::LEFT_MARGIN, legend::TOP_MARGIN),
+        false => (0, 0),
+    };
+
+    // One file in a frame of its own, with its legend.
+    let single = |index: usize| -> Result<RgbaImage> {
+        let spectrogram = &comparison.spectrograms[index];
+        if !settings.legend {
+            return Ok(spectrogram.clone());
+        }
+        // B's legend shows its own stream with the time range it was rendered for.
+        let file_settings = match index {
+            0 => settings.clone(),
+            _ => comparison_settings(
+                settings,
+                comparison.audio_info[0].as_ref(),
+                comparison.db_range,
+            ),
+        };
+        let mut image = draw_legend(
+            &comparison.input_paths[index],
+            &file_settings,
+            width,
+            height,
+            comparison.audio_info[index].clone(),
+            None,
+            comparison.db_range,
+        )?;
+        composite(&mut image, spectrogram);
+        Ok(image)
+    };
+
+    let (mut image, plots) = match layout {
+        CompareLayout::Stacked => {
+            let mut image = match settings.legend {
+                true => draw_legend_template(
+                    &format!(
+                        "A: {}   B: {}",
+                        file_name(&comparison.input_paths[0]),
+                        file_name(&comparison.input_paths[1])
+                    ),
+                    settings,
+                    width,
+                    height * 2,
+                    2,
+                    comparison.audio_info[0].clone(),
+                    None,
+                    dbfs_levels(settings, comparison.db_range),
+                )?,
+                false => RgbaImage::new(width, height * 2),
+            };
+            for (index, spectrogram) in comparison.spectrograms.iter().enumerate() {
+                image::imageops::replace(
+                    &mut image,
+                    spectrogram,
+                    left as i64,
+                    (top + height * index as u32) as i64,
+                );
+            }
+            (image, vec![(0, (left, top)), (1, (left, top + height))])
+        }
+        CompareLayout::SideBySide => {
+            let (a, b) = (single(0)?, single(1)?);
+            let mut image = RgbaImage::new(a.width() * 2, a.height());
+            image::imageops::replace(&mut image, &a, 0, 0);
+            image::imageops::replace(&mut image, &b, a.width() as i64, 0);
+            (image, vec![(0, (left, top)), (1, (a.width() + left, top))])
+        }
+        CompareLayout::Flip => {
+            let index = show_second as usize;
+            (single(index)?, vec![(index, (left, top))])
+        }
+        CompareLayout::Difference => {
+            // Rendered for another layout, there is only A to show.
+            let Some(difference) = &comparison.difference else {
+                return compose_comparison(comparison, settings, CompareLayout::Flip, false);
+            };
+            let range = settings.clamped_difference_range();
+            let plot =
+                spectrogram::colorize_difference(&difference.matrix, range, settings.saturation);
+            let image = match settings.legend {
+                true => {
+                    let shift = match difference.alignment.is_reliable() {
+                        true => format!(
+                            "B shifted by {:+.1} ms",
+                            difference.alignment.offset * 1000.0
+                        ),
+                        false => "B not aligned".to_string(),
+                    };
+                    let mut image = draw_legend_template(
+                        &format!(
+                            "B − A: {} − {}, {}",
+                            file_name(&comparison.input_paths[1]),
+                            file_name(&comparison.input_paths[0]),
+                            shift
+                        ),
+                        &difference_settings(settings, &comparison.audio_info),
+                        width,
+                        height,
+                        1,
+                        comparison.audio_info[0].clone(),
+                        None,
+                        LevelScale::Difference(range),
+                    )?;
+                    composite(&mut image, &plot);
+                    image
+                }
+                false => plot,
+            };
+            (image, vec![(0, (left, top))])
+        }
+    };
+
+    for &(index, (x, y)) in &plots {
+        let label = match layout {
+            CompareLayout::Difference => "B − A",
+            _ => ["A", "B"][index],
+        };
+        legend::draw_comparison_label(&mut image, x, y, label)?;
+    }
+    Ok(ComparisonImage { image, plots })
+}
+
+/// Draws the legend template for a `width`×`height` spectrogram of `input_path`
+/// whose palette spans `db_range`.
+pub fn draw_legend(
+    input_path: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    audio_info: Option<AudioInfo>,
+    analysis: Option<&AnalysisReport>,
+    db_range: DbRange,
+) -> Result<RgbaImage> {
+    draw_legend_template(
+        file_name(input_path),
+        settings,
+        width,
+        height,
+        1,
+        audio_info,
+        analysis,
+        dbfs_levels(settings, db_range),
+    )
+}
+
+fn dbfs_levels(settings: &AppSettings, db_range: DbRange) -> LevelScale {
+    LevelScale::Dbfs {
+        color_scheme: settings.color_scheme,
+        range: db_range,
+        scale: settings.scale,
+    }
+}
+
+fn file_name(input_path: &str) -> &str {
+    Path::new(input_path)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Unknown File")
+}
+
+/// Legend titled `title` around `files` spectrograms stacked in a
+/// `width`×`height` plot.
+#[allow(clippy::too_many_arguments)]
+fn draw_legend_template(
+    title: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    files: u32,
+    audio_info: Option<AudioInfo>,
+    analysis: Option<&AnalysisReport>,
+    levels: LevelScale,
+) -> Result<RgbaImage> {
+    let mut settings_text = format!(
+        "{}, {}, {}",
+        settings.win_func, settings.scale, settings.color_scheme
+    );
+    match settings.view {
+        SpectrogramView::ConstantQ => settings_text.push_str(&format!(
+            ", Constant-Q {} bins/octave",
+            settings.cqt_bins_per_octave
+        )),
+        SpectrogramView::Stft if settings.frequency_scale != FrequencyScale::Linear => {
+            settings_text.push_str(&format!(", {} frequency axis", settings.frequency_scale))
+        }
+        SpectrogramView::Stft => {}
+    }
+    let frequency_axis = audio_info
+        .as_ref()
+        .map(|info| FrequencyAxis::for_settings(settings, info.sample_rate));
+    let band_set = settings.freq_min_hz > 0.0 || settings.freq_max_hz > 0.0;
+    if let Some(axis) =
+        frequency_axis.filter(|_| settings.view == SpectrogramView::Stft && band_set)
+    {
+        settings_text.push_str(&format!(
+            ", {} to {}",
+            frequency::format_hz(axis.min_hz),
+            frequency::format_hz(axis.max_hz)
+        ));
+    }
+
+    let stereo_view = audio_info
+        .as_ref()
+        .map_or(StereoView::Channels, |info| info.stereo_view(settings));
+    match stereo_view {
+        StereoView::Mid => settings_text.push_str(", Mid (L+R)/2"),
+        StereoView::Side => settings_text.push_str(", Side (L−R)/2"),
+        StereoView::LeftRightSide => settings_text.push_str(", L/R + Side (L−R)/2"),
+        StereoView::Channels => {}
+    }
+
+    let time_range = audio_info
+        .as_ref()
+        .map(|info| settings.time_range(info.duration));
+    let (channels, channel_names) = match &audio_info {
+        Some(info) => (
+            info.stacked_channels(settings),
+            info.stacked_channel_names(settings),
+        ),
+        None => (1, Vec::new()),
+    };
+
+    legend::draw_legend(
+        width,
+        height,
+        title,
+        &settings_text,
+        audio_info,
+        settings.saturation,
+        channels * files,
+        &channel_names,
+        settings.show_version_in_legend,
+        analysis,
+        settings.correlation_strip,
+        frequency_axis,
+        time_range,
+        levels,
+    )
+}
+
+/// Copies a spectrogram into the plot area of a legend template.
+pub fn composite(legend_image: &mut RgbaImage, spectrogram: &RgbaImage) {
+    for (x, y, pixel) in spectrogram.enumerate_pixels() {
+        let dest_x = x + legend::LEFT_MARGIN;
+        let dest_y = y + legend::TOP_MARGIN;
+        if dest_x < legend_image.width() && dest_y < legend_image.height() {
+            legend_image.put_pixel(dest_x, dest_y, *pixel);
+        }
+    }
+}
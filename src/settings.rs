@@ -1,6 +1,8 @@
+use crate::error::{self, Error};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 // ======================================================
 // Core enums – OWNED by settings
@@ -14,22 +16,133 @@ pub enum SpectrogramColorScheme {
     Rainbow,
 }
 
+impl SpectrogramColorScheme {
+    pub const VALUES: [Self; 4] = [Self::Intensity, Self::Fire, Self::Ice, Self::Rainbow];
+
+    /// Name of the matching `color` option of ffmpeg's showspectrumpic.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Intensity => "intensity",
+            Self::Fire => "fire",
+            Self::Ice => "cool",
+            Self::Rainbow => "rainbow",
+        }
+    }
+}
+
 impl std::fmt::Display for SpectrogramColorScheme {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+impl FromStr for SpectrogramColorScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("palette", s, &Self::VALUES, |v| v.as_str())
+    }
+}
+
+/// Every window function of ffmpeg's showspectrum(pic).
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum SpectogramWinFunc {
+    Rect,
+    Bartlett,
     Hann,
     Hamming,
     Blackman,
+    Welch,
+    Flattop,
+    BHarris,
+    BNuttall,
+    BHann,
+    Sine,
+    Nuttall,
+    Lanczos,
+    Gauss,
+    Tukey,
+    Dolph,
+    Cauchy,
+    Parzen,
+    Poisson,
+    Bohman,
+    Kaiser,
+}
+
+impl SpectogramWinFunc {
+    /// The three original choices first, the rest in ffmpeg's order.
+    pub const VALUES: [Self; 21] = [
+        Self::Hann,
+        Self::Hamming,
+        Self::Blackman,
+        Self::Rect,
+        Self::Bartlett,
+        Self::Welch,
+        Self::Flattop,
+        Self::BHarris,
+        Self::BNuttall,
+        Self::BHann,
+        Self::Sine,
+        Self::Nuttall,
+        Self::Lanczos,
+        Self::Gauss,
+        Self::Tukey,
+        Self::Dolph,
+        Self::Cauchy,
+        Self::Parzen,
+        Self::Poisson,
+        Self::Bohman,
+        Self::Kaiser,
+    ];
+
+    /// Name of the matching `win_func` option of ffmpeg's showspectrumpic.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rect => "rect",
+            Self::Bartlett => "bartlett",
+            Self::Hann => "hann",
+            Self::Hamming => "hamming",
+            Self::Blackman => "blackman",
+            Self::Welch => "welch",
+            Self::Flattop => "flattop",
+            Self::BHarris => "bharris",
+            Self::BNuttall => "bnuttall",
+            Self::BHann => "bhann",
+            Self::Sine => "sine",
+            Self::Nuttall => "nuttall",
+            Self::Lanczos => "lanczos",
+            Self::Gauss => "gauss",
+            Self::Tukey => "tukey",
+            Self::Dolph => "dolph",
+            Self::Cauchy => "cauchy",
+            Self::Parzen => "parzen",
+            Self::Poisson => "poisson",
+            Self::Bohman => "bohman",
+            Self::Kaiser => "kaiser",
+        }
+    }
 }
 
 impl std::fmt::Display for SpectogramWinFunc {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Self::Rect => write!(f, "Rectangular"),
+            Self::Flattop => write!(f, "Flat top"),
+            Self::BHarris => write!(f, "Blackman-Harris"),
+            Self::BNuttall => write!(f, "Blackman-Nuttall"),
+            Self::BHann => write!(f, "Bartlett-Hann"),
+            Self::Dolph => write!(f, "Dolph-Chebyshev"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl FromStr for SpectogramWinFunc {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("window function", s, &Self::VALUES, |v| v.as_str())
     }
 }
 
@@ -48,6 +161,16 @@ impl SpectrogramScale {
         Self::FourthRt,
         Self::FifthRt,
     ];
+
+    /// Name of the matching `scale` option of ffmpeg's showspectrumpic.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Linear => "lin",
+            Self::Log => "log",
+            Self::FourthRt => "4thrt",
+            Self::FifthRt => "5thrt",
+        }
+    }
 }
 
 impl std::fmt::Display for SpectrogramScale {
@@ -56,26 +179,306 @@ impl std::fmt::Display for SpectrogramScale {
     }
 }
 
+impl FromStr for SpectrogramScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("scale", s, &Self::ALL, |v| v.as_str())
+    }
+}
+
+/// Mapping of frequencies to rows, independent of the amplitude `SpectrogramScale`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FrequencyScale {
+    Linear,
+    /// Logarithmic from `frequency::LOG_MIN_HZ` up.
+    Log,
+    Mel,
+    Bark,
+    Erb,
+}
+
+impl FrequencyScale {
+    pub const VALUES: [Self; 5] = [Self::Linear, Self::Log, Self::Mel, Self::Bark, Self::Erb];
+}
+
+impl std::fmt::Display for FrequencyScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Erb => write!(f, "ERB"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl FromStr for FrequencyScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("frequency scale", s, &Self::VALUES, |v| match v {
+            Self::Linear => "lin",
+            _ => "",
+        })
+    }
+}
+
+/// Transform behind the spectrogram.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SpectrogramView {
+    /// Short-time Fourier transform, evenly spaced bins.
+    Stft,
+    /// Constant-Q transform (`crate::cqt`), bins spaced in fractions of an
+    /// octave and a note axis. Native engine only.
+    ConstantQ,
+}
+
+impl SpectrogramView {
+    pub const VALUES: [Self; 2] = [Self::Stft, Self::ConstantQ];
+}
+
+impl std::fmt::Display for SpectrogramView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stft => write!(f, "STFT"),
+            Self::ConstantQ => write!(f, "Constant-Q"),
+        }
+    }
+}
+
+impl FromStr for SpectrogramView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("view", s, &Self::VALUES, |v| match v {
+            Self::Stft => "",
+            Self::ConstantQ => "cqt",
+        })
+    }
+}
+
+/// Stereo signals derived from the left and right channels, drawn instead
+/// of the file's own channels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StereoView {
+    /// The file's channels, mixed or split as `split_channels` says.
+    Channels,
+    /// What both channels have in common, (L + R) / 2.
+    Mid,
+    /// What sets them apart, (L - R) / 2.
+    Side,
+    /// Left and right split, with the side signal stacked below.
+    LeftRightSide,
+}
+
+impl StereoView {
+    pub const VALUES: [Self; 4] = [Self::Channels, Self::Mid, Self::Side, Self::LeftRightSide];
+}
+
+impl std::fmt::Display for StereoView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Channels => write!(f, "Channels"),
+            Self::Mid => write!(f, "Mid"),
+            Self::Side => write!(f, "Side"),
+            Self::LeftRightSide => write!(f, "L/R + Side"),
+        }
+    }
+}
+
+impl FromStr for StereoView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("stereo view", s, &Self::VALUES, |v| match v {
+            Self::LeftRightSide => "lr-side",
+            _ => "",
+        })
+    }
+}
+
+/// How the two files of a comparison are arranged.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CompareLayout {
+    /// One above the other, under a shared legend.
+    Stacked,
+    /// Next to each other, each with its own legend.
+    SideBySide,
+    /// One at a time, flipped in place.
+    Flip,
+    /// The level difference B minus A, after lining B up with A in time.
+    Difference,
+}
+
+impl CompareLayout {
+    pub const VALUES: [Self; 4] = [
+        Self::Stacked,
+        Self::SideBySide,
+        Self::Flip,
+        Self::Difference,
+    ];
+}
+
+impl std::fmt::Display for CompareLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stacked => write!(f, "Stacked"),
+            Self::SideBySide => write!(f, "Side by side"),
+            Self::Flip => write!(f, "A/B"),
+            Self::Difference => write!(f, "Difference"),
+        }
+    }
+}
+
+impl FromStr for CompareLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("compare layout", s, &Self::VALUES, |v| match v {
+            Self::Stacked => "",
+            Self::SideBySide => "side-by-side",
+            Self::Flip => "flip",
+            Self::Difference => "diff",
+        })
+    }
+}
+
+/// Engine used to turn decoded audio into a spectrogram image.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SpectrogramBackend {
+    /// ffmpeg's showspectrumpic filter.
+    Ffmpeg,
+    /// Built-in STFT engine (`crate::spectrogram`).
+    Native,
+}
+
+impl SpectrogramBackend {
+    pub const VALUES: [Self; 2] = [Self::Ffmpeg, Self::Native];
+}
+
+impl std::fmt::Display for SpectrogramBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for SpectrogramBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_choice("backend", s, &Self::VALUES, |_| "")
+    }
+}
+
+/// Matches `s` case-insensitively against the display names and ffmpeg names of `values`.
+fn parse_choice<T: Copy + std::fmt::Display>(
+    kind: &str,
+    s: &str,
+    values: &[T],
+    ffmpeg_name: impl Fn(&T) -> &'static str,
+) -> Result<T, String> {
+    values
+        .iter()
+        .find(|v| {
+            let ffmpeg = ffmpeg_name(v);
+            v.to_string().eq_ignore_ascii_case(s)
+                || (!ffmpeg.is_empty() && ffmpeg.eq_ignore_ascii_case(s))
+        })
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<String> = values
+                .iter()
+                .map(|v| v.to_string().to_lowercase())
+                .collect();
+            format!(
+                "invalid {} '{}', expected one of: {}",
+                kind,
+                s,
+                names.join(", ")
+            )
+        })
+}
+
 // ======================================================
 // App Settings (Headless-first, GUI tolerated for now)
 // ======================================================
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct AppSettings {
     pub color_scheme: SpectrogramColorScheme,
     pub win_func: SpectogramWinFunc,
     pub scale: SpectrogramScale,
+    pub frequency_scale: FrequencyScale,
+    /// Frequency band shown, in Hz. A `freq_max_hz` of 0 stands for the
+    /// Nyquist frequency. Ignored by the constant-Q view.
+    ///
+    /// The band and the time range below are the viewer's zoom, set for one
+    /// file, so they are never saved to or loaded from `config.toml`.
+    #[serde(skip)]
+    pub freq_min_hz: f32,
+    #[serde(skip)]
+    pub freq_max_hz: f32,
+    /// Part of the file rendered, in seconds from its start. An `end_time`
+    /// of 0 stands for the end of the file.
+    #[serde(skip)]
+    pub start_time: f64,
+    #[serde(skip)]
+    pub end_time: f64,
+    pub view: SpectrogramView,
     pub gain: f32,
     pub saturation: f32,
+    /// Levels in dBFS (after the gain) mapped to the bottom and top of the palette.
+    /// ffmpeg only honours the floor with the `Log` scale.
+    pub db_floor: f32,
+    pub db_ceiling: f32,
+    /// Fit the level range to the file's noise floor and peak instead.
+    /// Native engine only.
+    pub auto_db_range: bool,
     pub split_channels: bool,
+    /// Channels to show, by name ("L", "LFE") or 1-based number. Empty
+    /// shows all of them.
+    pub channels: Vec<String>,
+    /// Mid or side signal instead of the channels, for files with a left
+    /// and a right channel.
+    pub stereo_view: StereoView,
+    pub horizontal: bool,
+    /// Arrangement of the two files when comparing.
+    pub compare_layout: CompareLayout,
+    /// Level difference, in dB either way, at the ends of the difference palette.
+    pub difference_range: f32,
+
+    // Rendering engine
+    pub backend: SpectrogramBackend,
+    /// FFT window length in samples (native backend only).
+    pub fft_size: u32,
+    /// Overlap between consecutive FFT windows, 0.0..1.0 (native backend and
+    /// live mode; ffmpeg picks its own for still images).
+    pub overlap: f32,
+    /// Constant-Q resolution (constant-Q view only).
+    pub cqt_bins_per_octave: u32,
+    /// Lowest and highest bin frequency of the constant-Q view, in Hz.
+    pub cqt_min_hz: f32,
+    pub cqt_max_hz: f32,
 
     // Resolution
     pub custom_resolution: bool,
     pub resolution: [u32; 2],
+    pub resize_with_window: bool,
 
     // Legend (ALWAYS ON for you)
     pub legend: bool,
+    pub custom_legend: bool,
+    pub live_mode: bool,
+
+    /// Run the file analysers (transcode detection etc.) alongside rendering.
+    pub analyze_audio: bool,
+    /// Draw the stereo correlation found by the analysis under the time axis.
+    pub correlation_strip: bool,
+
+    // GUI persistence
+    pub remember_settings: bool,
+    pub save_window_size: bool,
+    pub window_size: [f32; 2],
 
     // Headless / PNG
     pub png_width: u32,
@@ -89,14 +492,46 @@ impl Default for AppSettings {
             color_scheme: SpectrogramColorScheme::Intensity,
             win_func: SpectogramWinFunc::Hann,
             scale: SpectrogramScale::Log,
+            frequency_scale: FrequencyScale::Linear,
+            freq_min_hz: 0.0,
+            freq_max_hz: 0.0,
+            start_time: 0.0,
+            end_time: 0.0,
+            view: SpectrogramView::Stft,
             gain: 1.0,
             saturation: 1.0,
+            db_floor: -120.0,
+            db_ceiling: 0.0,
+            auto_db_range: false,
             split_channels: false,
+            channels: Vec::new(),
+            stereo_view: StereoView::Channels,
+            horizontal: false,
+            compare_layout: CompareLayout::Stacked,
+            difference_range: 20.0,
+
+            backend: SpectrogramBackend::Native,
+            fft_size: 2048,
+            overlap: 0.75,
+            // C1 to C8, a little over the range of a piano.
+            cqt_bins_per_octave: 24,
+            cqt_min_hz: 32.703,
+            cqt_max_hz: 4186.0,
 
             custom_resolution: false,
             resolution: [500, 320],
+            resize_with_window: false,
 
             legend: true,
+            custom_legend: true,
+            live_mode: false,
+
+            analyze_audio: true,
+            correlation_strip: false,
+
+            remember_settings: true,
+            save_window_size: false,
+            window_size: [500.0 + 180.0, 320.0 + 128.0 + 39.0],
 
             png_width: 0,
             png_height: 0,
@@ -119,22 +554,141 @@ impl AppSettings {
         })
     }
 
+    /// Loads the saved settings, falling back to the defaults when there are none
+    /// or they cannot be read.
     pub fn load() -> Self {
-        if let Some(path) = Self::config_path() {
-            if let Ok(content) = fs::read_to_string(path) {
-                if let Ok(settings) = toml::from_str::<AppSettings>(&content) {
-                    return settings;
-                }
-            }
+        let Some(path) = Self::config_path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
         }
-        Self::default()
+        Self::load_from(&path).unwrap_or_else(|e| {
+            eprintln!("{}, using the default settings", e);
+            Self::default()
+        })
     }
 
-    pub fn save(&self) {
-        if let Some(path) = Self::config_path() {
-            if let Ok(content) = toml::to_string_pretty(self) {
-                let _ = fs::write(path, content);
-            }
+    /// Reads settings from a `config.toml` at `path`.
+    pub fn load_from(path: &Path) -> error::Result<Self> {
+        let config_error = |reason: String| Error::Config {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let content = fs::read_to_string(path).map_err(|e| config_error(e.to_string()))?;
+        toml::from_str(&content).map_err(|e| config_error(e.to_string()))
+    }
+
+    /// Whether the legend is drawn by `legend::draw_legend` instead of ffmpeg.
+    ///
+    /// Live mode, the native backend, non-linear frequency axes and the
+    /// constant-Q view have no ffmpeg legend to fall back on.
+    pub fn uses_custom_legend(&self) -> bool {
+        self.legend
+            && (self.custom_legend
+                || self.live_mode
+                || self.backend == SpectrogramBackend::Native
+                || self.frequency_scale != FrequencyScale::Linear
+                || self.view == SpectrogramView::ConstantQ)
+    }
+
+    /// Whether channels are drawn one above the other rather than mixed.
+    pub fn splits_channels(&self) -> bool {
+        self.split_channels || self.stereo_view == StereoView::LeftRightSide
+    }
+
+    /// Start and end in seconds of the part of a file `duration` seconds
+    /// long that is rendered. Empty when `start_time` is past the end.
+    pub fn time_range(&self, duration: f64) -> (f64, f64) {
+        let end = if self.end_time > 0.0 {
+            self.end_time.min(duration)
+        } else {
+            duration
+        };
+        (self.start_time.clamp(0.0, end.max(0.0)), end)
+    }
+
+    /// Whether only part of the file is rendered.
+    pub fn has_time_range(&self) -> bool {
+        self.start_time > 0.0 || self.end_time > 0.0
+    }
+
+    /// `difference_range` within the 1..120 dB the GUI and the command line
+    /// accept. A hand-edited config can hold anything, NaN included.
+    pub fn clamped_difference_range(&self) -> f32 {
+        match self.difference_range {
+            range if range.is_nan() => Self::default().difference_range,
+            range => range.clamp(1.0, 120.0),
         }
     }
+
+    /// Copy with the fields that only affect colour mapping reset to their defaults.
+    ///
+    /// Two settings with equal results here produce the same `MagnitudeMatrix`,
+    /// so switching between them only needs `spectrogram::colorize`.
+    pub fn without_color_mapping(&self) -> Self {
+        let defaults = Self::default();
+        Self {
+            color_scheme: defaults.color_scheme,
+            scale: defaults.scale,
+            gain: defaults.gain,
+            saturation: defaults.saturation,
+            db_floor: defaults.db_floor,
+            db_ceiling: defaults.db_ceiling,
+            auto_db_range: defaults.auto_db_range,
+            difference_range: defaults.difference_range,
+            remember_settings: defaults.remember_settings,
+            save_window_size: defaults.save_window_size,
+            window_size: defaults.window_size,
+            ..self.clone()
+        }
+    }
+
+    /// Writes the settings to the config directory, if the platform has one.
+    pub fn save(&self) -> error::Result<()> {
+        let Some(path) = Self::config_path() else {
+            return Ok(());
+        };
+        let content = toml::to_string_pretty(self).map_err(Error::ConfigSerialize)?;
+        fs::write(&path, content).map_err(|e| Error::Config {
+            path,
+            reason: e.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_is_not_saved() {
+        let settings = AppSettings {
+            freq_min_hz: 100.0,
+            freq_max_hz: 8000.0,
+            start_time: 12.0,
+            end_time: 14.5,
+            ..AppSettings::default()
+        };
+        let content = toml::to_string_pretty(&settings).unwrap();
+        assert!(!content.contains("freq_m"), "{}", content);
+        assert!(!content.contains("_time"), "{}", content);
+
+        // Old config files that still have them open unzoomed.
+        let old = format!("{}start_time = 12.0\nfreq_max_hz = 8000.0\n", content);
+        let loaded: AppSettings = toml::from_str(&old).unwrap();
+        assert_eq!(loaded, AppSettings::default());
+    }
+
+    #[test]
+    fn difference_range_is_clamped() {
+        let with_range = |difference_range| AppSettings {
+            difference_range,
+            ..AppSettings::default()
+        };
+        assert_eq!(with_range(30.0).clamped_difference_range(), 30.0);
+        assert_eq!(with_range(-5.0).clamped_difference_range(), 1.0);
+        assert_eq!(with_range(1e6).clamped_difference_range(), 120.0);
+        assert_eq!(with_range(f32::NAN).clamped_difference_range(), 20.0);
+    }
 }
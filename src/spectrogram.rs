@@ -0,0 +1,843 @@
+use crate::cqt::{ConstantQKernel, ConstantQParams};
+use crate::frequency::FrequencyAxis;
+use crate::palettes;
+use crate::settings::{
+    AppSettings, FrequencyScale, SpectogramWinFunc, SpectrogramScale, SpectrogramView,
+};
+use image::{Rgba, RgbaImage};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::Serialize;
+use std::f64::consts::PI as PI64;
+use std::sync::Arc;
+
+/// Lowest level stored in a `MagnitudeMatrix`, used instead of -inf for silence.
+pub const MIN_DB: f32 = -200.0;
+
+/// Share of the non-silent levels below the floor of an automatic `DbRange`.
+const AUTO_FLOOR_PERCENTILE: f32 = 0.01;
+
+/// Smallest span of an automatic `DbRange`, so near-silent files are not
+/// stretched over the whole palette.
+const AUTO_MIN_RANGE_DB: f32 = 30.0;
+
+// ======================================================
+// STFT parameters
+// ======================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StftParams {
+    /// FFT window length in samples.
+    pub fft_size: usize,
+    /// Distance between the starts of consecutive windows in samples.
+    pub hop: usize,
+    pub win_func: SpectogramWinFunc,
+    /// How the FFT bins are spread over the rows.
+    pub frequency_scale: FrequencyScale,
+    /// Lowest and highest frequency shown in Hz, 0 for the Nyquist frequency.
+    pub band: (f32, f32),
+    /// Computes constant-Q bins instead of FFT bins. The frame length then
+    /// follows from the lowest bin and `hop` keeps its ratio to `fft_size`.
+    pub constant_q: Option<ConstantQParams>,
+}
+
+impl StftParams {
+    pub fn from_settings(settings: &AppSettings) -> Self {
+        let fft_size = (settings.fft_size as usize).max(16);
+        let overlap = settings.overlap.clamp(0.0, 0.99);
+        let hop = ((fft_size as f32 * (1.0 - overlap)).round() as usize).max(1);
+        Self {
+            fft_size,
+            hop,
+            win_func: settings.win_func,
+            frequency_scale: settings.frequency_scale,
+            band: (settings.freq_min_hz, settings.freq_max_hz),
+            constant_q: match settings.view {
+                SpectrogramView::Stft => None,
+                SpectrogramView::ConstantQ => Some(ConstantQParams::from_settings(settings)),
+            },
+        }
+    }
+}
+
+/// Builds the analysis window, in the same shapes ffmpeg's showspectrum uses.
+pub fn window(win_func: SpectogramWinFunc, size: usize) -> Vec<f32> {
+    use SpectogramWinFunc as W;
+
+    if size < 2 {
+        return vec![1.0; size];
+    }
+    if win_func == W::Dolph {
+        return dolph_chebyshev(size);
+    }
+
+    let m = (size - 1) as f64;
+    let cosine_sum = |n: f64, coefficients: &[f64]| -> f64 {
+        coefficients
+            .iter()
+            .enumerate()
+            .map(|(k, c)| c * (2.0 * PI64 * k as f64 * n / m).cos())
+            .sum()
+    };
+    (0..size)
+        .map(|i| {
+            let n = i as f64;
+            // -1.0 at the first sample, 1.0 at the last.
+            let x = 2.0 * n / m - 1.0;
+            let value = match win_func {
+                W::Rect => 1.0,
+                W::Bartlett => 1.0 - x.abs(),
+                W::Hann => cosine_sum(n, &[0.5, -0.5]),
+                W::Hamming => cosine_sum(n, &[0.54, -0.46]),
+                W::Blackman => cosine_sum(n, &[0.42659, -0.49656, 0.076849]),
+                W::Welch => 1.0 - x * x,
+                W::Flattop => cosine_sum(
+                    n,
+                    &[
+                        1.0,
+                        -1.985844164102,
+                        1.791176438506,
+                        -1.282075284005,
+                        0.667777530266,
+                        -0.240160796576,
+                        0.056656381764,
+                        -0.008134974479,
+                        0.000624544650,
+                        -0.000019808998,
+                        0.000000132974,
+                    ],
+                ),
+                W::BHarris => cosine_sum(n, &[0.35875, -0.48829, 0.14128, -0.01168]),
+                W::BNuttall => cosine_sum(n, &[0.3635819, -0.4891775, 0.1365995, -0.0106411]),
+                W::BHann => 0.62 - 0.48 * (n / m - 0.5).abs() - 0.38 * (2.0 * PI64 * n / m).cos(),
+                W::Sine => (PI64 * n / m).sin(),
+                W::Nuttall => cosine_sum(n, &[0.355768, -0.487396, 0.144232, -0.012604]),
+                W::Lanczos => {
+                    if x == 0.0 {
+                        1.0
+                    } else {
+                        (PI64 * x).sin() / (PI64 * x)
+                    }
+                }
+                W::Gauss => (-0.5 * (x / 0.4).powi(2)).exp(),
+                W::Tukey => {
+                    // Flat in the middle 30 %, cosine tapers on both sides.
+                    if x.abs() >= 0.3 {
+                        0.5 * (1.0 + (PI64 * (x.abs() - 0.3) / 0.7).cos())
+                    } else {
+                        1.0
+                    }
+                }
+                W::Cauchy => {
+                    let x = x / 2.0;
+                    if x.abs() >= 0.5 {
+                        0.0
+                    } else {
+                        (1.0 / (1.0 + 64.0 * x * x)).min(1.0)
+                    }
+                }
+                W::Parzen => {
+                    let x = (x / 2.0).abs();
+                    if x <= 0.25 {
+                        1.0 - 24.0 * x * x + 48.0 * x * x * x
+                    } else {
+                        2.0 * (1.0 - 2.0 * x).powi(3)
+                    }
+                }
+                W::Poisson => (-3.0 * x.abs()).exp(),
+                W::Bohman => {
+                    let x = x.abs();
+                    (1.0 - x) * (PI64 * x).cos() + (PI64 * x).sin() / PI64
+                }
+                W::Kaiser => bessel_i0(12.0 * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(12.0),
+                W::Dolph => unreachable!("handled above"),
+            };
+            value as f32
+        })
+        .collect()
+}
+
+/// Dolph-Chebyshev window, computed the way ffmpeg does (from SoX).
+fn dolph_chebyshev(size: usize) -> Vec<f32> {
+    let n_total = size as f64;
+    let mut lut = vec![0.0; size];
+    let c = 1.0 - 1.0 / (7.600_902_209_541_989 / (n_total - 1.0)).cosh().powi(2);
+    let mut norm = 0.0;
+    for n in (0..=(size - 1) / 2).rev() {
+        let nf = n as f64;
+        let mut sum = if n == 0 { 1.0 } else { 0.0 };
+        let mut b = 1.0;
+        let mut t = 1.0;
+        let mut j = 1.0;
+        while j <= nf && sum != t {
+            t = sum;
+            b *= c * (n_total - nf - j) / j;
+            sum += b;
+            b *= (nf - j) / j;
+            j += 1.0;
+        }
+        sum /= n_total - 1.0 - nf;
+        if norm == 0.0 {
+            norm = sum;
+        }
+        sum /= norm;
+        lut[n] = sum as f32;
+        lut[size - 1 - n] = sum as f32;
+    }
+    lut
+}
+
+/// Modified Bessel function of the first kind, order 0.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+// ======================================================
+// Magnitude matrix
+// ======================================================
+
+/// Per-pixel spectrogram levels in dBFS, before any colour mapping.
+#[derive(Debug, Clone)]
+pub struct MagnitudeMatrix {
+    /// Number of time columns.
+    pub width: usize,
+    /// Number of frequency rows per channel.
+    pub height: usize,
+    pub channels: usize,
+    pub sample_rate: u32,
+    /// Levels laid out as `[channel][row][column]`, row 0 being the lowest frequency.
+    pub db: Vec<f32>,
+}
+
+impl MagnitudeMatrix {
+    pub fn get(&self, channel: usize, row: usize, column: usize) -> f32 {
+        self.db[(channel * self.height + row) * self.width + column]
+    }
+}
+
+// ======================================================
+// Streaming STFT analyser
+// ======================================================
+
+/// Computes a `MagnitudeMatrix` from interleaved PCM pushed in arbitrary chunks.
+///
+/// Every window is assigned to the image column its centre falls into and the
+/// column keeps the maximum power per row, so memory use depends only on the
+/// output size and not on the length of the recording. With
+/// `StftParams::constant_q` set, each window is reduced to constant-Q bins
+/// before it is spread over the rows.
+pub struct StftAnalyzer {
+    params: StftParams,
+    input_channels: usize,
+    split_channels: bool,
+    sample_rate: u32,
+    total_frames: u64,
+    width: usize,
+    height: usize,
+
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    power_norm: f32,
+    constant_q: Option<ConstantQKernel>,
+    row_bins: Vec<(f32, f32)>,
+
+    pending: Vec<Vec<f32>>,
+    frames_consumed: u64,
+    power: Vec<f32>,
+    column_filled: Vec<bool>,
+
+    scratch: Vec<Complex<f32>>,
+    bin_power: Vec<f32>,
+    channel_power: Vec<f32>,
+}
+
+impl StftAnalyzer {
+    /// `total_frames` is the expected number of samples per channel. It only
+    /// has to be approximately right; late windows land in the last column.
+    pub fn new(
+        params: StftParams,
+        input_channels: usize,
+        sample_rate: u32,
+        total_frames: u64,
+        width: usize,
+        height: usize,
+        split_channels: bool,
+    ) -> Self {
+        let input_channels = input_channels.max(1);
+        let width = width.max(1);
+        let height = height.max(1);
+        let total_frames = total_frames.max(1);
+
+        let constant_q = params
+            .constant_q
+            .map(|cq| ConstantQKernel::new(cq, params.win_func, sample_rate));
+        let params = match &constant_q {
+            Some(kernel) => StftParams {
+                fft_size: kernel.fft_size,
+                hop: (kernel.fft_size as f32 * params.hop as f32 / params.fft_size as f32).round()
+                    as usize,
+                ..params
+            },
+            None => params,
+        };
+
+        // Never let the hop skip over whole columns.
+        let max_hop = (total_frames / width as u64).max(1) as usize;
+        let params = StftParams {
+            hop: params.hop.clamp(1, max_hop),
+            ..params
+        };
+
+        let fft = FftPlanner::new().plan_fft_forward(params.fft_size);
+        // The constant-Q kernels carry their own windows.
+        let window = match constant_q {
+            Some(_) => vec![1.0; params.fft_size],
+            None => window(params.win_func, params.fft_size),
+        };
+        // A full-scale sine should read 0 dBFS: |X| = A * sum(w) / 2.
+        let window_sum: f32 = window.iter().sum();
+        let power_norm = 4.0 / (window_sum * window_sum);
+
+        let (bins, row_bins) = match &constant_q {
+            // Constant-Q bins are evenly spaced on a log axis already.
+            Some(kernel) => {
+                let last = (kernel.bin_count() - 1) as f32;
+                let row_bins = (0..height)
+                    .map(|row| {
+                        (
+                            row as f32 / height as f32 * last,
+                            (row + 1) as f32 / height as f32 * last,
+                        )
+                    })
+                    .collect();
+                (last as usize, row_bins)
+            }
+            None => {
+                let bins = params.fft_size / 2;
+                let (min_hz, max_hz) = params.band;
+                let axis = FrequencyAxis::band(params.frequency_scale, sample_rate, min_hz, max_hz);
+                let bin_hz = sample_rate as f32 / 2.0 / bins as f32;
+                let row_bins = (0..height)
+                    .map(|row| {
+                        let (lo_hz, hi_hz) = axis.row_range(row, height);
+                        (lo_hz / bin_hz, hi_hz / bin_hz)
+                    })
+                    .collect();
+                (bins, row_bins)
+            }
+        };
+
+        let output_channels = if split_channels { input_channels } else { 1 };
+
+        // Pad the start with half a window so the first window is centred on sample 0.
+        let pending = (0..input_channels)
+            .map(|_| vec![0.0; params.fft_size / 2])
+            .collect();
+
+        Self {
+            params,
+            input_channels,
+            split_channels,
+            sample_rate,
+            total_frames,
+            width,
+            height,
+            fft,
+            window,
+            power_norm,
+            constant_q,
+            row_bins,
+            pending,
+            frames_consumed: 0,
+            power: vec![0.0; output_channels * width * height],
+            column_filled: vec![false; width],
+            scratch: vec![Complex::new(0.0, 0.0); params.fft_size],
+            bin_power: vec![0.0; bins + 1],
+            channel_power: vec![0.0; height],
+        }
+    }
+
+    fn output_channels(&self) -> usize {
+        if self.split_channels {
+            self.input_channels
+        } else {
+            1
+        }
+    }
+
+    /// Feeds interleaved samples (`input_channels` values per frame).
+    pub fn push_interleaved(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.input_channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.pending[channel].push(sample);
+            }
+        }
+        self.process_pending();
+    }
+
+    fn process_pending(&mut self) {
+        while self.pending[0].len() >= self.params.fft_size {
+            self.process_window();
+            for buffer in &mut self.pending {
+                buffer.drain(..self.params.hop);
+            }
+        }
+    }
+
+    fn process_window(&mut self) {
+        // Centre of the current window, in samples from the start of the file.
+        let centre = self.frames_consumed;
+        self.frames_consumed += self.params.hop as u64;
+
+        let column = ((centre as f64 / self.total_frames as f64) * self.width as f64) as usize;
+        let column = column.min(self.width - 1);
+        self.column_filled[column] = true;
+
+        let output_channels = self.output_channels();
+        for channel in 0..self.input_channels {
+            for (i, value) in self.scratch.iter_mut().enumerate() {
+                *value = Complex::new(self.pending[channel][i] * self.window[i], 0.0);
+            }
+            self.fft.process(&mut self.scratch);
+            match &self.constant_q {
+                Some(kernel) => kernel.powers(&self.scratch, &mut self.bin_power),
+                None => {
+                    for (power, value) in self.bin_power.iter_mut().zip(&self.scratch) {
+                        *power = value.norm_sqr() * self.power_norm;
+                    }
+                }
+            }
+
+            for row in 0..self.height {
+                let row_power = reduce_bins(&self.bin_power, self.row_bins[row]);
+                if self.split_channels {
+                    self.channel_power[row] = row_power;
+                } else if channel == 0 {
+                    self.channel_power[row] = row_power / self.input_channels as f32;
+                } else {
+                    self.channel_power[row] += row_power / self.input_channels as f32;
+                }
+            }
+
+            let last_channel = channel + 1 == self.input_channels;
+            if self.split_channels || last_channel {
+                let out_channel = if output_channels == 1 { 0 } else { channel };
+                for row in 0..self.height {
+                    let index = (out_channel * self.height + row) * self.width + column;
+                    self.power[index] = self.power[index].max(self.channel_power[row]);
+                }
+            }
+        }
+    }
+
+    /// Flushes the remaining windows and converts the collected powers to dBFS.
+    pub fn finish(mut self) -> MagnitudeMatrix {
+        // Pad the end so the tail of the file gets analysed as well.
+        let tail = self.params.fft_size / 2 + self.params.hop;
+        for buffer in &mut self.pending {
+            buffer.extend(std::iter::repeat_n(0.0, tail));
+        }
+        let total_frames = self.total_frames;
+        while self.pending[0].len() >= self.params.fft_size && self.frames_consumed < total_frames {
+            self.process_window();
+            for buffer in &mut self.pending {
+                buffer.drain(..self.params.hop);
+            }
+        }
+
+        let output_channels = self.output_channels();
+
+        // Columns no window landed in (very short files) repeat their left neighbour.
+        for column in 1..self.width {
+            if !self.column_filled[column] && self.column_filled[column - 1] {
+                for channel in 0..output_channels {
+                    for row in 0..self.height {
+                        let index = (channel * self.height + row) * self.width + column;
+                        self.power[index] = self.power[index - 1];
+                    }
+                }
+                self.column_filled[column] = true;
+            }
+        }
+
+        let db = self
+            .power
+            .iter()
+            .map(|&power| (10.0 * power.max(1e-20).log10()).max(MIN_DB))
+            .collect();
+
+        MagnitudeMatrix {
+            width: self.width,
+            height: self.height,
+            channels: output_channels,
+            sample_rate: self.sample_rate,
+            db,
+        }
+    }
+}
+
+/// Reduces the FFT bins covered by a row to a single power value.
+///
+/// Rows spanning several bins keep the loudest one; rows narrower than a bin
+/// interpolate between the two nearest bins.
+fn reduce_bins(bin_power: &[f32], (lo, hi): (f32, f32)) -> f32 {
+    let last = bin_power.len() - 1;
+    if hi - lo >= 1.0 {
+        let start = (lo.ceil() as usize).min(last);
+        let end = (hi.floor() as usize).min(last);
+        bin_power[start..=end.max(start)]
+            .iter()
+            .fold(0.0, |acc: f32, &p| acc.max(p))
+    } else {
+        let centre = ((lo + hi) / 2.0).min(last as f32);
+        let index = centre.floor() as usize;
+        let next = (index + 1).min(last);
+        let frac = centre - index as f32;
+        bin_power[index] * (1.0 - frac) + bin_power[next] * frac
+    }
+}
+
+/// Runs the whole STFT over an in-memory buffer of interleaved samples.
+pub fn compute(
+    samples: &[f32],
+    channels: usize,
+    sample_rate: u32,
+    params: StftParams,
+    width: usize,
+    height: usize,
+    split_channels: bool,
+) -> MagnitudeMatrix {
+    let total_frames = (samples.len() / channels.max(1)) as u64;
+    let mut analyzer = StftAnalyzer::new(
+        params,
+        channels,
+        sample_rate,
+        total_frames,
+        width,
+        height,
+        split_channels,
+    );
+    analyzer.push_interleaved(samples);
+    analyzer.finish()
+}
+
+// ======================================================
+// Colour mapping
+// ======================================================
+
+/// Levels mapped to the bottom and top of the palette, in dBFS of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DbRange {
+    /// Level at the bottom of the palette, `-inf` when nothing is cut off.
+    pub floor: f32,
+    pub ceiling: f32,
+}
+
+impl DbRange {
+    /// `db_floor` and `db_ceiling` from the settings. The gain is folded in,
+    /// so a gain of 2 maps a level 6 dB lower to the top of the palette.
+    pub fn from_settings(settings: &AppSettings) -> Self {
+        let gain_db = 20.0 * settings.gain.max(1e-6).log10();
+        Self {
+            floor: settings.db_floor - gain_db,
+            ceiling: settings.db_ceiling - gain_db,
+        }
+    }
+
+    /// Fits the range to the levels of a render: the ceiling at the loudest
+    /// level, the floor at the noise floor (a low percentile of all levels
+    /// above digital silence). Both are rounded outwards to whole dB.
+    pub fn measure(matrix: &MagnitudeMatrix) -> Option<Self> {
+        let mut levels: Vec<f32> = matrix
+            .db
+            .iter()
+            .copied()
+            .filter(|&db| db > MIN_DB)
+            .collect();
+        if levels.is_empty() {
+            return None;
+        }
+        let index = ((levels.len() - 1) as f32 * AUTO_FLOOR_PERCENTILE) as usize;
+        let (_, &mut noise, _) = levels.select_nth_unstable_by(index, f32::total_cmp);
+        let peak = levels
+            .iter()
+            .fold(f32::NEG_INFINITY, |acc, &db| acc.max(db));
+
+        let ceiling = peak.ceil();
+        Some(Self {
+            floor: noise.floor().min(ceiling - AUTO_MIN_RANGE_DB),
+            ceiling,
+        })
+    }
+
+    /// The range `colorize` uses for `matrix`: measured when
+    /// `auto_db_range` is set, otherwise `from_settings`.
+    pub fn for_matrix(matrix: &MagnitudeMatrix, settings: &AppSettings) -> Self {
+        if settings.auto_db_range {
+            Self::measure(matrix).unwrap_or_else(|| Self::from_settings(settings))
+        } else {
+            Self::from_settings(settings)
+        }
+    }
+
+    /// Palette position (0.0..1.0) of a dBFS level.
+    ///
+    /// `Log` is linear in dB between floor and ceiling, the other scales are
+    /// linear in the amplitude, or its root, between the two.
+    pub fn position(&self, db: f32, scale: SpectrogramScale) -> f32 {
+        let (lo, hi) = (
+            warp_level(self.floor, scale),
+            warp_level(self.ceiling, scale),
+        );
+        if hi <= lo {
+            return if db >= self.ceiling { 1.0 } else { 0.0 };
+        }
+        ((warp_level(db, scale) - lo) / (hi - lo)).clamp(0.0, 1.0)
+    }
+
+    /// dBFS level at a palette position, the inverse of `position`.
+    pub fn level(&self, position: f32, scale: SpectrogramScale) -> f32 {
+        let (lo, hi) = (
+            warp_level(self.floor, scale),
+            warp_level(self.ceiling, scale),
+        );
+        unwarp_level(lo + position.clamp(0.0, 1.0) * (hi - lo), scale)
+    }
+
+    /// Round levels to label on a palette `length` pixels tall, at least
+    /// `min_spacing` pixels apart. The ends come first, then multiples of
+    /// 20, 10, 5, 2 and 1 dB wherever they still fit.
+    pub fn ticks(&self, scale: SpectrogramScale, length: f32, min_spacing: f32) -> Vec<f32> {
+        let y = |db: f32| self.position(db, scale) * length;
+        let lowest = self.floor.max(self.ceiling - 200.0);
+
+        let mut ticks: Vec<f32> = Vec::new();
+        let mut add = |db: f32| {
+            if ticks
+                .iter()
+                .all(|&tick| (y(tick) - y(db)).abs() >= min_spacing)
+            {
+                ticks.push(db);
+            }
+        };
+        add(self.ceiling);
+        if self.floor.is_finite() {
+            add(self.floor);
+        }
+        for step in [20.0, 10.0, 5.0, 2.0, 1.0] {
+            let mut db = (self.ceiling / step).floor() * step;
+            while db >= lowest {
+                add(db);
+                db -= step;
+            }
+        }
+        ticks.sort_by(|a, b| b.total_cmp(a));
+        ticks
+    }
+}
+
+/// A dBFS level in the units the scale is linear in.
+fn warp_level(db: f32, scale: SpectrogramScale) -> f32 {
+    let amplitude = || 10f32.powf(db / 20.0);
+    match scale {
+        SpectrogramScale::Log => db,
+        SpectrogramScale::Linear => amplitude(),
+        SpectrogramScale::FourthRt => amplitude().powf(1.0 / 4.0),
+        SpectrogramScale::FifthRt => amplitude().powf(1.0 / 5.0),
+    }
+}
+
+fn unwarp_level(value: f32, scale: SpectrogramScale) -> f32 {
+    let db = |amplitude: f32| 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+    match scale {
+        SpectrogramScale::Log => value,
+        SpectrogramScale::Linear => db(value),
+        SpectrogramScale::FourthRt => db(value.powi(4)),
+        SpectrogramScale::FifthRt => db(value.powi(5)),
+    }
+}
+
+/// Renders a `MagnitudeMatrix` with the palette, scale, level range and
+/// saturation from `settings`.
+///
+/// Channels are stacked top to bottom, highest frequency at the top of each.
+pub fn colorize(matrix: &MagnitudeMatrix, settings: &AppSettings) -> RgbaImage {
+    let palette = palettes::get_palette(settings.color_scheme);
+    let range = DbRange::for_matrix(matrix, settings);
+    let image_height = matrix.height * matrix.channels;
+    let mut image = RgbaImage::new(matrix.width as u32, image_height as u32);
+
+    for channel in 0..matrix.channels {
+        for row in 0..matrix.height {
+            let y = channel * matrix.height + (matrix.height - 1 - row);
+            for column in 0..matrix.width {
+                let a = range.position(matrix.get(channel, row, column), settings.scale);
+                let color = palettes::color_at(palette, a, settings.saturation);
+                image.put_pixel(column as u32, y as u32, color);
+            }
+        }
+    }
+
+    image
+}
+
+/// Palette position of a level difference of `db` on a scale spanning
+/// `range` dB either way, no difference at 0.5.
+pub fn difference_position(db: f32, range: f32) -> f32 {
+    (0.5 + db / (2.0 * range.max(f32::EPSILON))).clamp(0.0, 1.0)
+}
+
+/// Renders a matrix of level differences with the difference palette, no
+/// difference in the middle and `range` dB either way at its ends. Pixels
+/// without a difference (`NAN`) are black.
+pub fn colorize_difference(matrix: &MagnitudeMatrix, range: f32, saturation: f32) -> RgbaImage {
+    let palette = palettes::difference_palette();
+    let image_height = matrix.height * matrix.channels;
+    let mut image = RgbaImage::new(matrix.width as u32, image_height as u32);
+
+    for channel in 0..matrix.channels {
+        for row in 0..matrix.height {
+            let y = channel * matrix.height + (matrix.height - 1 - row);
+            for column in 0..matrix.width {
+                let db = matrix.get(channel, row, column);
+                let color = match db.is_nan() {
+                    true => Rgba([0, 0, 0, 255]),
+                    false => {
+                        palettes::color_at(palette, difference_position(db, range), saturation)
+                    }
+                };
+                image.put_pixel(column as u32, y as u32, color);
+            }
+        }
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+    const FFT_SIZE: usize = 2048;
+
+    fn params() -> StftParams {
+        StftParams {
+            fft_size: FFT_SIZE,
+            hop: FFT_SIZE / 2,
+            win_func: SpectogramWinFunc::Hann,
+            frequency_scale: FrequencyScale::Linear,
+            band: (0.0, 0.0),
+            constant_q: None,
+        }
+    }
+
+    fn sine(frequency: f64, amplitude: f64, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|n| {
+                (amplitude * (2.0 * PI64 * frequency * n as f64 / SAMPLE_RATE as f64).sin()) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_scale_sine_reads_zero_dbfs_in_its_row() {
+        // Exactly on FFT bin 43, so there is no scalloping loss.
+        let bin_hz = SAMPLE_RATE as f64 / FFT_SIZE as f64;
+        let frequency = 43.0 * bin_hz;
+        let samples = sine(frequency, 1.0, SAMPLE_RATE as usize);
+        let (width, height) = (8, 256);
+        let matrix = compute(&samples, 1, SAMPLE_RATE, params(), width, height, false);
+
+        let row_hz = SAMPLE_RATE as f64 / 2.0 / height as f64;
+        let expected_row = (frequency / row_hz) as usize;
+        for column in 1..width - 1 {
+            let levels: Vec<f32> = (0..height).map(|row| matrix.get(0, row, column)).collect();
+            let loudest = (0..height)
+                .max_by(|&a, &b| levels[a].total_cmp(&levels[b]))
+                .unwrap();
+            assert_eq!(loudest, expected_row, "column {column}");
+            assert!(
+                levels[loudest].abs() < 0.1,
+                "{} dB in column {column}",
+                levels[loudest]
+            );
+        }
+    }
+
+    #[test]
+    fn half_scale_sine_reads_minus_six_dbfs() {
+        let frequency = 43.0 * SAMPLE_RATE as f64 / FFT_SIZE as f64;
+        let samples = sine(frequency, 0.5, SAMPLE_RATE as usize);
+        let matrix = compute(&samples, 1, SAMPLE_RATE, params(), 8, 256, false);
+        let loudest = (0..256)
+            .map(|row| matrix.get(0, row, 4))
+            .fold(MIN_DB, f32::max);
+        assert!((loudest + 6.02).abs() < 0.1, "{loudest} dB");
+    }
+
+    #[test]
+    fn silence_reads_min_db() {
+        let samples = vec![0.0; 2 * SAMPLE_RATE as usize / 2];
+        let matrix = compute(&samples, 2, SAMPLE_RATE, params(), 6, 64, true);
+        assert_eq!(matrix.channels, 2);
+        for channel in 0..2 {
+            for row in 0..64 {
+                for column in 0..6 {
+                    assert_eq!(matrix.get(channel, row, column), MIN_DB);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn windows_are_symmetric() {
+        for win_func in SpectogramWinFunc::VALUES {
+            for size in [64, 65] {
+                let w = window(win_func, size);
+                assert_eq!(w.len(), size);
+                for i in 0..size / 2 {
+                    assert!(
+                        (w[i] - w[size - 1 - i]).abs() < 1e-5,
+                        "{win_func} of {size} at {i}: {} vs {}",
+                        w[i],
+                        w[size - 1 - i]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn windows_peak_at_one() {
+        use SpectogramWinFunc as W;
+        for win_func in [
+            W::Hann,
+            W::Hamming,
+            W::Blackman,
+            W::Rect,
+            W::Bartlett,
+            W::Welch,
+        ] {
+            let w = window(win_func, 65);
+            assert!((w[32] - 1.0).abs() < 1e-5, "{win_func}: {}", w[32]);
+            assert!(w.iter().all(|&x| x <= 1.0 + 1e-5), "{win_func}");
+        }
+    }
+
+    #[test]
+    fn hann_window_sums_to_half_its_span() {
+        let w = window(SpectogramWinFunc::Hann, 1025);
+        assert!(w[0].abs() < 1e-6 && w[1024].abs() < 1e-6);
+        let sum: f64 = w.iter().map(|&x| x as f64).sum();
+        assert!((sum - 512.0).abs() < 1e-3, "{sum}");
+    }
+}
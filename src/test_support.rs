@@ -0,0 +1,97 @@
+//! Signals and files shared by the unit tests.
+
+use crate::playback::{AudioSink, WavSink};
+use std::path::{Path, PathBuf};
+
+/// Small xorshift generator, the same sequence for the same seed.
+pub struct Noise(u64);
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        // An all-zero state never leaves zero.
+        Self(seed.max(1))
+    }
+
+    /// Uniform in 0.0..1.0.
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// `samples` of white noise, uniform in `-amplitude..amplitude`.
+pub fn white_noise(seed: u64, samples: usize, amplitude: f32) -> Vec<f32> {
+    let mut noise = Noise::new(seed);
+    (0..samples)
+        .map(|_| amplitude * (2.0 * noise.next_f64() as f32 - 1.0))
+        .collect()
+}
+
+/// `name` in the temp directory, unique to this process.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("spek-rs-{}-{}", std::process::id(), name))
+}
+
+/// File in the temp directory, unique to this process, removed when dropped.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        Self(temp_path(name))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn path_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// Directory in the temp directory, unique to this process, removed with
+/// everything in it when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = temp_path(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Creates an empty file at `relative`, with its parent directories.
+    pub fn touch(&self, relative: &str) -> PathBuf {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"").unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Writes interleaved `samples` to a 32-bit float WAV file in the temp directory.
+pub fn write_wav(name: &str, sample_rate: u32, channels: usize, samples: &[f32]) -> TempFile {
+    let file = TempFile::new(&format!("{}.wav", name));
+    let mut sink = WavSink::create(file.path(), sample_rate, channels).unwrap();
+    sink.write(samples).unwrap();
+    sink.finish().unwrap();
+    file
+}
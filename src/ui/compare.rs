@@ -0,0 +1,227 @@
+use eframe::egui;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+use super::{to_color_image, MyApp};
+use crate::render;
+use crate::settings::CompareLayout;
+use crate::spectrogram::MagnitudeMatrix;
+
+impl MyApp {
+    /// File B when a comparison is shown. Live mode shows the open file only.
+    pub(super) fn comparing(&self) -> Option<&String> {
+        self.compare_path
+            .as_ref()
+            .filter(|_| !self.settings.live_mode)
+    }
+
+    /// Levels of both files of the shown comparison (native engine only).
+    pub(super) fn comparison_magnitudes(&self) -> Option<&[MagnitudeMatrix; 2]> {
+        self.comparing()?;
+        self.comparison.as_ref()?.magnitudes.as_ref()
+    }
+
+    /// Renders the open file and file B in the background, see `poll_comparison`.
+    pub(super) fn regenerate_comparison(
+        &mut self,
+        ctx: &egui::Context,
+        input_path: String,
+        compare_path: String,
+    ) {
+        self.comparison = None;
+        self.comparison_plots.clear();
+        self.final_image = None;
+        self.texture = None;
+
+        let (width, height) = self.spectrogram_size();
+        let settings = self.settings.clone();
+        let (sender, receiver) = mpsc::channel();
+        self.comparison_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.generation_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let comparison = render::render_comparison_spectrograms(
+                &input_path,
+                &compare_path,
+                &settings,
+                width,
+                height,
+                cancel_token,
+            );
+            sender.send(comparison).ok();
+            ctx_clone.request_repaint();
+        });
+    }
+
+    pub(super) fn poll_comparison(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.comparison_receiver else {
+            return;
+        };
+        let Ok(comparison) = receiver.try_recv() else {
+            return;
+        };
+        self.is_generating = false;
+        self.comparison_receiver = None;
+        match comparison {
+            Ok(comparison) => {
+                self.comparison = Some(comparison);
+                self.show_comparison(ctx);
+            }
+            Err(e) => self.fail_generation(e),
+        }
+    }
+
+    /// Arranges the rendered comparison in the current layout.
+    pub(super) fn show_comparison(&mut self, ctx: &egui::Context) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        let composed = render::compose_comparison(
+            comparison,
+            &self.settings,
+            self.settings.compare_layout,
+            self.show_compare_b,
+        );
+        match composed {
+            Ok(composed) => {
+                let image = to_color_image(&composed.image);
+                self.texture =
+                    Some(ctx.load_texture("spectrogram", image.clone(), Default::default()));
+                self.final_image = Some(image);
+                self.comparison_plots = composed.plots;
+            }
+            Err(e) => self.fail_generation(e),
+        }
+    }
+
+    /// Level differences B minus A, when the difference of the files is shown.
+    pub(super) fn comparison_difference(&self) -> Option<&MagnitudeMatrix> {
+        self.comparing()?;
+        if self.settings.compare_layout != CompareLayout::Difference {
+            return None;
+        }
+        Some(&self.comparison.as_ref()?.difference.as_ref()?.matrix)
+    }
+
+    /// Shows the other file in place, carrying on playback in it.
+    pub(super) fn flip_comparison(&mut self, ctx: &egui::Context) {
+        if self.comparing().is_none() {
+            return;
+        }
+        let resume = self
+            .player
+            .as_ref()
+            .map(|player| (player.position(), player.is_playing()));
+
+        // From the other layouts, A is on screen already. The difference
+        // was rendered with B shifted, so B is rendered again as it is.
+        if self.settings.compare_layout == CompareLayout::Flip {
+            self.show_compare_b = !self.show_compare_b;
+            self.show_comparison(ctx);
+        } else {
+            let rendered_shifted = self.settings.compare_layout == CompareLayout::Difference;
+            self.settings.compare_layout = CompareLayout::Flip;
+            self.show_compare_b = true;
+            match rendered_shifted {
+                true => self.regenerate_spectrogram(ctx),
+                false => self.show_comparison(ctx),
+            }
+        }
+
+        if let Some((position, playing)) = resume {
+            self.seek_playback(position);
+            if let Some(player) = self.player.as_ref().filter(|_| playing) {
+                player.play();
+            }
+        }
+    }
+
+    pub(super) fn show_compare_controls(
+        &mut self,
+        ctx: &egui::Context,
+        ui: &mut egui::Ui,
+        trigger_regeneration: &mut bool,
+    ) {
+        if self.input_path.is_none() {
+            return;
+        }
+        ui.add_enabled_ui(!self.settings.live_mode, |ui| {
+            if self.compare_path.is_none() {
+                if ui
+                    .button("Compare With...")
+                    .on_hover_text("Render a second file with the same settings")
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new().pick_file() {
+                        self.compare_path = Some(path.display().to_string());
+                        self.show_compare_b = false;
+                        *trigger_regeneration = true;
+                    }
+                }
+                return;
+            }
+
+            let old_layout = self.settings.compare_layout;
+            egui::ComboBox::from_id_salt("compare_layout_combo")
+                .selected_text(old_layout.to_string())
+                .show_ui(ui, |ui| {
+                    for layout in CompareLayout::VALUES {
+                        ui.selectable_value(
+                            &mut self.settings.compare_layout,
+                            layout,
+                            layout.to_string(),
+                        );
+                    }
+                });
+            let new_layout = self.settings.compare_layout;
+            if new_layout != old_layout {
+                // Only the difference lines B up with A and subtracts the levels.
+                if (old_layout == CompareLayout::Difference)
+                    != (new_layout == CompareLayout::Difference)
+                {
+                    *trigger_regeneration = true;
+                } else {
+                    self.show_comparison(ctx);
+                }
+            }
+            if new_layout == CompareLayout::Difference {
+                let response = ui
+                    .add(
+                        egui::DragValue::new(&mut self.settings.difference_range)
+                            .speed(1.0)
+                            .range(1.0..=120.0)
+                            .prefix("±")
+                            .suffix(" dB"),
+                    )
+                    .on_hover_text("Difference shown in the end colours of the palette.");
+                if response.changed() {
+                    self.show_comparison(ctx);
+                }
+            }
+            if self.settings.compare_layout == CompareLayout::Flip
+                && ui
+                    .button(if self.show_compare_b { "B" } else { "A" })
+                    .on_hover_text("Flip between the files (X)")
+                    .clicked()
+            {
+                self.flip_comparison(ctx);
+            }
+            if ui.button("Stop Comparing").clicked() {
+                if self.player.as_ref().map(|player| player.input_path())
+                    == self.compare_path.as_deref()
+                {
+                    self.player = None;
+                }
+                self.compare_path = None;
+                self.comparison = None;
+                self.comparison_plots.clear();
+                self.show_compare_b = false;
+                *trigger_regeneration = true;
+            }
+        });
+    }
+}
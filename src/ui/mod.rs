@@ -1,28 +1,50 @@
 use eframe::egui::{self, Color32, ColorImage};
+use image::RgbaImage;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver};
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 
+use viewer::PlotView;
+
+use crate::analysis::{self, AnalysisReport};
+use crate::error::{Error, Result};
 use crate::legend;
-use crate::settings::AppSettings;
+use crate::playback::Player;
+use crate::render::{self, Comparison};
+use crate::settings::{AppSettings, SpectrogramBackend};
+use crate::spectrogram::{self, DbRange, MagnitudeMatrix};
 use crate::utils;
 
+mod compare;
 pub mod ffmpeg_setup;
 pub use ffmpeg_setup::FfmpegSetup;
+mod playback;
 mod settings_panel;
+mod status_bar;
+mod viewer;
 mod window_about;
 mod window_help;
 mod window_keybindings;
 mod window_legend_settings;
 
+/// dB levels of the last native render, kept so colour changes don't need a new decode.
+struct CachedMagnitudes {
+    input_path: String,
+    settings: AppSettings,
+    matrix: MagnitudeMatrix,
+}
+
 pub struct MyApp {
     texture: Option<egui::TextureHandle>,
     final_image: Option<eframe::egui::ColorImage>,
     input_path: Option<String>,
     settings: AppSettings,
     is_generating: bool,
-    image_receiver: Option<Receiver<Option<ColorImage>>>,
+    image_receiver: Option<Receiver<Result<RgbaImage>>>,
+    magnitude_receiver: Option<Receiver<CachedMagnitudes>>,
+    magnitudes: Option<CachedMagnitudes>,
     spectrogram_slice_position: usize,
     about_window_open: bool,
     keybindings_window_open: bool,
@@ -30,6 +52,37 @@ pub struct MyApp {
     legend_settings_window_open: bool,
     audio_info: Option<utils::AudioInfo>,
     generation_cancel_token: Option<Arc<AtomicBool>>,
+    /// Why the last render failed, shown instead of the spectrogram.
+    last_error: Option<Error>,
+
+    // File analysis (runs in the background, independent of rendering)
+    analysis: Option<AnalysisReport>,
+    analysis_path: Option<String>,
+    analysis_error: Option<Error>,
+    analysis_receiver: Option<Receiver<Result<AnalysisReport>>>,
+    analysis_cancel_token: Option<Arc<AtomicBool>>,
+    legend_outdated: bool,
+
+    // Zoom and pan
+    /// View of the spectrogram being shown, for mapping the pointer onto it.
+    plot_view: Option<PlotView>,
+    /// Where a box zoom started.
+    zoom_box_origin: Option<egui::Pos2>,
+    /// Last zoom or pan step that is not rendered yet.
+    view_changed_at: Option<Instant>,
+
+    /// Playback of the open file, started the first time it is used.
+    player: Option<Player>,
+
+    // Comparison
+    /// File B, rendered with the same settings as the open file (A).
+    compare_path: Option<String>,
+    comparison: Option<Comparison>,
+    comparison_receiver: Option<Receiver<Result<Comparison>>>,
+    /// File index and top-left pixel of each plot in the shown comparison.
+    comparison_plots: Vec<(usize, (u32, u32))>,
+    /// The flip layout shows B instead of A.
+    show_compare_b: bool,
 
     // Keybinding triggers
     trigger_open_file: bool,
@@ -45,6 +98,8 @@ pub struct MyApp {
     trigger_gain_down: bool,
     trigger_saturation_up: bool,
     trigger_saturation_down: bool,
+    trigger_reset_view: bool,
+    trigger_flip_compare: bool,
 }
 
 impl MyApp {
@@ -54,7 +109,7 @@ impl MyApp {
         app_settings: AppSettings,
     ) -> Self {
         let audio_info = if let Some(path) = &input_path {
-            utils::get_audio_info(path)
+            utils::get_audio_info(path).ok()
         } else {
             None
         };
@@ -65,6 +120,8 @@ impl MyApp {
             settings: app_settings,
             is_generating: false,
             image_receiver: None,
+            magnitude_receiver: None,
+            magnitudes: None,
             spectrogram_slice_position: 0,
             about_window_open: false,
             keybindings_window_open: false,
@@ -72,6 +129,26 @@ impl MyApp {
             legend_settings_window_open: false,
             audio_info,
             generation_cancel_token: None,
+            last_error: None,
+
+            analysis: None,
+            analysis_error: None,
+            analysis_path: None,
+            analysis_receiver: None,
+            analysis_cancel_token: None,
+            legend_outdated: false,
+
+            plot_view: None,
+            zoom_box_origin: None,
+            view_changed_at: None,
+
+            player: None,
+
+            compare_path: None,
+            comparison: None,
+            comparison_receiver: None,
+            comparison_plots: Vec::new(),
+            show_compare_b: false,
 
             // Keybinding triggers
             trigger_open_file: false,
@@ -87,159 +164,252 @@ impl MyApp {
             trigger_gain_down: false,
             trigger_saturation_up: false,
             trigger_saturation_down: false,
+            trigger_reset_view: false,
+            trigger_flip_compare: false,
         }
     }
 
-// =====================================================================
-// HEADLESS / CLI SPECTROGRAM GENERATION (NO GUI)
-// =====================================================================
-pub fn regenerate_spectrogram_headless(&mut self) -> Option<ColorImage> {
-    let input_path = self.input_path.as_ref()?.clone();
-
-    // -------------------------------------------------
-    // Resolution handling (HEADLESS PRIORITY)
-    // -------------------------------------------------
-    // Priority:
-    // 1) Explicit PNG size from CLI (png_width / png_height)
-    // 2) Custom resolution (GUI / config)
-    // 3) Default fallback (500x320)
-    let (width, height) = if self.settings.png_width > 0 && self.settings.png_height > 0 {
-        (
-            self.settings.png_width as usize,
-            self.settings.png_height as usize,
-        )
-    } else if self.settings.custom_resolution {
-        (
-            self.settings.resolution[0] as usize,
-            self.settings.resolution[1] as usize,
-        )
-    } else {
-        (500, 320)
-    };
-
-    let cancel_token = Arc::new(AtomicBool::new(false));
-
-    // -------------------------------------------------
-    // FFmpeg must NEVER draw its own legend in headless
-    // -------------------------------------------------
-    let mut ffmpeg_settings = self.settings.clone();
-    ffmpeg_settings.legend = false;
-
-    let spectrogram = utils::generate_spectrogram_in_memory(
-        &input_path,
-        &ffmpeg_settings,
-        width,
-        height,
-        cancel_token,
-    )?;
-
-    // -------------------------------------------------
-    // No custom legend → return raw spectrogram
-    // -------------------------------------------------
-    if !self.settings.legend {
-        self.final_image = Some(spectrogram.clone());
-        return Some(spectrogram);
+    fn spectrogram_size(&self) -> (u32, u32) {
+        if self.settings.custom_resolution || self.settings.resize_with_window {
+            (self.settings.resolution[0], self.settings.resolution[1])
+        } else {
+            render::DEFAULT_SIZE
+        }
     }
 
-    // -------------------------------------------------
-    // Custom legend compositing (same logic as GUI)
-    // -------------------------------------------------
-    let filename = std::path::Path::new(&input_path)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Unknown File");
-
-    let ffmpeg_settings_text = format!(
-        "{}, {}, {}",
-        self.settings.win_func,
-        self.settings.scale,
-        self.settings.color_scheme
-    );
-
-    let legend_rgba = legend::draw_legend(
-        width as u32,
-        height as u32,
-        filename,
-        &ffmpeg_settings_text,
-        self.audio_info.clone(),
-        self.settings.saturation,
-        self.settings.color_scheme,
-        self.settings.split_channels,
-        self.settings.show_version_in_legend,
-    );
-
-    let mut final_image = utils::rgba_image_to_color_image(&legend_rgba);
+    fn draw_legend_image(&self, input_path: &str, width: u32, height: u32) -> Result<ColorImage> {
+        let legend_rgba = render::draw_legend(
+            input_path,
+            &self.settings,
+            width,
+            height,
+            self.audio_info.clone(),
+            self.analysis
+                .as_ref()
+                .filter(|_| self.settings.analyze_audio),
+            self.db_range(),
+        )?;
+        Ok(to_color_image(&legend_rgba))
+    }
 
-    for y in 0..spectrogram.height() {
-        for x in 0..spectrogram.width() {
-            let dest_x = x + legend::LEFT_MARGIN as usize;
-            let dest_y = y + legend::TOP_MARGIN as usize;
+    /// Levels the palette of the shown spectrogram spans, measured from the
+    /// cached magnitudes when they belong to it.
+    fn db_range(&self) -> DbRange {
+        if let Some(cached) = &self.magnitudes {
+            if Some(&cached.input_path) == self.input_path.as_ref()
+                && cached.settings.without_color_mapping() == self.settings.without_color_mapping()
+            {
+                return DbRange::for_matrix(&cached.matrix, &self.settings);
+            }
+        }
+        if self.settings.live_mode
+            || utils::effective_backend(&self.settings) == SpectrogramBackend::Ffmpeg
+        {
+            utils::ffmpeg_db_range(&self.settings)
+        } else {
+            DbRange::from_settings(&self.settings)
+        }
+    }
 
-            if dest_x < final_image.width() && dest_y < final_image.height() {
-                final_image[(dest_x, dest_y)] = spectrogram[(x, y)];
+    /// Stream parameters of the loaded file.
+    pub fn audio_info(&self) -> Option<&utils::AudioInfo> {
+        self.audio_info.as_ref()
+    }
+
+    /// Results of the last file analysis, if it ran and finished.
+    pub fn analysis(&self) -> Option<&AnalysisReport> {
+        self.analysis.as_ref()
+    }
+
+    /// Cancels a running analysis and forgets the results of the last one.
+    fn clear_analysis(&mut self) {
+        if let Some(token) = self.analysis_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.analysis = None;
+        self.analysis_error = None;
+        self.analysis_receiver = None;
+        self.analysis_path = None;
+    }
+
+    /// Starts analysing the current file in the background, replacing any running analysis.
+    fn start_analysis(&mut self, ctx: &egui::Context) {
+        self.clear_analysis();
+        self.analysis_path = self.input_path.clone();
+
+        let Some(input_path) = self.input_path.clone() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        self.analysis_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.analysis_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let report = analysis::analyze_file(&input_path, cancel_token);
+            sender.send(report).ok();
+            ctx_clone.request_repaint();
+        });
+    }
+
+    fn poll_analysis(&mut self, ctx: &egui::Context) {
+        if self.settings.analyze_audio && self.analysis_path != self.input_path {
+            self.start_analysis(ctx);
+        }
+
+        if let Some(receiver) = &self.analysis_receiver {
+            if let Ok(report) = receiver.try_recv() {
+                match report {
+                    Ok(report) => self.analysis = Some(report),
+                    Err(e) => self.analysis_error = Some(e),
+                }
+                self.analysis_receiver = None;
+                self.legend_outdated = true;
             }
         }
+
+        if self.legend_outdated && !self.is_generating {
+            self.legend_outdated = false;
+            self.redraw_legend(ctx);
+        }
     }
 
-    self.final_image = Some(final_image.clone());
-    Some(final_image)
-}
+    /// Draws a fresh legend around the spectrogram that is currently shown.
+    fn redraw_legend(&mut self, ctx: &egui::Context) {
+        if self.comparing().is_some() {
+            self.show_comparison(ctx);
+            return;
+        }
+        if !self.settings.uses_custom_legend() || self.settings.live_mode {
+            return;
+        }
+        let (Some(old_image), Some(input_path)) = (&self.final_image, self.input_path.clone())
+        else {
+            return;
+        };
+
+        let (width, height) = self.spectrogram_size();
+        let mut final_image = match self.draw_legend_image(&input_path, width, height) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        if final_image.size != old_image.size {
+            return;
+        }
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let pos = (
+                    x + legend::LEFT_MARGIN as usize,
+                    y + legend::TOP_MARGIN as usize,
+                );
+                final_image[pos] = old_image[pos];
+            }
+        }
+
+        self.texture =
+            Some(ctx.load_texture("spectrogram", final_image.clone(), Default::default()));
+        self.final_image = Some(final_image);
+    }
+
+    /// Re-applies the colour mapping to the cached magnitudes of the last render.
+    ///
+    /// Returns `false` when the current settings need a full regeneration.
+    fn recolor_spectrogram(&mut self, ctx: &egui::Context) -> bool {
+        let (Some(cached), Some(input_path)) = (&self.magnitudes, self.input_path.clone()) else {
+            return false;
+        };
+        if self.settings.live_mode
+            || self.comparing().is_some()
+            || cached.input_path != input_path
+            || cached.settings.without_color_mapping() != self.settings.without_color_mapping()
+        {
+            return false;
+        }
+
+        let start = Instant::now();
+        let spectrogram = to_color_image(&spectrogram::colorize(&cached.matrix, &self.settings));
+
+        let final_image = if self.settings.uses_custom_legend() {
+            let (width, height) = self.spectrogram_size();
+            // A full regeneration reports the error.
+            let Ok(mut final_image) = self.draw_legend_image(&input_path, width, height) else {
+                return false;
+            };
+            composite_spectrogram(&mut final_image, &spectrogram);
+            final_image
+        } else {
+            spectrogram
+        };
+
+        self.texture =
+            Some(ctx.load_texture("spectrogram", final_image.clone(), Default::default()));
+        self.final_image = Some(final_image);
+
+        if self.settings.remember_settings {
+            self.save_settings();
+        }
+        println!("Spectrogram recoloured in {:?}.", start.elapsed());
+        true
+    }
 
     fn regenerate_spectrogram(&mut self, ctx: &egui::Context) {
         if self.input_path.is_none() {
             return;
         }
 
+        if !self.is_generating && self.recolor_spectrogram(ctx) {
+            return;
+        }
+
         if let Some(token) = &self.generation_cancel_token {
             token.store(true, Ordering::Relaxed);
         }
 
         if self.settings.remember_settings {
-            self.settings.save();
+            self.save_settings();
         }
 
         self.is_generating = true;
+        self.magnitudes = None;
+        self.last_error = None;
+        self.view_changed_at = None;
+        self.plot_view = self
+            .audio_info
+            .as_ref()
+            .map(|info| PlotView::new(&self.settings, info));
         let input_path = self.input_path.clone().unwrap();
 
+        if let Some(compare_path) = self.comparing().cloned() {
+            self.regenerate_comparison(ctx, input_path, compare_path);
+            return;
+        }
+        self.comparison = None;
+
         let (sender, receiver) = mpsc::channel();
         self.image_receiver = Some(receiver);
+        let (magnitude_sender, magnitude_receiver) = mpsc::channel();
+        self.magnitude_receiver = Some(magnitude_receiver);
 
-        let (width, height) = if self.settings.custom_resolution || self.settings.resize_with_window
-        {
-            (self.settings.resolution[0], self.settings.resolution[1])
-        } else {
-            (500, 320)
-        };
+        let (width, height) = self.spectrogram_size();
 
-        let use_custom_legend =
-            self.settings.legend && (self.settings.custom_legend || self.settings.live_mode);
+        let use_custom_legend = self.settings.uses_custom_legend();
+        let rendered_settings = self.settings.clone();
+        let audio_info = self.audio_info.clone();
         let mut thread_settings = self.settings.clone();
 
         if use_custom_legend {
             self.spectrogram_slice_position = 0;
-            let filename = std::path::Path::new(&input_path)
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("Unknown File");
-            let ffmpeg_settings = format!(
-                "{}, {}, {}",
-                self.settings.win_func.to_string(),
-                self.settings.scale.to_string(),
-                self.settings.color_scheme.to_string()
-            );
-            let audio_info = self.audio_info.clone();
-
-            let legend_rgba = legend::draw_legend(
-                width as u32,
-                height as u32,
-                filename,
-                &ffmpeg_settings,
-                audio_info,
-                self.settings.saturation,
-                self.settings.color_scheme,
-                self.settings.split_channels,
-            );
-            let legend_color_image = utils::rgba_image_to_color_image(&legend_rgba);
+            let legend_color_image = match self.draw_legend_image(&input_path, width, height) {
+                Ok(image) => image,
+                Err(e) => {
+                    self.fail_generation(e);
+                    return;
+                }
+            };
 
             self.final_image = Some(legend_color_image.clone());
             self.texture =
@@ -267,34 +437,93 @@ pub fn regenerate_spectrogram_headless(&mut self) -> Option<ColorImage> {
         self.generation_cancel_token = Some(cancel_token.clone());
 
         thread::spawn(move || {
+            // The ffmpeg paths need the stream parameters, probed when the file was opened.
+            let probe = |info: Option<utils::AudioInfo>| {
+                info.map_or_else(|| utils::get_audio_info(&input_path), Ok)
+            };
             if thread_settings.live_mode {
-                utils::stream_spectrogram_frames(
-                    sender,
+                let streamed = probe(audio_info).and_then(|info| {
+                    utils::stream_spectrogram_frames(
+                        sender.clone(),
+                        &input_path,
+                        &info,
+                        &thread_settings,
+                        width,
+                        height,
+                        cancel_token,
+                    )
+                });
+                if let Err(e) = streamed {
+                    sender.send(Err(e)).ok();
+                }
+            } else if utils::effective_backend(&thread_settings) == SpectrogramBackend::Native {
+                // Keep the magnitudes so colour changes don't need another decode.
+                let image = utils::generate_magnitudes(
                     &input_path,
                     &thread_settings,
                     width,
                     height,
+                    None,
                     cancel_token,
-                );
+                )
+                .map(|matrix| {
+                    let image = spectrogram::colorize(&matrix, &thread_settings);
+                    magnitude_sender
+                        .send(CachedMagnitudes {
+                            input_path: input_path.clone(),
+                            settings: rendered_settings,
+                            matrix,
+                        })
+                        .ok();
+                    image
+                });
+                sender.send(image).ok();
             } else {
-                let image = utils::generate_spectrogram_in_memory(
-                    &input_path,
-                    &thread_settings,
-                    width,
-                    height,
-                    cancel_token,
-                );
-                if let Some(img) = image {
-                    sender.send(Some(img)).ok();
-                }
+                let image = probe(audio_info)
+                    .and_then(|info| {
+                        utils::generate_spectrogram_in_memory(
+                            &input_path,
+                            &info,
+                            &thread_settings,
+                            width,
+                            height,
+                            cancel_token,
+                        )
+                    })
+                    .map(|(image, _)| image);
+                sender.send(image).ok();
             }
             ctx_clone.request_repaint();
         });
     }
+
+    /// Ends a render that failed, dropping the legend template so the error is shown.
+    fn fail_generation(&mut self, error: Error) {
+        self.is_generating = false;
+        self.image_receiver = None;
+        self.magnitude_receiver = None;
+        self.comparison_receiver = None;
+        if matches!(error, Error::Cancelled) {
+            return;
+        }
+        eprintln!("Failed to generate spectrogram: {}", error);
+        self.texture = None;
+        self.final_image = None;
+        self.last_error = Some(error);
+    }
+
+    pub(super) fn save_settings(&self) {
+        if let Err(e) = self.settings.save() {
+            eprintln!("{}", e);
+        }
+    }
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Space and the arrows belong to a focused text field or button.
+        let keyboard_free = !ctx.wants_keyboard_input() && ctx.memory(|m| m.focused().is_none());
+        let (mut toggle_playback, mut seek_by, mut seek_to_start) = (false, 0.0, false);
         ctx.input(|i| {
             if i.key_pressed(egui::Key::Escape) {
                 // https://github.com/emilk/egui/discussions/4103#discussioncomment-9225022
@@ -304,6 +533,17 @@ impl eframe::App for MyApp {
                 });
             }
 
+            if keyboard_free && self.input_path.is_some() {
+                toggle_playback = i.key_pressed(egui::Key::Space);
+                if i.key_pressed(egui::Key::ArrowLeft) {
+                    seek_by -= playback::SEEK_STEP;
+                }
+                if i.key_pressed(egui::Key::ArrowRight) {
+                    seek_by += playback::SEEK_STEP;
+                }
+                seek_to_start = i.key_pressed(egui::Key::Home);
+            }
+
             if !self.is_generating {
                 if i.key_pressed(egui::Key::F1) {
                     self.help_window_open = !self.help_window_open;
@@ -359,15 +599,42 @@ impl eframe::App for MyApp {
                         self.trigger_saturation_down = true;
                     }
                 }
+                if i.key_pressed(egui::Key::R) {
+                    self.trigger_reset_view = true;
+                }
+                if i.key_pressed(egui::Key::X) {
+                    self.trigger_flip_compare = true;
+                }
             }
         });
 
+        if toggle_playback {
+            self.toggle_playback();
+        }
+        if seek_by != 0.0 {
+            self.seek_playback_by(seek_by);
+        }
+        if seek_to_start {
+            let start = self.plot_view.map_or(0.0, |view| view.start);
+            self.seek_playback(start);
+        }
+
+        if std::mem::take(&mut self.trigger_flip_compare) {
+            self.flip_comparison(ctx);
+        }
+
+        self.poll_analysis(ctx);
+        self.poll_view_change(ctx);
+        self.poll_playback(ctx);
+        self.poll_comparison(ctx);
+
         let mut trigger_regeneration_due_to_resize = false;
         if self.settings.resize_with_window {
             let inner_size = ctx.available_rect().size();
 
             let new_width = (inner_size.x - 180.0).max(100.0) as u32;
-            let new_height = (inner_size.y - 128.0 - 39.0).max(100.0) as u32;
+            let new_height =
+                (inner_size.y - 128.0 - 39.0 - self.status_bar_height()).max(100.0) as u32;
 
             let new_res = [new_width, new_height];
             if self.settings.resolution != new_res {
@@ -380,60 +647,66 @@ impl eframe::App for MyApp {
             self.regenerate_spectrogram(ctx);
         }
 
-        let use_custom_legend =
-            self.settings.legend && (self.settings.custom_legend || self.settings.live_mode);
+        let use_custom_legend = self.settings.uses_custom_legend();
 
+        let mut failure = None;
         if self.is_generating {
             if let Some(receiver) = &self.image_receiver {
                 if self.settings.live_mode {
                     // Live mode (always custom legend): receive slices and draw them
-                    for slice in receiver.try_iter() {
-                        if let Some(slice) = slice {
-                            if let Some(image) = self.final_image.as_mut() {
-                                let slice_width = slice.width();
-
-                                let (spec_width, x_offset, y_offset) = if use_custom_legend {
-                                    (
-                                        image.width()
-                                            - (legend::LEFT_MARGIN as usize
-                                                + legend::RIGHT_MARGIN as usize),
-                                        legend::LEFT_MARGIN as usize,
-                                        legend::TOP_MARGIN as usize,
-                                    )
-                                } else {
-                                    (image.width(), 0, 0)
-                                };
-
-                                if self.spectrogram_slice_position + slice_width <= spec_width {
-                                    for y in 0..slice.height() {
-                                        for x in 0..slice_width {
-                                            let dest_x =
-                                                self.spectrogram_slice_position + x + x_offset;
-                                            let dest_y = y + y_offset;
-                                            if dest_x < image.width() && dest_y < image.height() {
-                                                image[(dest_x, dest_y)] = slice[(x, y)];
-                                            }
+                    for message in receiver.try_iter() {
+                        let slice = match message {
+                            Ok(slice) => to_color_image(&slice),
+                            Err(e) => {
+                                failure = Some(e);
+                                break;
+                            }
+                        };
+                        if let Some(image) = self.final_image.as_mut() {
+                            let slice_width = slice.width();
+
+                            let (spec_width, x_offset, y_offset) = if use_custom_legend {
+                                (
+                                    image.width()
+                                        - (legend::LEFT_MARGIN as usize
+                                            + legend::RIGHT_MARGIN as usize),
+                                    legend::LEFT_MARGIN as usize,
+                                    legend::TOP_MARGIN as usize,
+                                )
+                            } else {
+                                (image.width(), 0, 0)
+                            };
+
+                            if self.spectrogram_slice_position + slice_width <= spec_width {
+                                for y in 0..slice.height() {
+                                    for x in 0..slice_width {
+                                        let dest_x = self.spectrogram_slice_position + x + x_offset;
+                                        let dest_y = y + y_offset;
+                                        if dest_x < image.width() && dest_y < image.height() {
+                                            image[(dest_x, dest_y)] = slice[(x, y)];
                                         }
                                     }
-                                    if let Some(texture) = self.texture.as_mut() {
-                                        texture.set(image.clone(), Default::default());
-                                    }
-                                    self.spectrogram_slice_position += slice_width;
                                 }
+                                if let Some(texture) = self.texture.as_mut() {
+                                    texture.set(image.clone(), Default::default());
+                                }
+                                self.spectrogram_slice_position += slice_width;
                             }
                         }
                     }
 
                     // A bit of a hack to check if the channel is disconnected
-                    if let Err(mpsc::TryRecvError::Disconnected) = receiver.try_recv() {
-                        self.is_generating = false;
-                        self.image_receiver = None;
+                    if failure.is_none() {
+                        if let Err(mpsc::TryRecvError::Disconnected) = receiver.try_recv() {
+                            self.is_generating = false;
+                            self.image_receiver = None;
 
-                        // Save window size after live spectrogram is ready
-                        if self.settings.save_window_size {
-                            let inner_size = ctx.available_rect().size();
-                            self.settings.window_size = [inner_size.x, inner_size.y];
-                            self.settings.save();
+                            // Save window size after live spectrogram is ready
+                            if self.settings.save_window_size {
+                                let inner_size = ctx.available_rect().size();
+                                self.settings.window_size = [inner_size.x, inner_size.y];
+                                self.save_settings();
+                            }
                         }
                     }
                 } else {
@@ -441,50 +714,64 @@ impl eframe::App for MyApp {
                     if let Ok(maybe_image) = receiver.try_recv() {
                         self.is_generating = false;
                         self.image_receiver = None;
-                        if let Some(new_spectrogram) = maybe_image {
-                            if use_custom_legend {
-                                // Composite onto custom legend
-                                if let Some(final_image) = self.final_image.as_mut() {
-                                    for y in 0..new_spectrogram.height() {
-                                        for x in 0..new_spectrogram.width() {
-                                            let dest_x = x + legend::LEFT_MARGIN as usize;
-                                            let dest_y = y + legend::TOP_MARGIN as usize;
-                                            if dest_x < final_image.width()
-                                                && dest_y < final_image.height()
-                                            {
-                                                final_image[(dest_x, dest_y)] =
-                                                    new_spectrogram[(x, y)];
+                        if let Some(magnitude_receiver) = self.magnitude_receiver.take() {
+                            self.magnitudes = magnitude_receiver.try_recv().ok();
+                            // The template was drawn before the levels were measured.
+                            if self.settings.auto_db_range && self.magnitudes.is_some() {
+                                self.legend_outdated = true;
+                            }
+                        }
+                        match maybe_image {
+                            Ok(new_spectrogram) => {
+                                let new_spectrogram = to_color_image(&new_spectrogram);
+                                if use_custom_legend {
+                                    // Composite onto custom legend
+                                    if let Some(final_image) = self.final_image.as_mut() {
+                                        for y in 0..new_spectrogram.height() {
+                                            for x in 0..new_spectrogram.width() {
+                                                let dest_x = x + legend::LEFT_MARGIN as usize;
+                                                let dest_y = y + legend::TOP_MARGIN as usize;
+                                                if dest_x < final_image.width()
+                                                    && dest_y < final_image.height()
+                                                {
+                                                    final_image[(dest_x, dest_y)] =
+                                                        new_spectrogram[(x, y)];
+                                                }
                                             }
                                         }
+                                        self.texture = Some(ctx.load_texture(
+                                            "spectrogram",
+                                            final_image.clone(),
+                                            Default::default(),
+                                        ));
                                     }
+                                } else {
+                                    // Display ffmpeg-generated image directly
                                     self.texture = Some(ctx.load_texture(
                                         "spectrogram",
-                                        final_image.clone(),
+                                        new_spectrogram.clone(),
                                         Default::default(),
                                     ));
+                                    self.final_image = Some(new_spectrogram);
                                 }
-                            } else {
-                                // Display ffmpeg-generated image directly
-                                self.texture = Some(ctx.load_texture(
-                                    "spectrogram",
-                                    new_spectrogram.clone(),
-                                    Default::default(),
-                                ));
-                                self.final_image = Some(new_spectrogram);
-                            }
 
-                            // Save window size after spectrogram is ready
-                            if self.settings.save_window_size {
-                                let inner_size = ctx.available_rect().size();
-                                self.settings.window_size = [inner_size.x, inner_size.y];
-                                self.settings.save();
+                                // Save window size after spectrogram is ready
+                                if self.settings.save_window_size {
+                                    let inner_size = ctx.available_rect().size();
+                                    self.settings.window_size = [inner_size.x, inner_size.y];
+                                    self.save_settings();
+                                }
                             }
+                            Err(e) => failure = Some(e),
                         }
                     }
                 }
                 ctx.request_repaint();
             }
         }
+        if let Some(error) = failure {
+            self.fail_generation(error);
+        }
 
         if self.texture.is_none() && self.final_image.is_some() {
             if let Some(image) = self.final_image.as_ref() {
@@ -493,6 +780,8 @@ impl eframe::App for MyApp {
             }
         }
 
+        self.show_status_bar(ctx);
+
         egui::CentralPanel::default()
             .frame(egui::Frame::NONE.fill(Color32::BLACK))
             .show(ctx, |ui| {
@@ -519,31 +808,13 @@ impl eframe::App for MyApp {
                     });
                 }
 
-                if let Some(texture) = &self.texture {
-                    let available_size = ui.available_size();
-                    let image_size = texture.size_vec2();
-
-                    let image_aspect = image_size.x / image_size.y;
-                    let available_aspect = available_size.x / available_size.y;
-
-                    let new_size = if image_aspect > available_aspect {
-                        // Fit to width
-                        egui::vec2(available_size.x, available_size.x / image_aspect)
-                    } else {
-                        // Fit to height
-                        egui::vec2(available_size.y * image_aspect, available_size.y)
-                    };
-
-                    ui.centered_and_justified(|ui| {
-                        if self.settings.custom_resolution {
-                            ui.image((texture.id(), new_size));
-                        } else {
-                            ui.add(egui::Image::from_texture(texture));
-                        }
-                    });
+                if let Some(texture) = self.texture.clone() {
+                    self.show_spectrogram(ui, &texture);
                 } else if !self.is_generating {
                     ui.centered_and_justified(|ui| {
-                        if self.input_path.is_some() {
+                        if let Some(error) = &self.last_error {
+                            ui.label(format!("Failed to generate spectrogram: {}", error));
+                        } else if self.input_path.is_some() {
                             ui.label("Failed to generate or load spectrogram.");
                         } else {
                             ui.label("Open a file to begin.");
@@ -569,3 +840,22 @@ impl eframe::App for MyApp {
         }
     }
 }
+
+/// Converts an `image::RgbaImage` to an `eframe::egui::ColorImage`.
+fn to_color_image(rgba_image: &RgbaImage) -> ColorImage {
+    let size = [rgba_image.width() as usize, rgba_image.height() as usize];
+    ColorImage::from_rgba_unmultiplied(size, rgba_image.as_raw())
+}
+
+/// Copies a rendered spectrogram into the plot area of a legend template.
+fn composite_spectrogram(final_image: &mut ColorImage, spectrogram: &ColorImage) {
+    for y in 0..spectrogram.height() {
+        for x in 0..spectrogram.width() {
+            let dest_x = x + legend::LEFT_MARGIN as usize;
+            let dest_y = y + legend::TOP_MARGIN as usize;
+            if dest_x < final_image.width() && dest_y < final_image.height() {
+                final_image[(dest_x, dest_y)] = spectrogram[(x, y)];
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use eframe::egui;
+use std::time::Duration;
+
+use super::viewer::PlotView;
+use super::MyApp;
+use crate::error::Result;
+use crate::playback::{AudioSink, NullSink, Player};
+use crate::settings::CompareLayout;
+use crate::utils;
+
+/// Seconds the arrow keys move the playback position by.
+pub(super) const SEEK_STEP: f64 = 5.0;
+/// Repaint interval that keeps the playhead moving.
+const PLAYHEAD_REFRESH: Duration = Duration::from_millis(30);
+
+impl MyApp {
+    /// File being listened to: the open one, or B while a flipped comparison shows it.
+    fn playback_path(&self) -> Option<&String> {
+        match self.comparing() {
+            Some(compare_path)
+                if self.show_compare_b && self.settings.compare_layout == CompareLayout::Flip =>
+            {
+                Some(compare_path)
+            }
+            _ => self.input_path.as_ref(),
+        }
+    }
+
+    /// Player of the file being listened to, started on first use.
+    fn player(&mut self) -> Option<&Player> {
+        let input_path = self.playback_path()?;
+        if self
+            .player
+            .as_ref()
+            .is_none_or(|player| player.input_path() != input_path)
+        {
+            self.player = Some(Player::new(input_path, Box::new(open_sink)));
+        }
+        self.player.as_ref()
+    }
+
+    pub(super) fn toggle_playback(&mut self) {
+        let view = self.plot_view;
+        let Some(player) = self.player() else {
+            return;
+        };
+        if player.is_playing() {
+            player.pause();
+            return;
+        }
+        // Play what is shown, not a part of the file that is zoomed out of view.
+        if let Some(view) = view {
+            let position = player.position();
+            if position < view.start || position >= view.end {
+                player.seek(view.start);
+            }
+        }
+        player.play();
+    }
+
+    /// Moves playback to `seconds` from the start of the file.
+    pub(super) fn seek_playback(&mut self, seconds: f64) {
+        if let Some(player) = self.player() {
+            player.seek(seconds);
+        }
+    }
+
+    /// Moves playback by `seconds`, backwards when negative.
+    pub(super) fn seek_playback_by(&mut self, seconds: f64) {
+        if let Some(player) = self.player() {
+            player.seek(player.position() + seconds);
+        }
+    }
+
+    /// Reports a failed playback and keeps the playhead moving while playing.
+    pub(super) fn poll_playback(&mut self, ctx: &egui::Context) {
+        let Some(player) = &self.player else {
+            return;
+        };
+        if let Some(e) = player.take_error() {
+            eprintln!("Playback failed: {}", e);
+            self.player = None;
+            return;
+        }
+        if player.is_playing() {
+            ctx.request_repaint_after(PLAYHEAD_REFRESH);
+        }
+    }
+
+    pub(super) fn show_playback_controls(&mut self, ui: &mut egui::Ui) {
+        if self.input_path.is_none() {
+            return;
+        }
+        let playing = self.player.as_ref().is_some_and(Player::is_playing);
+        let button = ui.button(if playing { "Pause" } else { "Play" });
+        #[cfg(not(feature = "playback"))]
+        let button = button.on_hover_text(
+            "Built without the playback feature: the playhead moves, but no sound is played.",
+        );
+        if button.clicked() {
+            self.toggle_playback();
+        }
+        if let (Some(player), Some(info)) = (&self.player, &self.audio_info) {
+            let hours = info.duration >= 3600.0;
+            ui.label(format!(
+                "{} / {}",
+                utils::format_time(player.position(), 0.1, hours),
+                utils::format_time(info.duration, 0.1, hours)
+            ));
+        }
+    }
+
+    /// Draws the playback position as a vertical line over the plot.
+    pub(super) fn draw_playhead(&self, ui: &egui::Ui, plot: egui::Rect, view: &PlotView) {
+        let Some(player) = &self.player else {
+            return;
+        };
+        let position = player.position();
+        if position < view.start || position > view.end || view.end <= view.start {
+            return;
+        }
+        let x =
+            plot.left() + plot.width() * ((position - view.start) / (view.end - view.start)) as f32;
+        ui.painter().vline(
+            x,
+            plot.y_range(),
+            egui::Stroke::new(1.0, egui::Color32::WHITE),
+        );
+    }
+}
+
+/// The sound card when it can be opened, otherwise a sink that plays
+/// silently in real time so the playhead still moves.
+fn open_sink(sample_rate: u32, channels: usize) -> Result<Box<dyn AudioSink>> {
+    #[cfg(feature = "playback")]
+    match crate::playback::CpalSink::open(sample_rate, channels) {
+        Ok(sink) => return Ok(Box::new(sink)),
+        Err(e) => eprintln!("{}, playing silently", e),
+    }
+    Ok(Box::new(NullSink::new(sample_rate, channels, true)))
+}
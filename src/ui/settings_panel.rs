@@ -1,13 +1,30 @@
-use eframe::egui;
+use eframe::egui::{self, ColorImage};
+use std::path::Path;
 
 use super::MyApp;
-use crate::settings::{AppSettings, SpectogramWinFunc, SpectrogramColorScheme, SpectrogramScale};
+use crate::cqt;
+use crate::error::{Error, Result};
+use crate::settings::{
+    AppSettings, FrequencyScale, SpectogramWinFunc, SpectrogramBackend, SpectrogramColorScheme,
+    SpectrogramScale, SpectrogramView, StereoView,
+};
+use crate::utils;
+
+/// Frequency bands offered in the band menu: name, lowest and highest
+/// frequency in Hz (0 for the Nyquist frequency).
+const FREQUENCY_BANDS: [(&str, f32, f32); 5] = [
+    ("Full", 0.0, 0.0),
+    ("Hum (0-500 Hz)", 0.0, 500.0),
+    ("Speech (0-8 kHz)", 0.0, 8000.0),
+    ("Bass (20-250 Hz)", 20.0, 250.0),
+    ("Encoder cutoff (15-22 kHz)", 15000.0, 22050.0),
+];
 
 impl MyApp {
     pub(super) fn show_settings_panel(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         let mut trigger_regeneration = false;
 
-        self.show_file_buttons(ui, &mut trigger_regeneration);
+        self.show_file_buttons(ctx, ui, &mut trigger_regeneration);
         self.show_settings_controls(ctx, ui, &mut trigger_regeneration);
 
         if trigger_regeneration && !self.is_generating {
@@ -15,7 +32,12 @@ impl MyApp {
         }
     }
 
-    fn show_file_buttons(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+    fn show_file_buttons(
+        &mut self,
+        ctx: &egui::Context,
+        ui: &mut egui::Ui,
+        trigger_regeneration: &mut bool,
+    ) {
         ui.add_enabled_ui(!self.is_generating, |ui| {
             let open_button_clicked = ui.button("Open File...").clicked();
             if open_button_clicked || self.trigger_open_file {
@@ -23,7 +45,14 @@ impl MyApp {
                 if let Some(path) = rfd::FileDialog::new().pick_file() {
                     self.input_path = Some(path.display().to_string());
                     self.audio_info =
-                        crate::utils::get_audio_info(self.input_path.as_ref().unwrap());
+                        crate::utils::get_audio_info(self.input_path.as_ref().unwrap()).ok();
+                    // The zoom belongs to the file it was picked for.
+                    self.settings.start_time = 0.0;
+                    self.settings.end_time = 0.0;
+                    self.settings.freq_min_hz = 0.0;
+                    self.settings.freq_max_hz = 0.0;
+                    self.player = None;
+                    self.clear_analysis();
                     *trigger_regeneration = true;
                 }
             }
@@ -33,11 +62,16 @@ impl MyApp {
                 if save_button_clicked || self.trigger_save_as {
                     self.trigger_save_as = false;
                     if let Some(input_path) = &self.input_path {
-                        crate::utils::save_image(&self.final_image, input_path);
+                        if let Err(e) = save_image(&self.final_image, input_path) {
+                            eprintln!("{}", e);
+                        }
                     }
                 }
             }
+
+            self.show_compare_controls(ctx, ui, trigger_regeneration);
         });
+        self.show_playback_controls(ui);
     }
 
     fn show_settings_controls(
@@ -51,7 +85,7 @@ impl MyApp {
             let has_multiple_channels = self
                 .audio_info
                 .as_ref()
-                .map_or(false, |info| info.channels > 1);
+                .is_some_and(|info| info.channels > 1);
             if has_multiple_channels {
                 self.settings.split_channels = !self.settings.split_channels;
                 *trigger_regeneration = true;
@@ -101,6 +135,10 @@ impl MyApp {
 
                     ui.add_space(4.0);
 
+                    self.show_view_combo(ui, trigger_regeneration);
+
+                    ui.add_space(4.0);
+
                     self.show_color_scheme_combo(ui, trigger_regeneration);
                 });
             });
@@ -127,7 +165,10 @@ impl MyApp {
                     }
 
                     if self.settings.legend {
-                        if self.settings.live_mode {
+                        // Both are drawn through the custom legend only.
+                        if self.settings.live_mode
+                            || self.settings.frequency_scale != FrequencyScale::Linear
+                        {
                             ui.add_enabled(
                                 false,
                                 egui::Checkbox::new(&mut dummy_true, "Custom Legend"),
@@ -140,17 +181,15 @@ impl MyApp {
                             *trigger_regeneration = true;
                         }
 
-                        if self.settings.custom_legend
-                            || (self.settings.legend && self.settings.live_mode)
-                        {
-                            if ui
+                        if (self.settings.custom_legend
+                            || (self.settings.legend && self.settings.live_mode))
+                            && ui
                                 .button("Legend settings")
                                 .on_hover_text("Customize custom legend appearance.")
                                 .clicked()
-                            {
-                                self.legend_settings_window_open = true;
-                                ui.close();
-                            }
+                        {
+                            self.legend_settings_window_open = true;
+                            ui.close();
                         }
                         ui.separator();
                     }
@@ -175,7 +214,7 @@ impl MyApp {
                         .on_hover_text("Save and restore window size on startup.")
                         .changed()
                     {
-                        self.settings.save();
+                        self.save_settings();
                     }
 
                     ui.add_enabled_ui(!self.settings.resize_with_window, |ui| {
@@ -187,7 +226,7 @@ impl MyApp {
                     let has_multiple_channels = self
                         .audio_info
                         .as_ref()
-                        .map_or(false, |info| info.channels > 1);
+                        .is_some_and(|info| info.channels > 1);
 
                     if has_multiple_channels {
                         if ui
@@ -196,6 +235,11 @@ impl MyApp {
                         {
                             *trigger_regeneration = true;
                         }
+                        self.show_stereo_view_combo(ui, trigger_regeneration);
+                        // The stereo views always take the left and right channels.
+                        if self.settings.stereo_view == StereoView::Channels {
+                            self.show_channel_selection(ui, trigger_regeneration);
+                        }
                     } else {
                         ui.add_enabled(
                             false,
@@ -203,7 +247,10 @@ impl MyApp {
                         );
                     }
 
-                    if self.settings.live_mode || self.settings.custom_legend {
+                    if self.settings.live_mode
+                        || self.settings.custom_legend
+                        || self.settings.frequency_scale != FrequencyScale::Linear
+                    {
                         ui.add_enabled(false, egui::Checkbox::new(&mut dummy_false, "Horizontal"));
                     } else if ui
                         .checkbox(&mut self.settings.horizontal, "Horizontal")
@@ -212,10 +259,29 @@ impl MyApp {
                         *trigger_regeneration = true;
                     }
 
+                    if self.settings.view == SpectrogramView::ConstantQ {
+                        self.show_constant_q_controls(ui, trigger_regeneration);
+                    } else {
+                        self.show_frequency_scale_combo(ui, trigger_regeneration);
+                        self.show_frequency_band_controls(ui, trigger_regeneration);
+                    }
+                    self.show_time_range_controls(ui, trigger_regeneration);
                     self.show_gain_drag(ui, trigger_regeneration);
                     self.show_saturation_drag(ui, trigger_regeneration);
+                    self.show_db_range_controls(ui, trigger_regeneration);
 
-                    if ui
+                    ui.separator();
+
+                    self.show_backend_controls(ui, trigger_regeneration);
+
+                    ui.separator();
+
+                    if self.settings.view == SpectrogramView::ConstantQ {
+                        ui.add_enabled(
+                            false,
+                            egui::Checkbox::new(&mut dummy_false, "Live mode (WIP)"),
+                        );
+                    } else if ui
                         .checkbox(&mut self.settings.live_mode, "Live mode (WIP)")
                         .on_hover_text(
                             "Generates a lower quality image, but shows the result instantly.",
@@ -225,13 +291,40 @@ impl MyApp {
                         *trigger_regeneration = true;
                     }
 
+                    if ui
+                        .checkbox(&mut self.settings.analyze_audio, "Analyse audio")
+                        .on_hover_text(
+                            "Check for lossy transcodes and fake stereo and show the results in the status bar and legend.",
+                        )
+                        .changed()
+                    {
+                        self.legend_outdated = true;
+                    }
+                    if self.settings.analyze_audio && self.settings.uses_custom_legend() {
+                        if ui
+                            .checkbox(&mut self.settings.correlation_strip, "Correlation strip")
+                            .on_hover_text(
+                                "Draw the left/right correlation over time under the time axis: \
+                                 green for correlated, amber for unrelated, red for inverted channels.",
+                            )
+                            .changed()
+                        {
+                            self.legend_outdated = true;
+                        }
+                    } else {
+                        ui.add_enabled(
+                            false,
+                            egui::Checkbox::new(&mut dummy_false, "Correlation strip"),
+                        );
+                    }
+
                     ui.separator();
 
                     if ui
                         .checkbox(&mut self.settings.remember_settings, "Save settings")
                         .changed()
                     {
-                        self.settings.save();
+                        self.save_settings();
                     }
 
                     if ui.button("Reset settings").clicked() {
@@ -371,6 +464,27 @@ impl MyApp {
         }
     }
 
+    fn show_view_combo(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let old_view = self.settings.view;
+        egui::ComboBox::from_label("View:")
+            .selected_text(self.settings.view.to_string())
+            .width(80.0)
+            .show_ui(ui, |ui| {
+                for view in SpectrogramView::VALUES {
+                    ui.selectable_value(&mut self.settings.view, view, view.to_string());
+                }
+            })
+            .response
+            .on_hover_text("STFT has evenly spaced bins, Constant-Q has bins spaced in semitones and a note axis.");
+        if self.settings.view != old_view {
+            // Live mode streams from ffmpeg, which has no constant-Q picture.
+            if self.settings.view == SpectrogramView::ConstantQ {
+                self.settings.live_mode = false;
+            }
+            *trigger_regeneration = true;
+        }
+    }
+
     fn show_saturation_drag(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
         let saturation_drag_value = egui::DragValue::new(&mut self.settings.saturation)
             .speed(0.1)
@@ -383,6 +497,55 @@ impl MyApp {
         }
     }
 
+    fn show_db_range_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        if self.settings.live_mode {
+            let mut dummy_false = false;
+            ui.add_enabled(
+                false,
+                egui::Checkbox::new(&mut dummy_false, "Auto level range"),
+            );
+        } else if ui
+            .checkbox(&mut self.settings.auto_db_range, "Auto level range")
+            .on_hover_text(
+                "Fit the colours to the file's noise floor and peak. Uses the native engine.",
+            )
+            .changed()
+        {
+            *trigger_regeneration = true;
+        }
+
+        ui.add_enabled_ui(!self.settings.auto_db_range, |ui| {
+            let ceiling = self.settings.db_ceiling;
+            let floor_response = ui
+                .add(
+                    egui::DragValue::new(&mut self.settings.db_floor)
+                        .speed(1.0)
+                        .range(-200.0..=ceiling - 1.0)
+                        .prefix("Floor: ")
+                        .suffix(" dB"),
+                )
+                .on_hover_text("Level shown in the bottom colour of the palette.");
+
+            let floor = self.settings.db_floor;
+            let ceiling_response = ui
+                .add(
+                    egui::DragValue::new(&mut self.settings.db_ceiling)
+                        .speed(1.0)
+                        .range(floor + 1.0..=20.0)
+                        .prefix("Ceiling: ")
+                        .suffix(" dB"),
+                )
+                .on_hover_text("Level shown in the top colour of the palette.");
+
+            if [floor_response, ceiling_response]
+                .iter()
+                .any(|response| response.drag_stopped() || response.lost_focus())
+            {
+                *trigger_regeneration = true;
+            }
+        });
+    }
+
     fn show_gain_drag(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
         let gain_drag_value = egui::DragValue::new(&mut self.settings.gain)
             .speed(0.1)
@@ -395,6 +558,317 @@ impl MyApp {
         }
     }
 
+    fn show_frequency_scale_combo(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let old_frequency_scale = self.settings.frequency_scale;
+        egui::ComboBox::from_label("Frequency axis")
+            .selected_text(self.settings.frequency_scale.to_string())
+            .show_ui(ui, |ui| {
+                for scale in FrequencyScale::VALUES {
+                    ui.selectable_value(
+                        &mut self.settings.frequency_scale,
+                        scale,
+                        scale.to_string(),
+                    );
+                }
+            })
+            .response
+            .on_hover_text("Spacing of the frequency axis. Log, Mel, Bark and ERB give more room to low frequencies.");
+        if self.settings.frequency_scale != old_frequency_scale {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_frequency_band_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let band = (self.settings.freq_min_hz, self.settings.freq_max_hz);
+        let band_name = FREQUENCY_BANDS
+            .iter()
+            .find(|(_, min_hz, max_hz)| (*min_hz, *max_hz) == band)
+            .map_or("Custom", |(name, _, _)| name);
+        egui::ComboBox::from_label("Band")
+            .selected_text(band_name)
+            .show_ui(ui, |ui| {
+                for (name, min_hz, max_hz) in FREQUENCY_BANDS {
+                    if ui.selectable_label(band_name == name, name).clicked() {
+                        self.settings.freq_min_hz = min_hz;
+                        self.settings.freq_max_hz = max_hz;
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Zoom into part of the spectrum, e.g. to judge an encoder's lowpass.");
+        if (self.settings.freq_min_hz, self.settings.freq_max_hz) != band {
+            *trigger_regeneration = true;
+        }
+
+        let max_hz = self.settings.freq_max_hz;
+        let min_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.freq_min_hz)
+                    .speed(10.0)
+                    .range(0.0..=if max_hz > 0.0 { max_hz - 1.0 } else { 96000.0 })
+                    .max_decimals(0)
+                    .prefix("From: ")
+                    .suffix(" Hz"),
+            )
+            .on_hover_text("Lowest frequency shown.");
+
+        let min_hz = self.settings.freq_min_hz;
+        let max_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.freq_max_hz)
+                    .speed(10.0)
+                    .range(0.0..=96000.0)
+                    .max_decimals(0)
+                    .prefix("To: ")
+                    .suffix(" Hz"),
+            )
+            .on_hover_text("Highest frequency shown, 0 for the Nyquist frequency.");
+        // Only a band that is still non-empty is applied.
+        if self.settings.freq_max_hz > 0.0 && self.settings.freq_max_hz <= min_hz {
+            self.settings.freq_max_hz = min_hz + 1.0;
+        }
+
+        if [min_response, max_response]
+            .iter()
+            .any(|response| response.drag_stopped() || response.lost_focus())
+        {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_time_range_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let duration = self
+            .audio_info
+            .as_ref()
+            .map_or(24.0 * 3600.0, |info| info.duration);
+        let hours = duration >= 3600.0;
+        let speed = (duration / 500.0).max(0.01);
+
+        let end = self.settings.end_time;
+        let start_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.start_time)
+                    .speed(speed)
+                    .range(0.0..=if end > 0.0 { end - 0.01 } else { duration })
+                    .custom_formatter(|v, _| utils::format_time(v, 0.01, hours))
+                    .custom_parser(utils::parse_time)
+                    .prefix("Start: "),
+            )
+            .on_hover_text("Render from this point on, e.g. 1:30.");
+
+        let start = self.settings.start_time;
+        let end_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.end_time)
+                    .speed(speed)
+                    .range(0.0..=duration)
+                    .custom_formatter(|v, _| match v > 0.0 {
+                        true => utils::format_time(v, 0.01, hours),
+                        false => "end of file".to_string(),
+                    })
+                    .custom_parser(|s| match s.trim().eq_ignore_ascii_case("end") {
+                        true => Some(0.0),
+                        false => utils::parse_time(s),
+                    })
+                    .prefix("End: "),
+            )
+            .on_hover_text("Render up to this point, 0 for the end of the file.");
+        // Only a range that is still non-empty is applied.
+        if self.settings.end_time > 0.0 && self.settings.end_time <= start {
+            self.settings.end_time = (start + 0.01).min(duration);
+        }
+
+        if [start_response, end_response]
+            .iter()
+            .any(|response| response.drag_stopped() || response.lost_focus())
+        {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_constant_q_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let bins_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.cqt_bins_per_octave)
+                    .speed(1.0)
+                    .range(6..=96)
+                    .prefix("Bins per octave: "),
+            )
+            .on_hover_text("12 gives one bin per semitone.");
+
+        let max_hz = self.settings.cqt_max_hz;
+        let min_response = ui
+            .horizontal(|ui| {
+                let response = ui.add(
+                    egui::DragValue::new(&mut self.settings.cqt_min_hz)
+                        .speed(1.0)
+                        .range(8.0..=max_hz / 2.0)
+                        .max_decimals(1)
+                        .prefix("From: ")
+                        .suffix(" Hz"),
+                );
+                ui.label(cqt::note_name(self.settings.cqt_min_hz));
+                response
+            })
+            .inner;
+
+        let min_hz = self.settings.cqt_min_hz;
+        let max_response = ui
+            .horizontal(|ui| {
+                let response = ui.add(
+                    egui::DragValue::new(&mut self.settings.cqt_max_hz)
+                        .speed(10.0)
+                        .range(min_hz * 2.0..=24000.0)
+                        .max_decimals(1)
+                        .prefix("To: ")
+                        .suffix(" Hz"),
+                );
+                ui.label(cqt::note_name(self.settings.cqt_max_hz));
+                response
+            })
+            .inner;
+
+        if [bins_response, min_response, max_response]
+            .iter()
+            .any(|response| response.drag_stopped() || response.lost_focus())
+        {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_stereo_view_combo(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let old_view = self.settings.stereo_view;
+        egui::ComboBox::from_label("Stereo")
+            .selected_text(self.settings.stereo_view.to_string())
+            .show_ui(ui, |ui| {
+                for view in StereoView::VALUES {
+                    ui.selectable_value(&mut self.settings.stereo_view, view, view.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Mid is (L+R)/2, what both channels share. Side is (L−R)/2, what differs \
+                 between them; a fake stereo file has next to none.",
+            );
+        if self.settings.stereo_view != old_view {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_channel_selection(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let Some(info) = &self.audio_info else {
+            return;
+        };
+        let names: Vec<String> = (0..info.channels as usize)
+            .map(|index| info.channel_name(index))
+            .collect();
+        let shown = info.shown_channels(&self.settings);
+        let selected_text = match shown.len() == names.len() {
+            true => "All".to_string(),
+            false => shown
+                .iter()
+                .map(|&index| names[index].as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+
+        let mut toggled = None;
+        egui::ComboBox::from_label("Channels")
+            .selected_text(selected_text)
+            .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside)
+            .show_ui(ui, |ui| {
+                for (index, name) in names.iter().enumerate() {
+                    let mut checked = shown.contains(&index);
+                    // The last shown channel stays.
+                    let enabled = !checked || shown.len() > 1;
+                    if ui
+                        .add_enabled(enabled, egui::Checkbox::new(&mut checked, name))
+                        .changed()
+                    {
+                        toggled = Some(index);
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Channels drawn, split or mixed into one.");
+
+        if let Some(index) = toggled {
+            let mut selected = shown;
+            match selected.iter().position(|&shown| shown == index) {
+                Some(position) => {
+                    selected.remove(position);
+                }
+                None => selected.push(index),
+            }
+            selected.sort_unstable();
+            // Names are kept so the selection carries over to files with
+            // the same layout; numbers stand in for unnamed channels.
+            self.settings.channels = match selected.len() == names.len() {
+                true => Vec::new(),
+                false => selected.iter().map(|&index| names[index].clone()).collect(),
+            };
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_backend_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let old_backend = self.settings.backend;
+        egui::ComboBox::from_label("Engine")
+            .selected_text(self.settings.backend.to_string())
+            .show_ui(ui, |ui| {
+                for backend in SpectrogramBackend::VALUES {
+                    ui.selectable_value(&mut self.settings.backend, backend, backend.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Ffmpeg uses showspectrumpic, Native uses the built-in STFT engine.");
+        if self.settings.backend != old_backend {
+            *trigger_regeneration = true;
+        }
+
+        // Live mode streams through ffmpeg's showspectrum, which takes an overlap too.
+        if self.settings.backend != SpectrogramBackend::Native {
+            if self.settings.live_mode {
+                self.show_overlap_drag(ui, trigger_regeneration);
+            }
+            return;
+        }
+
+        let old_fft_size = self.settings.fft_size;
+        egui::ComboBox::from_label("FFT size")
+            .selected_text(self.settings.fft_size.to_string())
+            .show_ui(ui, |ui| {
+                for fft_size in [256, 512, 1024, 2048, 4096, 8192, 16384] {
+                    ui.selectable_value(
+                        &mut self.settings.fft_size,
+                        fft_size,
+                        fft_size.to_string(),
+                    );
+                }
+            })
+            .response
+            .on_hover_text("Larger windows give finer frequency and coarser time resolution.");
+        if self.settings.fft_size != old_fft_size {
+            *trigger_regeneration = true;
+        }
+
+        self.show_overlap_drag(ui, trigger_regeneration);
+    }
+
+    fn show_overlap_drag(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let overlap_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.overlap)
+                    .speed(0.01)
+                    .range(0.0..=0.95)
+                    .prefix("Overlap: "),
+            )
+            .on_hover_text("Overlap between consecutive FFT windows.");
+        if overlap_response.drag_stopped() || overlap_response.lost_focus() {
+            *trigger_regeneration = true;
+        }
+    }
+
     fn show_custom_res_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
         if ui
             .checkbox(&mut self.settings.custom_resolution, "Custom size")
@@ -437,3 +911,31 @@ impl MyApp {
         }
     }
 }
+
+/// Asks for a file name and saves `image` there.
+///
+/// Returns `Ok(())` without saving when there is no image or the dialog was cancelled.
+fn save_image(image: &Option<ColorImage>, input_path: &str) -> Result<()> {
+    if let Some(image) = image {
+        if let Some(pictures_dir) = dirs::picture_dir() {
+            let input_filename = Path::new(input_path)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("spectrogram");
+
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name(format!("{}.png", input_filename))
+                .set_directory(&pictures_dir)
+                .save_file()
+            {
+                let (width, height) = (image.width() as u32, image.height() as u32);
+                let pixels: Vec<u8> = image.pixels.iter().flat_map(|p| p.to_array()).collect();
+                let rgba_image = image::RgbaImage::from_raw(width, height, pixels)
+                    .ok_or(Error::ImageBuffer { width, height })?;
+                utils::save_png(&rgba_image, &path)?;
+                eprintln!("Image saved to {:?}", path);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use eframe::egui;
+
+use super::MyApp;
+
+const STATUS_BAR_HEIGHT: f32 = 22.0;
+
+impl MyApp {
+    fn has_status_bar(&self) -> bool {
+        self.settings.analyze_audio && self.input_path.is_some()
+    }
+
+    /// Vertical space taken by the status bar, for sizing the spectrogram.
+    pub(super) fn status_bar_height(&self) -> f32 {
+        if self.has_status_bar() {
+            STATUS_BAR_HEIGHT
+        } else {
+            0.0
+        }
+    }
+
+    pub(super) fn show_status_bar(&mut self, ctx: &egui::Context) {
+        if !self.has_status_bar() {
+            return;
+        }
+
+        egui::TopBottomPanel::bottom("status_bar")
+            .exact_height(STATUS_BAR_HEIGHT)
+            .show(ctx, |ui| {
+                ui.horizontal_centered(|ui| {
+                    if self.analysis_receiver.is_some() {
+                        ui.spinner();
+                        ui.label("Analysing...");
+                    } else if let Some(report) = &self.analysis {
+                        let response = ui.label(report.summary().join("   |   "));
+                        if let Some(stereo) = &report.stereo {
+                            response.on_hover_text(format!(
+                                "Stereo correlation per band:\n{}",
+                                stereo.band_lines().join("\n")
+                            ));
+                        }
+                    } else if let Some(error) = &self.analysis_error {
+                        ui.label(format!("Analysis failed: {}", error));
+                    } else {
+                        ui.label("Analysis failed.");
+                    }
+                });
+            });
+    }
+}
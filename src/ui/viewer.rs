@@ -0,0 +1,458 @@
+use eframe::egui;
+use std::time::{Duration, Instant};
+
+use super::MyApp;
+use crate::cqt;
+use crate::frequency::{self, FrequencyAxis};
+use crate::legend;
+use crate::settings::{AppSettings, SpectrogramBackend, SpectrogramView};
+use crate::spectrogram::{MagnitudeMatrix, MIN_DB};
+use crate::utils::{self, AudioInfo};
+
+/// Wheel movement, in points, that zooms by one `ZOOM_STEP`.
+const WHEEL_NOTCH: f32 = 50.0;
+/// Span kept per wheel notch towards the cursor.
+const ZOOM_STEP: f64 = 0.8;
+/// Narrowest time span that can be zoomed into, in seconds.
+const MIN_TIME_SPAN: f64 = 0.01;
+/// Narrowest frequency span, as a fraction of the full axis.
+const MIN_FREQUENCY_SPAN: f32 = 0.002;
+/// Quiet time after the last zoom or pan step before the view is re-rendered.
+const RERENDER_DELAY: Duration = Duration::from_millis(300);
+/// Lowest frequency given a note name in the readout, about C0.
+const MIN_NOTE_HZ: f32 = 16.0;
+
+/// Time and frequency range of a rendered spectrogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct PlotView {
+    pub start: f64,
+    pub end: f64,
+    pub axis: FrequencyAxis,
+    /// Channels stacked in the plot, each spanning the whole axis.
+    pub channels: u32,
+}
+
+impl PlotView {
+    pub fn new(settings: &AppSettings, info: &AudioInfo) -> Self {
+        let (start, end) = settings.time_range(info.duration);
+        Self {
+            start,
+            end,
+            axis: FrequencyAxis::for_settings(settings, info.sample_rate),
+            channels: info.stacked_channels(settings),
+        }
+    }
+
+    /// Time at `x`, 0.0 being the left and 1.0 the right edge of the plot.
+    pub fn time(&self, x: f32) -> f64 {
+        self.start + (self.end - self.start) * x.clamp(0.0, 1.0) as f64
+    }
+
+    /// Channel and position on the frequency axis (0.0 at the bottom) at
+    /// `y`, 0.0 being the top and 1.0 the bottom edge of the plot.
+    pub fn channel_position(&self, y: f32) -> (u32, f32) {
+        let stacked = y.clamp(0.0, 0.9999) * self.channels as f32;
+        (stacked as u32, 1.0 - stacked.fract())
+    }
+}
+
+impl MyApp {
+    /// Screen areas of the plots inside the spectrogram image shown in
+    /// `image_rect`, each with the file it shows (1 for B of a comparison).
+    ///
+    /// Empty when the image has no known plot area: ffmpeg's own legend and
+    /// its horizontal orientation are laid out by ffmpeg.
+    fn plot_rects(
+        &self,
+        image_rect: egui::Rect,
+        image_size: egui::Vec2,
+    ) -> Vec<(usize, egui::Rect)> {
+        let scale = image_rect.size() / image_size;
+        let (width, height) = self.spectrogram_size();
+        let size = egui::vec2(width as f32, height as f32) * scale;
+        let plot_at = |x: u32, y: u32| {
+            egui::Rect::from_min_size(
+                image_rect.min + egui::vec2(x as f32, y as f32) * scale,
+                size,
+            )
+        };
+
+        if self.comparing().is_some() {
+            return self
+                .comparison_plots
+                .iter()
+                .map(|&(index, (x, y))| (index, plot_at(x, y)))
+                .collect();
+        }
+        let custom_legend = self.settings.uses_custom_legend();
+        if !custom_legend && (self.settings.legend || self.settings.horizontal) {
+            return Vec::new();
+        }
+        match custom_legend {
+            true => vec![(0, plot_at(legend::LEFT_MARGIN, legend::TOP_MARGIN))],
+            false => vec![(0, plot_at(0, 0))],
+        }
+    }
+
+    /// Shows the spectrogram and handles zooming and panning it.
+    ///
+    /// The wheel zooms the time axis and Shift + wheel the frequency axis,
+    /// dragging pans, dragging with the right button zooms into the box and
+    /// R resets the view. Every change re-renders the new time range and band.
+    pub(super) fn show_spectrogram(&mut self, ui: &mut egui::Ui, texture: &egui::TextureHandle) {
+        let available_size = ui.available_size();
+        let image_size = texture.size_vec2();
+
+        let image_aspect = image_size.x / image_size.y;
+        let available_aspect = available_size.x / available_size.y;
+
+        let new_size = if image_aspect > available_aspect {
+            // Fit to width
+            egui::vec2(available_size.x, available_size.x / image_aspect)
+        } else {
+            // Fit to height
+            egui::vec2(available_size.y * image_aspect, available_size.y)
+        };
+
+        let response = ui
+            .centered_and_justified(|ui| {
+                let image = if self.settings.custom_resolution {
+                    egui::Image::new((texture.id(), new_size))
+                } else {
+                    egui::Image::from_texture(texture)
+                };
+                ui.add(image.sense(egui::Sense::click_and_drag()))
+            })
+            .inner;
+
+        let plots = self.plot_rects(response.rect, image_size);
+        let (Some(&(_, first_plot)), Some(view)) = (plots.first(), self.plot_view) else {
+            return;
+        };
+        if self.settings.live_mode {
+            return;
+        }
+        // The plot under the pointer, or the one a box zoom started in. The
+        // plots of a comparison share the view, any of them maps it.
+        let plot = self
+            .zoom_box_origin
+            .or(response.interact_pointer_pos())
+            .or(response.hover_pos())
+            .and_then(|pos| {
+                plots
+                    .iter()
+                    .map(|&(_, rect)| rect)
+                    .find(|rect| rect.contains(pos))
+            })
+            .unwrap_or(first_plot);
+        let fraction = |pos: egui::Pos2| (pos - plot.min) / plot.size();
+
+        for &(_, rect) in &plots {
+            self.draw_playhead(ui, rect, &view);
+        }
+        if response.clicked() {
+            if let Some(pos) = response
+                .interact_pointer_pos()
+                .filter(|pos| plot.contains(*pos))
+            {
+                self.seek_playback(view.time(fraction(pos).x));
+            }
+        }
+
+        // Box zoom: the selection is drawn while dragging and applied on release.
+        if response.drag_started_by(egui::PointerButton::Secondary) {
+            self.zoom_box_origin = response
+                .interact_pointer_pos()
+                .filter(|pos| plot.contains(*pos));
+        }
+        if let Some(origin) = self.zoom_box_origin {
+            let pos = response
+                .interact_pointer_pos()
+                .or(response.hover_pos())
+                .unwrap_or(origin);
+            let selection = egui::Rect::from_two_pos(origin, pos).intersect(plot);
+            if response.dragged_by(egui::PointerButton::Secondary) {
+                ui.painter().rect_stroke(
+                    selection,
+                    0.0,
+                    egui::Stroke::new(1.0, egui::Color32::WHITE),
+                    egui::StrokeKind::Inside,
+                );
+            } else {
+                self.zoom_box_origin = None;
+                if response.drag_stopped_by(egui::PointerButton::Secondary)
+                    && selection.width() >= 4.0
+                    && selection.height() >= 4.0
+                {
+                    let (min, max) = (fraction(selection.min), fraction(selection.max));
+                    self.zoom_to_box(&view, (min.x, max.x), (min.y, max.y));
+                }
+            }
+        }
+
+        // Panning moves the content with the pointer.
+        if response.dragged_by(egui::PointerButton::Primary) {
+            let delta = response.drag_delta() / plot.size();
+            if delta != egui::Vec2::ZERO {
+                self.pan_view(delta.x, delta.y * view.channels as f32);
+            }
+        }
+
+        if let Some(pos) = response.hover_pos().filter(|pos| plot.contains(*pos)) {
+            if self.zoom_box_origin.is_none() && !response.dragged() {
+                let at = fraction(pos);
+                let lines = self.readout(&view, at.x, at.y);
+                response.clone().on_hover_ui_at_pointer(|ui| {
+                    for line in lines {
+                        ui.label(line);
+                    }
+                });
+
+                // The same point in the other files of a comparison.
+                let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(160));
+                for &(_, rect) in plots.iter().filter(|(_, rect)| *rect != plot) {
+                    let point = rect.min + at * rect.size();
+                    ui.painter().hline(rect.x_range(), point.y, stroke);
+                    ui.painter().vline(point.x, rect.y_range(), stroke);
+                }
+            }
+
+            let (scroll, modifiers) = ui.input(|i| (i.raw_scroll_delta, i.modifiers));
+            // Shift turns the wheel sideways on some platforms.
+            let notches = (scroll.x + scroll.y) / WHEEL_NOTCH;
+            // Ctrl + wheel zooms the whole interface.
+            if notches != 0.0 && !modifiers.command {
+                let factor = ZOOM_STEP.powf(notches as f64);
+                let at = fraction(pos);
+                if modifiers.shift {
+                    let (_, position) = view.channel_position(at.y);
+                    self.zoom_frequency(&view, position, factor as f32);
+                } else {
+                    self.zoom_time(view.time(at.x), at.x as f64, factor);
+                }
+            }
+        }
+
+        if self.view_changed_at.is_some() {
+            ui.ctx().request_repaint_after(RERENDER_DELAY);
+        }
+    }
+
+    /// Time, frequency and level at `x`, `y` (fractions of the plot, y down)
+    /// of the spectrogram shown in `view`, one value per line.
+    fn readout(&self, view: &PlotView, x: f32, y: f32) -> Vec<String> {
+        let time = view.time(x);
+        let (channel, position) = view.channel_position(y);
+        let hz = view.axis.frequency(position);
+
+        let mut lines = vec![format!(
+            "Time: {}",
+            utils::format_time(time, 0.001, view.end >= 3600.0)
+        )];
+        let mut frequency = format!("Frequency: {}", frequency::format_hz(hz));
+        if hz >= MIN_NOTE_HZ {
+            let note = cqt::note_number(hz);
+            let cents = 1200.0 * (hz / cqt::note_frequency(note)).log2();
+            frequency.push_str(&format!(" ({} {:+.0} ct)", cqt::note_name(hz), cents));
+        }
+        lines.push(frequency);
+        if let Some(info) = self.audio_info.as_ref().filter(|_| view.channels > 1) {
+            let mixes = info.channel_mixes(&self.settings);
+            if let Some(mix) = mixes.get(channel as usize) {
+                lines.push(format!("Channel: {}", mix.name));
+            }
+        }
+
+        // Levels exist for native renders only; they are dropped when a new render starts.
+        let matrices = match self.comparison_magnitudes() {
+            Some([a, b]) => vec![("Level A", a), ("Level B", b)],
+            None => self
+                .magnitudes
+                .as_ref()
+                .filter(|cached| Some(&cached.input_path) == self.input_path.as_ref())
+                .map(|cached| ("Level", &cached.matrix))
+                .into_iter()
+                .collect(),
+        };
+        for &(label, matrix) in &matrices {
+            match level_at(matrix, view, channel, position, x) {
+                Some(db) if db <= MIN_DB => lines.push(format!("{}: silence", label)),
+                Some(db) => lines.push(format!("{}: {:.1} dBFS", label, db)),
+                None => {}
+            }
+        }
+        if matrices.is_empty()
+            && utils::effective_backend(&self.settings) == SpectrogramBackend::Ffmpeg
+        {
+            lines.push("Level: native engine only".to_string());
+        }
+        let difference = self
+            .comparison_difference()
+            .and_then(|matrix| level_at(matrix, view, channel, position, x));
+        match difference {
+            Some(db) if db.is_nan() => lines.push("Difference: B has no audio here".to_string()),
+            Some(db) => lines.push(format!("Difference: {:+.1} dB", db)),
+            None => {}
+        }
+        lines
+    }
+
+    /// Re-renders a zoomed or panned view once the changes stop coming.
+    pub(super) fn poll_view_change(&mut self, ctx: &egui::Context) {
+        if std::mem::take(&mut self.trigger_reset_view) {
+            self.set_view_settings(|settings| {
+                settings.start_time = 0.0;
+                settings.end_time = 0.0;
+                settings.freq_min_hz = 0.0;
+                settings.freq_max_hz = 0.0;
+            });
+        }
+
+        if let Some(changed) = self.view_changed_at {
+            let elapsed = changed.elapsed();
+            if elapsed >= RERENDER_DELAY {
+                self.view_changed_at = None;
+                self.regenerate_spectrogram(ctx);
+            } else {
+                ctx.request_repaint_after(RERENDER_DELAY - elapsed);
+            }
+        }
+    }
+
+    /// Applies a change of the time range or band, see `poll_view_change`.
+    fn set_view_settings(&mut self, change: impl FnOnce(&mut AppSettings)) {
+        let old = self.settings.clone();
+        change(&mut self.settings);
+        if self.settings != old {
+            self.view_changed_at = Some(Instant::now());
+        }
+    }
+
+    fn duration(&self) -> f64 {
+        self.audio_info.as_ref().map_or(0.0, |info| info.duration)
+    }
+
+    /// Full axis the band of the current settings is part of.
+    fn full_axis(&self) -> Option<FrequencyAxis> {
+        let info = self.audio_info.as_ref()?;
+        // The constant-Q view has its own range.
+        if self.settings.view == SpectrogramView::ConstantQ {
+            return None;
+        }
+        Some(FrequencyAxis::new(
+            self.settings.frequency_scale,
+            info.sample_rate,
+        ))
+    }
+
+    /// Sets the time range to `start`..`end` seconds, kept inside the file.
+    fn set_time_view(&mut self, start: f64, end: f64) {
+        let duration = self.duration();
+        let span = (end - start).clamp(MIN_TIME_SPAN.min(duration), duration);
+        let start = start.clamp(0.0, duration - span);
+        self.set_view_settings(|settings| {
+            // The whole file is stored as "no range", so it follows the file.
+            if span >= duration {
+                settings.start_time = 0.0;
+                settings.end_time = 0.0;
+            } else {
+                settings.start_time = start;
+                settings.end_time = start + span;
+            }
+        });
+    }
+
+    /// Sets the band to positions `lo`..`hi` of the full axis, kept inside it.
+    fn set_frequency_view(&mut self, full: &FrequencyAxis, lo: f32, hi: f32) {
+        let span = (hi - lo).clamp(MIN_FREQUENCY_SPAN, 1.0);
+        let lo = lo.clamp(0.0, 1.0 - span);
+        let (min_hz, max_hz) = (full.frequency(lo), full.frequency(lo + span));
+        self.set_view_settings(|settings| {
+            if span >= 1.0 {
+                settings.freq_min_hz = 0.0;
+                settings.freq_max_hz = 0.0;
+            } else {
+                settings.freq_min_hz = if lo > 0.0 { min_hz } else { 0.0 };
+                settings.freq_max_hz = max_hz;
+            }
+        });
+    }
+
+    /// Band of the current settings as positions on the full axis.
+    fn frequency_view(&self, full: &FrequencyAxis) -> (f32, f32) {
+        let info = self.audio_info.as_ref();
+        let band = FrequencyAxis::for_settings(&self.settings, info.map_or(0, |i| i.sample_rate));
+        (full.position(band.min_hz), full.position(band.max_hz))
+    }
+
+    /// Zooms the time axis by `factor`, keeping `anchor` seconds at `x` of the plot.
+    fn zoom_time(&mut self, anchor: f64, x: f64, factor: f64) {
+        let (start, end) = self.settings.time_range(self.duration());
+        let span = (end - start) * factor;
+        self.set_time_view(anchor - x * span, anchor - x * span + span);
+    }
+
+    /// Zooms the frequency axis by `factor`, keeping the frequency at
+    /// `position` (0.0 bottom, 1.0 top) of the shown axis in place.
+    fn zoom_frequency(&mut self, view: &PlotView, position: f32, factor: f32) {
+        let Some(full) = self.full_axis() else {
+            return;
+        };
+        let anchor = full.position(view.axis.frequency(position));
+        let (lo, hi) = self.frequency_view(&full);
+        let span = (hi - lo) * factor;
+        self.set_frequency_view(
+            &full,
+            anchor - position * span,
+            anchor - position * span + span,
+        );
+    }
+
+    /// Moves the view by a fraction of the plot, `dx` to the right and `dy` down.
+    fn pan_view(&mut self, dx: f32, dy: f32) {
+        let (start, end) = self.settings.time_range(self.duration());
+        let shift = -(dx as f64) * (end - start);
+        if shift != 0.0 {
+            self.set_time_view(start + shift, end + shift);
+        }
+        if let Some(full) = self.full_axis().filter(|_| dy != 0.0) {
+            let (lo, hi) = self.frequency_view(&full);
+            let shift = dy * (hi - lo);
+            self.set_frequency_view(&full, lo + shift, hi + shift);
+        }
+    }
+
+    /// Zooms into the box spanning `x` and `y` (fractions of the plot, y down)
+    /// of the spectrogram shown in `view`.
+    fn zoom_to_box(&mut self, view: &PlotView, x: (f32, f32), y: (f32, f32)) {
+        self.set_time_view(view.time(x.0), view.time(x.1));
+        let Some(full) = self.full_axis() else {
+            return;
+        };
+        // Within the channel the box starts in.
+        let (channel, top) = view.channel_position(y.0);
+        let bottom = 1.0 - (y.1 * view.channels as f32 - channel as f32).min(1.0);
+        self.set_frequency_view(
+            &full,
+            full.position(view.axis.frequency(bottom)),
+            full.position(view.axis.frequency(top)),
+        );
+    }
+}
+
+/// Level in `matrix` at `x` (fraction of the plot width) and `position` on
+/// the frequency axis of `channel`, if the matrix has that channel layout.
+fn level_at(
+    matrix: &MagnitudeMatrix,
+    view: &PlotView,
+    channel: u32,
+    position: f32,
+    x: f32,
+) -> Option<f32> {
+    if matrix.channels as u32 != view.channels || matrix.width == 0 || matrix.height == 0 {
+        return None;
+    }
+    let column = ((x * matrix.width as f32) as usize).min(matrix.width - 1);
+    let row = ((position * matrix.height as f32) as usize).min(matrix.height - 1);
+    Some(matrix.get(channel as usize, row, column))
+}
@@ -50,6 +50,38 @@ pub fn show(ctx: &egui::Context, is_open: &mut bool) {
                         ui.label("Toggle Split Channels");
                         ui.end_row();
 
+                        ui.label("Wheel,   Shift + Wheel");
+                        ui.label("Zoom Time, Frequency");
+                        ui.end_row();
+
+                        ui.label("Drag");
+                        ui.label("Pan");
+                        ui.end_row();
+
+                        ui.label("Right Drag");
+                        ui.label("Zoom Into Box");
+                        ui.end_row();
+
+                        ui.label("R");
+                        ui.label("Reset View");
+                        ui.end_row();
+
+                        ui.label("X");
+                        ui.label("Flip A/B Comparison");
+                        ui.end_row();
+
+                        ui.label("Space");
+                        ui.label("Play / Pause");
+                        ui.end_row();
+
+                        ui.label("Left,   Right");
+                        ui.label("Seek 5 s");
+                        ui.end_row();
+
+                        ui.label("Home,   Click");
+                        ui.label("Seek to Start, Point");
+                        ui.end_row();
+
                         ui.label("ESC");
                         ui.label("Close Application");
                         ui.end_row();
@@ -1,9 +1,16 @@
-use crate::settings::AppSettings;
-use dirs;
-use eframe::egui::ColorImage;
+use crate::analysis::FileAnalyzer;
+use crate::channels;
+use crate::decoder;
+use crate::error::{Error, Result};
+use crate::frequency::{self, FrequencyAxis};
+use crate::settings::{
+    AppSettings, FrequencyScale, SpectrogramBackend, SpectrogramScale, SpectrogramView, StereoView,
+};
+use crate::spectrogram::{self, DbRange};
 use ffmpeg_sidecar::command::{ffmpeg_is_installed, FfmpegCommand};
 use ffmpeg_sidecar::ffprobe::ffprobe_path;
-use image::{GenericImageView, RgbaImage};
+use image::RgbaImage;
+use serde::Serialize;
 use std::io::{ErrorKind, Read};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -13,24 +20,47 @@ use std::time::Instant;
 #[cfg(windows)]
 use std::os::windows::process::CommandExt;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct AudioInfo {
     pub duration: f64,
     pub sample_rate: u32,
     pub format: String,
     pub bits_per_sample: u32,
     pub channels: u32,
+    /// Speaker names of the channels ("L", "R", "LFE", ...), empty when the
+    /// file does not say.
+    pub channel_names: Vec<String>,
 }
 
-/// Converts an `image::RgbaImage` to an `eframe::egui::ColorImage`.
-pub fn rgba_image_to_color_image(rgba_image: &RgbaImage) -> ColorImage {
-    let size = [rgba_image.width() as usize, rgba_image.height() as usize];
-    let pixels = rgba_image.as_raw();
-    ColorImage::from_rgba_unmultiplied(size, pixels)
+/// Whether an ffmpeg/ffprobe pair is available, either on PATH or in the data directory.
+pub fn ffmpeg_available() -> bool {
+    if ffmpeg_is_installed() {
+        return true;
+    }
+    let ff_paths = get_ffmpeg_paths();
+    ff_paths.ffmpeg.exists() && ff_paths.ffprobe.exists()
+}
+
+/// Retrieves audio information (duration, sample rate, format, and bit depth).
+///
+/// Uses the in-process decoder when it supports the file, ffprobe otherwise.
+pub fn get_audio_info(input_path: &str) -> Result<AudioInfo> {
+    let reason = match decoder::probe_with_symphonia(input_path) {
+        Ok(info) => return Ok(info),
+        Err(Error::UnsupportedFormat { reason, .. }) => reason,
+        Err(e) => return Err(e),
+    };
+    if ffmpeg_available() {
+        return get_audio_info_with_ffprobe(input_path);
+    }
+    Err(Error::UnsupportedFormat {
+        path: input_path.to_string(),
+        reason: format!("{}, and ffmpeg is not installed", reason),
+    })
 }
 
 /// Retrieves audio information (duration, sample rate, format, and bit depth) using ffprobe.
-pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
+pub fn get_audio_info_with_ffprobe(input_path: &str) -> Result<AudioInfo> {
     let ffprobe = match ffmpeg_is_installed() {
         true => ffprobe_path(),
         false => get_ffmpeg_paths().ffprobe,
@@ -43,7 +73,7 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
         "-select_streams",
         "a:0",
         "-show_entries",
-        "stream=duration,sample_rate,bits_per_sample,bits_per_raw_sample,codec_name,channels:format=format_name",
+        "stream=duration,sample_rate,bits_per_sample,bits_per_raw_sample,codec_name,channels,channel_layout:format=format_name",
         "-of",
         "default=noprint_wrappers=1",
         input_path,
@@ -52,11 +82,14 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
     #[cfg(windows)]
     command.creation_flags(0x08000000); // CREATE_NO_WINDOW
 
-    let output = command.output().ok()?;
+    let output = command
+        .output()
+        .map_err(|e| Error::io("failed to run ffprobe", e))?;
 
     if !output.status.success() {
-        eprintln!("ffprobe error: {}", String::from_utf8_lossy(&output.stderr));
-        return None;
+        return Err(Error::Ffmpeg {
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
 
     let output_str = String::from_utf8_lossy(&output.stdout);
@@ -67,6 +100,7 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
     let mut bits_per_sample = None;
     let mut bits_per_raw_sample = None;
     let mut channels = None;
+    let mut channel_layout = None;
 
     for line in output_str.lines() {
         let parts: Vec<&str> = line.split('=').collect();
@@ -79,6 +113,7 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
                 "bits_per_sample" => bits_per_sample = parts[1].parse::<u32>().ok(),
                 "bits_per_raw_sample" => bits_per_raw_sample = parts[1].parse::<u32>().ok(),
                 "channels" => channels = parts[1].parse::<u32>().ok(),
+                "channel_layout" => channel_layout = Some(parts[1].to_string()),
                 _ => {}
             }
         }
@@ -101,54 +136,230 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
         codec_name
     };
 
-    match (duration, sample_rate, format, channels) {
-        (Some(d), Some(s), Some(f), Some(c)) => Some(AudioInfo {
-            duration: d,
-            sample_rate: s,
-            format: f,
-            bits_per_sample: final_bits,
-            channels: c,
-        }),
-        _ => None,
+    let missing = |field: &str| Error::Probe {
+        path: input_path.to_string(),
+        missing: field.to_string(),
+    };
+    let channels = channels.ok_or_else(|| missing("channel count"))?;
+    Ok(AudioInfo {
+        duration: duration.ok_or_else(|| missing("duration"))?,
+        sample_rate: sample_rate.ok_or_else(|| missing("sample rate"))?,
+        format: format.ok_or_else(|| missing("format"))?,
+        bits_per_sample: final_bits,
+        channels,
+        channel_names: channel_layout
+            .map(|layout| channels::names_from_ffmpeg_layout(&layout, channels))
+            .unwrap_or_default(),
+    })
+}
+
+/// Computes the raw magnitude matrix of a file with the native STFT engine.
+///
+/// `file_analyzer` is fed the decoded samples as well, so the file is only
+/// decoded once when it is analysed too.
+pub fn generate_magnitudes(
+    input_path: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    mut file_analyzer: Option<&mut FileAnalyzer>,
+    cancel_token: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> Result<spectrogram::MagnitudeMatrix> {
+    let mut decoder = decoder::open(input_path)?;
+    let info = decoder.info().clone();
+    let channels = info.channels.max(1) as usize;
+    let mixes = info.channel_mixes(settings);
+    let split_channels = settings.splits_channels() && mixes.len() > 1;
+    let rows = if split_channels {
+        height as usize / mixes.len()
+    } else {
+        height as usize
+    };
+    let (start, end) = settings.time_range(info.duration);
+    if end <= start {
+        return Err(Error::EmptyTimeRange {
+            path: input_path.to_string(),
+            start: settings.start_time,
+            end,
+        });
+    }
+    if start > 0.0 {
+        decoder.seek(start)?;
+    }
+    let total_frames = ((end - start) * info.sample_rate as f64).round() as u64;
+
+    let mut analyzer = spectrogram::StftAnalyzer::new(
+        spectrogram::StftParams::from_settings(settings),
+        mixes.len(),
+        info.sample_rate,
+        total_frames,
+        width as usize,
+        rows,
+        split_channels,
+    );
+    let mut samples = Vec::new();
+    let mut mixed = Vec::new();
+    // Without an end time the whole rest is used, even past a short duration estimate.
+    let mut remaining = match settings.end_time > 0.0 {
+        true => total_frames as usize * channels,
+        false => usize::MAX,
+    };
+    while remaining > 0 && decoder.next_block(&mut samples)? {
+        if cancel_token.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        let block = &samples[..samples.len().min(remaining)];
+        if let Some(file_analyzer) = file_analyzer.as_deref_mut() {
+            file_analyzer.push_interleaved(block);
+        }
+        if info.remaps_channels(settings) {
+            channels::mix_interleaved(block, channels, &mixes, &mut mixed);
+            analyzer.push_interleaved(&mixed);
+        } else {
+            analyzer.push_interleaved(block);
+        }
+        remaining -= block.len();
+    }
+    Ok(analyzer.finish())
+}
+
+/// Backend that will actually render with `settings`.
+///
+/// Falls back to the native engine when ffmpeg is selected but not installed,
+/// and always uses it for the constant-Q view and the automatic level range,
+/// which ffmpeg cannot do.
+pub fn effective_backend(settings: &AppSettings) -> SpectrogramBackend {
+    if settings.view == SpectrogramView::ConstantQ || settings.auto_db_range {
+        SpectrogramBackend::Native
+    } else if settings.backend == SpectrogramBackend::Ffmpeg && !ffmpeg_available() {
+        eprintln!("ffmpeg not found, using the native engine instead.");
+        SpectrogramBackend::Native
+    } else {
+        settings.backend
     }
 }
 
-/// Generates a spectrogram by calling ffmpeg and captures the output image from stdout.
+/// Generates a spectrogram with the backend selected in `settings`, along
+/// with the levels its palette spans.
 pub fn generate_spectrogram_in_memory(
     input_path: &str,
+    info: &AudioInfo,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    cancel_token: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> Result<(RgbaImage, DbRange)> {
+    match effective_backend(settings) {
+        SpectrogramBackend::Ffmpeg => {
+            let image = generate_spectrogram_with_ffmpeg(
+                input_path,
+                info,
+                settings,
+                width,
+                height,
+                cancel_token,
+            )?;
+            Ok((image, ffmpeg_db_range(settings)))
+        }
+        SpectrogramBackend::Native => {
+            let start = Instant::now();
+            eprintln!("Generating native spectrogram for: {}", input_path);
+            eprintln!("{:#?}", settings);
+
+            let matrix =
+                generate_magnitudes(input_path, settings, width, height, None, cancel_token)?;
+            let image = spectrogram::colorize(&matrix, settings);
+
+            eprintln!("Spectrogram generated in {:?}.", start.elapsed());
+            Ok((image, DbRange::for_matrix(&matrix, settings)))
+        }
+    }
+}
+
+/// Levels ffmpeg maps to the palette with the options from `ffmpeg_level_options`.
+pub fn ffmpeg_db_range(settings: &AppSettings) -> DbRange {
+    let range = DbRange::from_settings(settings);
+    match settings.scale {
+        SpectrogramScale::Log => range,
+        _ => DbRange {
+            floor: f32::NEG_INFINITY,
+            ..range
+        },
+    }
+}
+
+/// `gain`, `drange` and `limit` options of showspectrum(pic) for the level range.
+fn ffmpeg_level_options(settings: &AppSettings) -> String {
+    let defaults = AppSettings::default();
+    match settings.scale {
+        SpectrogramScale::Log => {
+            let mut options = format!("gain={}", settings.gain);
+            // Only passed when changed, older ffmpeg builds do not know them.
+            if settings.db_floor != defaults.db_floor || settings.db_ceiling != defaults.db_ceiling
+            {
+                options.push_str(&format!(
+                    ":drange={}:limit={}",
+                    settings.db_ceiling - settings.db_floor,
+                    settings.db_ceiling
+                ));
+            }
+            options
+        }
+        // The other scales have no floor, the ceiling is folded into the gain.
+        _ => format!(
+            "gain={}",
+            settings.gain * 10f32.powf(-settings.db_ceiling / 20.0)
+        ),
+    }
+}
+
+/// Generates a spectrogram by calling ffmpeg and captures the output image from stdout.
+pub fn generate_spectrogram_with_ffmpeg(
+    input_path: &str,
+    info: &AudioInfo,
     settings: &AppSettings,
     width: u32,
     height: u32,
     cancel_token: std::sync::Arc<std::sync::atomic::AtomicBool>,
-) -> Option<ColorImage> {
+) -> Result<RgbaImage> {
+    if !ffmpeg_available() {
+        return Err(Error::FfmpegMissing);
+    }
     let start = Instant::now();
-    println!("Generating spectrogram for: {}", input_path,);
-    println!("{:#?}", settings);
+    eprintln!("Generating spectrogram for: {}", input_path,);
+    eprintln!("{:#?}", settings);
 
-    let mode = if settings.split_channels {
+    let mode = if settings.splits_channels() {
         "separate"
     } else {
         "combined"
     };
 
-    let orientation = if settings.horizontal && !settings.custom_legend {
-        "horizontal"
-    } else {
-        "vertical"
-    };
+    // ffmpeg only draws linear axes, other scales are remapped from a taller image.
+    let frequencies = FfmpegFrequencies::new(info, settings);
+    let render_height = frequencies.render_height(height);
+
+    let orientation =
+        if settings.horizontal && !settings.custom_legend && frequencies.remap.is_none() {
+            "horizontal"
+        } else {
+            "vertical"
+        };
 
     let lavfi_filter = format!(
-        "showspectrumpic=s={}x{}:legend={}:color={}:win_func={}:scale={}:gain={}:saturation={}:mode={}:orientation={}",
+        "{}showspectrumpic=s={}x{}:legend={}:color={}:win_func={}:scale={}:{}:saturation={}:mode={}:orientation={}{}",
+        ffmpeg_channel_filter(info, settings),
         width,
-        height,
-        settings.legend,
+        render_height,
+        settings.legend && frequencies.remap.is_none(),
         settings.color_scheme.as_str(),
         settings.win_func.as_str(),
         settings.scale.as_str(),
-        settings.gain,
+        ffmpeg_level_options(settings),
         settings.saturation,
         mode,
-        orientation
+        orientation,
+        frequencies.options(),
     );
 
     let mut cmd_builder = match ffmpeg_is_installed() {
@@ -156,10 +367,9 @@ pub fn generate_spectrogram_in_memory(
         false => FfmpegCommand::new_with_path(get_ffmpeg_paths().ffmpeg),
     };
 
+    cmd_builder.args(["-hide_banner", "-loglevel", "error"]);
+    cmd_builder.args(ffmpeg_time_options(input_path, info, settings)?);
     cmd_builder.args([
-        "-hide_banner",
-        "-loglevel",
-        "error",
         "-i",
         input_path,
         "-lavfi",
@@ -169,14 +379,9 @@ pub fn generate_spectrogram_in_memory(
         "-",
     ]);
 
-    let mut cmd = match cmd_builder.spawn() // direct spawn
-    {
-        Ok(cmd) => cmd,
-        Err(e) => {
-            eprintln!("Failed to spawn ffmpeg: {}", e);
-            return None;
-        }
-    };
+    let mut cmd = cmd_builder
+        .spawn() // direct spawn
+        .map_err(|e| Error::io("failed to spawn ffmpeg", e))?;
 
     let mut stdout = cmd.take_stdout().unwrap();
     let mut buffer = Vec::new();
@@ -188,7 +393,7 @@ pub fn generate_spectrogram_in_memory(
                 eprintln!("Failed to kill ffmpeg process: {}", e);
             }
             let _ = cmd.wait();
-            return None;
+            return Err(Error::Cancelled);
         }
 
         match stdout.read(&mut read_buf) {
@@ -196,74 +401,187 @@ pub fn generate_spectrogram_in_memory(
             Ok(n) => buffer.extend_from_slice(&read_buf[..n]),
             Err(e) if e.kind() == ErrorKind::Interrupted => continue,
             Err(e) => {
-                eprintln!("Failed to read ffmpeg stdout: {}", e);
                 if let Err(e) = cmd.kill() {
                     eprintln!("Failed to kill ffmpeg process: {}", e);
                 }
-                return None;
+                return Err(Error::io("failed to read ffmpeg output", e));
             }
         }
     }
 
-    let status = match cmd.wait() {
-        Ok(status) => status,
-        Err(e) => {
-            eprintln!("Failed to wait for ffmpeg process: {}", e);
-            return None;
+    let status = cmd
+        .wait()
+        .map_err(|e| Error::io("failed to wait for ffmpeg", e))?;
+    if !status.success() {
+        return Err(ffmpeg_failure(&mut cmd));
+    }
+
+    let image = image::load_from_memory(&buffer).map_err(Error::ImageDecode)?;
+
+    let image = frequencies.apply(image.to_rgba8(), height);
+
+    eprintln!("Spectrogram generated in {:?}.", start.elapsed());
+    Ok(image)
+}
+
+/// Filter mixing the channels `settings` shows, see `AudioInfo::ffmpeg_channel_filter`.
+fn ffmpeg_channel_filter(info: &AudioInfo, settings: &AppSettings) -> String {
+    if settings.channels.is_empty() && settings.stereo_view == StereoView::Channels {
+        return String::new();
+    }
+    info.ffmpeg_channel_filter(settings)
+}
+
+/// `-ss` and `-t` input options selecting the time range of `settings`. They
+/// go before `-i`, so ffmpeg seeks in the input instead of decoding up to it.
+fn ffmpeg_time_options(
+    input_path: &str,
+    info: &AudioInfo,
+    settings: &AppSettings,
+) -> Result<Vec<String>> {
+    if !settings.has_time_range() {
+        return Ok(Vec::new());
+    }
+    let (start, end) = settings.time_range(info.duration);
+    if end <= start {
+        return Err(Error::EmptyTimeRange {
+            path: input_path.to_string(),
+            start: settings.start_time,
+            end,
+        });
+    }
+    Ok(vec![
+        "-ss".to_string(),
+        start.to_string(),
+        "-t".to_string(),
+        (end - start).to_string(),
+    ])
+}
+
+/// Source rows per output row when remapping ffmpeg's linear frequency axis.
+const REMAP_OVERSAMPLING: u32 = 4;
+/// Upper bound for the height of the image ffmpeg renders for remapping.
+const MAX_REMAP_SOURCE_HEIGHT: u32 = 8192;
+
+/// Frequency range ffmpeg draws and how its rows are remapped afterwards.
+struct FfmpegFrequencies {
+    /// `start`/`stop` in Hz, `None` for the full band.
+    band: Option<(f32, f32)>,
+    remap: Option<Remap>,
+}
+
+/// Remapping of ffmpeg's linear rows onto a non-linear axis.
+struct Remap {
+    axis: FrequencyAxis,
+    /// Linear range ffmpeg drew.
+    source: (f32, f32),
+    /// Number of stacked channels in ffmpeg's image.
+    channels: u32,
+}
+
+impl FfmpegFrequencies {
+    fn new(info: &AudioInfo, settings: &AppSettings) -> Self {
+        let full_band = settings.freq_min_hz <= 0.0 && settings.freq_max_hz <= 0.0;
+        if settings.frequency_scale == FrequencyScale::Linear && full_band {
+            return Self {
+                band: None,
+                remap: None,
+            };
         }
-    };
 
-    if !status.success() {
-        let mut stderr_output = String::new();
-        if let Some(mut stderr) = cmd.take_stderr() {
-            if stderr.read_to_string(&mut stderr_output).is_ok() {
-                eprintln!("ffmpeg error:\n{}", stderr_output);
-            }
+        let axis = FrequencyAxis::for_settings(settings, info.sample_rate);
+        let nyquist = info.sample_rate as f32 / 2.0;
+        // ffmpeg takes whole Hz.
+        let band = (axis.min_hz.round(), axis.max_hz.round());
+        let band = (band.0 > 0.0 || band.1 < nyquist.floor()).then_some(band);
+        let remap = (!axis.is_linear()).then(|| Remap {
+            axis,
+            source: band.unwrap_or((0.0, nyquist)),
+            channels: info.stacked_channels(settings),
+        });
+        Self { band, remap }
+    }
+
+    /// `start` and `stop` options for the filter string, empty for the full band.
+    fn options(&self) -> String {
+        match self.band {
+            Some((start, stop)) => format!(":start={}:stop={}", start, stop),
+            None => String::new(),
         }
-        eprintln!("ffmpeg process exited with non-zero status");
-        return None;
     }
 
-    let image = match image::load_from_memory(&buffer) {
-        Ok(img) => img,
-        Err(e) => {
-            eprintln!("Failed to decode image: {}", e);
-            return None;
+    /// Height ffmpeg should render for an output `height` pixels tall.
+    fn render_height(&self, height: u32) -> u32 {
+        match self.remap {
+            Some(_) => remap_source_height(height),
+            None => height,
         }
-    };
+    }
 
-    let (width, height) = image.dimensions();
-    let rgba_image = image.to_rgba8();
+    fn apply(&self, image: RgbaImage, height: u32) -> RgbaImage {
+        match &self.remap {
+            Some(remap) => frequency::remap_rows(
+                &image,
+                &remap.axis,
+                remap.source,
+                remap.channels,
+                height / remap.channels,
+            ),
+            None => image,
+        }
+    }
+}
 
-    let color_image =
-        ColorImage::from_rgba_unmultiplied([width as usize, height as usize], rgba_image.as_raw());
+/// Taller source image so the stretched low end of non-linear axes keeps some detail.
+fn remap_source_height(height: u32) -> u32 {
+    (height * REMAP_OVERSAMPLING).min(MAX_REMAP_SOURCE_HEIGHT.max(height))
+}
 
-    println!("Spectrogram generated in {:?}.", start.elapsed());
-    Some(color_image)
+/// Collects the stderr of an ffmpeg process that exited with an error.
+fn ffmpeg_failure(cmd: &mut ffmpeg_sidecar::child::FfmpegChild) -> Error {
+    let mut stderr_output = String::new();
+    if let Some(mut stderr) = cmd.take_stderr() {
+        stderr.read_to_string(&mut stderr_output).ok();
+    }
+    Error::Ffmpeg {
+        stderr: stderr_output,
+    }
 }
 
+/// Streams one-pixel-wide columns of the spectrogram to `sender` as ffmpeg
+/// produces them.
+///
+/// Errors are returned rather than sent, columns already sent stay valid.
 pub fn stream_spectrogram_frames(
-    sender: Sender<Option<ColorImage>>,
+    sender: Sender<Result<RgbaImage>>,
     input_path: &str,
+    info: &AudioInfo,
     settings: &AppSettings,
     width: u32,
     height: u32,
     cancel_token: std::sync::Arc<std::sync::atomic::AtomicBool>,
-) {
+) -> Result<()> {
+    if !ffmpeg_available() {
+        return Err(Error::FfmpegMissing);
+    }
     let start = Instant::now();
-    println!("Generating spectrogram for: {}", input_path,);
-    println!("{:#?}", settings);
-
-    let duration = match get_audio_info(input_path) {
-        Some(info) if info.duration > 0.0 => info.duration,
-        _ => {
-            eprintln!("Failed to get valid audio duration.");
-            return;
-        }
-    };
+    eprintln!("Generating spectrogram for: {}", input_path,);
+    eprintln!("{:#?}", settings);
+
+    // Checks the range first, an empty one is not the file's fault.
+    let time_options = ffmpeg_time_options(input_path, info, settings)?;
+    let (start_time, end_time) = settings.time_range(info.duration);
+    let duration = end_time - start_time;
+    let frequencies = FfmpegFrequencies::new(info, settings);
+    let render_height = frequencies.render_height(height);
+    if duration <= 0.0 {
+        return Err(Error::ZeroDuration {
+            path: input_path.to_string(),
+        });
+    }
 
     let fps = width as f64 / duration;
-    let mode = if settings.split_channels {
+    let mode = if settings.splits_channels() {
         "separate"
     } else {
         "combined"
@@ -276,23 +594,25 @@ pub fn stream_spectrogram_frames(
     let temp_width = 10;
 
     let lavfi_filter = format!(
-        "showspectrum=s={}x{}:legend=0:color={}:win_func={}:scale={}:gain={}:saturation={}:mode={}:orientation={}:slide=scroll",
+        "{}showspectrum=s={}x{}:legend=0:color={}:win_func={}:scale={}:{}:saturation={}:mode={}:orientation={}:overlap={}:slide=scroll{}",
+        ffmpeg_channel_filter(info, settings),
         temp_width,
-        height,
+        render_height,
         settings.color_scheme.as_str(),
         settings.win_func.as_str(),
         settings.scale.as_str(),
-        settings.gain,
+        ffmpeg_level_options(settings),
         settings.saturation,
         mode,
         "vertical", // orientation
+        settings.overlap,
+        frequencies.options(),
     );
 
     let mut cmd_builder = FfmpegCommand::new();
+    cmd_builder.args(["-hide_banner", "-loglevel", "error"]);
+    cmd_builder.args(time_options);
     cmd_builder.args([
-        "-hide_banner",
-        "-loglevel",
-        "error",
         "-i",
         input_path,
         "-lavfi",
@@ -306,16 +626,12 @@ pub fn stream_spectrogram_frames(
         "-",
     ]);
 
-    let mut cmd = match cmd_builder.spawn() {
-        Ok(cmd) => cmd,
-        Err(e) => {
-            eprintln!("Failed to spawn ffmpeg: {}", e);
-            return;
-        }
-    };
+    let mut cmd = cmd_builder
+        .spawn()
+        .map_err(|e| Error::io("failed to spawn ffmpeg", e))?;
 
     let mut stdout = cmd.take_stdout().unwrap();
-    let frame_size = (temp_width * height * 4) as usize;
+    let frame_size = (temp_width * render_height * 4) as usize;
     let mut frame_buffer = vec![0; frame_size];
 
     // Discard the first few frames which are often empty
@@ -333,48 +649,101 @@ pub fn stream_spectrogram_frames(
                 eprintln!("Failed to kill ffmpeg process: {}", e);
             }
             let _ = cmd.wait();
-            break;
+            return Err(Error::Cancelled);
         }
         match stdout.read_exact(&mut frame_buffer) {
             Ok(_) => {
-                let mut slice_pixels = Vec::with_capacity((height * 4) as usize);
-                for y in 0..height {
+                let mut slice_pixels = Vec::with_capacity((render_height * 4) as usize);
+                for y in 0..render_height {
                     let start_index = (y * temp_width * 4 + (temp_width - 1) * 4) as usize;
                     slice_pixels.extend_from_slice(&frame_buffer[start_index..start_index + 4]);
                 }
 
-                let slice_image =
-                    ColorImage::from_rgba_unmultiplied([1, height as usize], &slice_pixels);
-                if sender.send(Some(slice_image)).is_err() {
+                let slice_image = RgbaImage::from_raw(1, render_height, slice_pixels).ok_or(
+                    Error::ImageBuffer {
+                        width: 1,
+                        height: render_height,
+                    },
+                )?;
+                let slice_image = frequencies.apply(slice_image, height);
+                if sender.send(Ok(slice_image)).is_err() {
                     if let Err(e) = cmd.kill() {
                         eprintln!("Failed to kill ffmpeg: {}", e);
                     }
-                    break;
+                    let _ = cmd.wait();
+                    return Err(Error::Cancelled);
                 }
             }
             Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
                 break;
             }
             Err(e) => {
-                eprintln!("Failed to read from ffmpeg stdout: {}", e);
-                break;
+                if let Err(e) = cmd.kill() {
+                    eprintln!("Failed to kill ffmpeg: {}", e);
+                }
+                return Err(Error::io("failed to read ffmpeg output", e));
             }
         }
     }
 
-    if let Ok(status) = cmd.wait() {
-        if !status.success() {
-            let mut stderr_output = String::new();
-            if let Some(mut stderr) = cmd.take_stderr() {
-                if stderr.read_to_string(&mut stderr_output).is_ok() {
-                    eprintln!("ffmpeg error:\n{}", stderr_output);
-                }
-            }
-            eprintln!("ffmpeg process exited with non-zero status");
-        }
+    let status = cmd
+        .wait()
+        .map_err(|e| Error::io("failed to wait for ffmpeg", e))?;
+    if !status.success() {
+        return Err(ffmpeg_failure(&mut cmd));
+    }
+
+    eprintln!("Spectrogram generated in {:?}.", start.elapsed());
+    Ok(())
+}
+
+/// "1:05", or "1:02:30" with `hours`. Ticks less than a second apart
+/// (`step`) get decimals, e.g. "0:01.25".
+pub fn format_time(seconds: f64, step: f64, hours: bool) -> String {
+    let decimals = if step > 0.0 && step < 1.0 {
+        (-step.log10()).ceil().clamp(1.0, 3.0) as u32
+    } else {
+        0
+    };
+    let scale = 10u64.pow(decimals);
+    // Whole seconds are cut off, like a clock; decimals are rounded. The
+    // epsilon keeps a tick computed as 6.9999999 from reading "0:06".
+    let ticks = match decimals {
+        0 => (seconds.max(0.0) + 1e-6).floor() as u64,
+        _ => (seconds.max(0.0) * scale as f64).round() as u64,
+    };
+    let whole = ticks / scale;
+    let fraction = match decimals {
+        0 => String::new(),
+        _ => format!(".{:0width$}", ticks % scale, width = decimals as usize),
+    };
+    if hours {
+        format!(
+            "{}:{:02}:{:02}{}",
+            whole / 3600,
+            whole / 60 % 60,
+            whole % 60,
+            fraction
+        )
+    } else {
+        format!("{}:{:02}{}", whole / 60, whole % 60, fraction)
     }
+}
 
-    println!("Spectrogram generated in {:?}.", start.elapsed());
+/// Parses "90", "1:30" or "1:02:30.5" into seconds.
+pub fn parse_time(s: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for (i, part) in s.trim().split(':').enumerate() {
+        if i > 2 {
+            return None;
+        }
+        let value: f64 = part.trim().parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
 }
 
 pub fn cycle_option<T: PartialEq + Clone>(current: T, values: &[T], up: bool) -> T {
@@ -391,34 +760,6 @@ pub fn cycle_option<T: PartialEq + Clone>(current: T, values: &[T], up: bool) ->
     values[new_index].clone()
 }
 
-pub fn save_image(image: &Option<ColorImage>, input_path: &String) {
-    if let Some(image) = image {
-        if let Some(pictures_dir) = dirs::picture_dir() {
-            let input_filename = Path::new(input_path)
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("spectrogram");
-
-            if let Some(path) = rfd::FileDialog::new()
-                .set_file_name(&format!("{}.png", input_filename))
-                .set_directory(&pictures_dir)
-                .save_file()
-            {
-                let pixels: Vec<u8> = image.pixels.iter().flat_map(|p| p.to_array()).collect();
-                if let Some(rgba_image) =
-                    image::RgbaImage::from_raw(image.width() as u32, image.height() as u32, pixels)
-                {
-                    if let Err(e) = rgba_image.save(&path) {
-                        eprintln!("Failed to save image: {}", e);
-                    } else {
-                        println!("Image saved to {:?}", path);
-                    }
-                }
-            }
-        }
-    }
-}
-
 pub struct FfmpegPaths {
     pub directory: PathBuf,
     pub ffmpeg: PathBuf,
@@ -448,28 +789,41 @@ pub fn get_ffmpeg_paths() -> FfmpegPaths {
     }
 }
 
-// ============================================================
-// ADDITIVE, HEADLESS PNG EXPORT
-// (verändert keinen bestehenden Code)
-// ============================================================
-
-pub fn save_color_image_as_png(
-    image: &eframe::egui::ColorImage,
-    output_path: &std::path::Path,
-) -> Result<(), String> {
-    let width = image.width() as u32;
-    let height = image.height() as u32;
-
-    let pixels: Vec<u8> = image
-        .pixels
-        .iter()
-        .flat_map(|p| p.to_array())
-        .collect();
-
-    let rgba_image = image::RgbaImage::from_raw(width, height, pixels)
-        .ok_or("Failed to create RGBA image buffer")?;
-
-    rgba_image
-        .save(output_path)
-        .map_err(|e| format!("Failed to save PNG: {}", e))
+/// Writes `image` to `output_path` as PNG.
+pub fn save_png(image: &RgbaImage, output_path: &Path) -> Result<()> {
+    image.save(output_path).map_err(|source| Error::ImageSave {
+        path: output_path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_seconds_are_cut_off_like_a_clock() {
+        assert_eq!(format_time(0.0, 1.0, false), "0:00");
+        assert_eq!(format_time(65.9, 10.0, false), "1:05");
+        assert_eq!(format_time(3750.0, 600.0, true), "1:02:30");
+    }
+
+    #[test]
+    fn ticks_just_below_a_second_keep_it() {
+        // The 7th of 10 ticks over 10 s, as f32 arithmetic produces it.
+        let tick = 10.0 * (7.0f32 / 10.0) as f64;
+        assert!(tick < 7.0);
+        assert_eq!(format_time(tick, 1.0, false), "0:07");
+        for i in 0..=10 {
+            let tick = 10.0 * (i as f64 / 10.0);
+            assert_eq!(format_time(tick, 1.0, false), format!("0:{:02}", i));
+        }
+    }
+
+    #[test]
+    fn short_steps_get_rounded_decimals() {
+        assert_eq!(format_time(1.25, 0.05, false), "0:01.25");
+        assert_eq!(format_time(0.6999999, 0.1, false), "0:00.7");
+        assert_eq!(format_time(59.9996, 0.001, false), "1:00.000");
+    }
 }